@@ -0,0 +1,215 @@
+use chrono::{DateTime, Duration, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+const ADDED_TREE: &str = "added";
+const LAST_ADDED_TREE: &str = "last_added";
+
+/// A transaction that was added by a command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct AddedTransaction {
+    transaction_id: String,
+    added_at: DateTime<Local>,
+}
+
+/// Persists the id of the Firefly transaction group that was added by each command, and by the
+/// bot's confirmation of it, so that it can be deleted with `!delete`, along with the last
+/// transaction each user added in each room, so that it can be changed with `!edit`. Entries are
+/// kept for the retention period.
+pub struct AddedStore {
+    tree: sled::Tree,
+    /// The last transaction added by each user in each room, by `last_key`
+    last_tree: sled::Tree,
+    retention: Duration,
+}
+
+impl AddedStore {
+    /// Opens the store, pruning the entries that are older than the retention period
+    pub fn open(db: &sled::Db, retention: Duration) -> anyhow::Result<Self> {
+        let store = Self {
+            tree: db.open_tree(ADDED_TREE)?,
+            last_tree: db.open_tree(LAST_ADDED_TREE)?,
+            retention,
+        };
+        store.prune(Local::now())?;
+        Ok(store)
+    }
+
+    pub fn record(
+        &self,
+        event_id: &str,
+        transaction_id: &str,
+        added_at: DateTime<Local>,
+    ) -> anyhow::Result<()> {
+        let added = AddedTransaction {
+            transaction_id: transaction_id.to_string(),
+            added_at,
+        };
+        self.tree
+            .insert(event_id.as_bytes(), serde_json::to_vec(&added)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Records the transaction as the last one that the user added in the room
+    pub fn record_last(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        transaction_id: &str,
+        added_at: DateTime<Local>,
+    ) -> anyhow::Result<()> {
+        let added = AddedTransaction {
+            transaction_id: transaction_id.to_string(),
+            added_at,
+        };
+        self.last_tree
+            .insert(last_key(room_id, user_id), serde_json::to_vec(&added)?)?;
+        self.last_tree.flush()?;
+        Ok(())
+    }
+
+    /// Returns the id of the transaction that the event added, unless it's older than the
+    /// retention period
+    pub fn transaction_id(&self, event_id: &str, now: DateTime<Local>) -> Option<String> {
+        self.read(&self.tree, event_id.as_bytes(), now)
+    }
+
+    /// Returns the id of the last transaction that the user added in the room, unless it's older
+    /// than the retention period or was deleted
+    pub fn last_transaction_id(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        now: DateTime<Local>,
+    ) -> Option<String> {
+        self.read(&self.last_tree, &last_key(room_id, user_id), now)
+    }
+
+    fn read(&self, tree: &sled::Tree, key: &[u8], now: DateTime<Local>) -> Option<String> {
+        let key_text = String::from_utf8_lossy(key);
+        match tree.get(key) {
+            Ok(Some(value)) => match serde_json::from_slice::<AddedTransaction>(&value) {
+                Ok(added) if added.added_at + self.retention > now => Some(added.transaction_id),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Failed to parse added transaction for {key_text}: {e}");
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to read added transaction for {key_text}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Forgets every event that added the transaction
+    pub fn remove_transaction(&self, transaction_id: &str) -> anyhow::Result<()> {
+        self.remove_where(|added| added.transaction_id == transaction_id)
+    }
+
+    /// Removes the entries that are older than the retention period
+    pub fn prune(&self, now: DateTime<Local>) -> anyhow::Result<()> {
+        self.remove_where(|added| added.added_at + self.retention <= now)
+    }
+
+    fn remove_where(&self, matches: impl Fn(&AddedTransaction) -> bool) -> anyhow::Result<()> {
+        for tree in [&self.tree, &self.last_tree] {
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                let remove = match serde_json::from_slice::<AddedTransaction>(&value) {
+                    Ok(added) => matches(&added),
+                    Err(e) => {
+                        warn!("Removing unreadable added transaction: {e}");
+                        true
+                    }
+                };
+                if remove {
+                    tree.remove(key)?;
+                }
+            }
+            tree.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn last_key(room_id: &str, user_id: &str) -> Vec<u8> {
+    format!("{room_id} {user_id}").into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::added::AddedStore;
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_added_persistence() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = Local::now();
+
+        let store = AddedStore::open(&db, Duration::days(30)).unwrap();
+        store.record("$command", "42", now).unwrap();
+        store.record("$confirmation", "42", now).unwrap();
+        store.record("$other", "43", now).unwrap();
+
+        let reopened = AddedStore::open(&db, Duration::days(30)).unwrap();
+        assert_eq!(
+            Some("42".to_string()),
+            reopened.transaction_id("$confirmation", now)
+        );
+
+        reopened.remove_transaction("42").unwrap();
+        assert_eq!(None, reopened.transaction_id("$command", now));
+        assert_eq!(None, reopened.transaction_id("$confirmation", now));
+        assert_eq!(
+            Some("43".to_string()),
+            reopened.transaction_id("$other", now)
+        );
+    }
+
+    #[test]
+    fn test_added_retention() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = Local::now();
+
+        let store = AddedStore::open(&db, Duration::days(7)).unwrap();
+        store.record("$old", "41", now - Duration::days(8)).unwrap();
+        store.record("$new", "42", now).unwrap();
+        assert_eq!(None, store.transaction_id("$old", now));
+        assert_eq!(Some("42".to_string()), store.transaction_id("$new", now));
+
+        store.prune(now).unwrap();
+        assert_eq!(1, store.tree.len());
+    }
+
+    #[test]
+    fn test_last_added() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = Local::now();
+
+        let store = AddedStore::open(&db, Duration::days(7)).unwrap();
+        store.record_last("!room", "@alice", "41", now).unwrap();
+        store.record_last("!room", "@alice", "42", now).unwrap();
+        store.record_last("!room", "@bob", "43", now).unwrap();
+        store
+            .record_last("!other", "@alice", "44", now - Duration::days(8))
+            .unwrap();
+
+        assert_eq!(
+            Some("42".to_string()),
+            store.last_transaction_id("!room", "@alice", now)
+        );
+        assert_eq!(None, store.last_transaction_id("!other", "@alice", now));
+        assert_eq!(None, store.last_transaction_id("!room", "@carol", now));
+
+        store.remove_transaction("42").unwrap();
+        assert_eq!(None, store.last_transaction_id("!room", "@alice", now));
+        assert_eq!(
+            Some("43".to_string()),
+            store.last_transaction_id("!room", "@bob", now)
+        );
+    }
+}
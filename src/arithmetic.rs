@@ -0,0 +1,74 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Evaluates a simple arithmetic expression of positive numbers joined by `+`, `-`, `*`, and `/`,
+/// eg: `12.50+3.99` or `60/3`. Multiplication and division bind tighter than addition and
+/// subtraction. Returns `None` if the expression is malformed or its result isn't finite.
+pub fn evaluate(expression: &str) -> Option<f64> {
+    let mut chars = expression.chars().peekable();
+    let result = sum(&mut chars)?;
+    (chars.next().is_none() && result.is_finite()).then_some(result)
+}
+
+fn sum(chars: &mut Peekable<Chars>) -> Option<f64> {
+    let mut result = product(chars)?;
+    while let Some(op @ ('+' | '-')) = chars.peek().copied() {
+        chars.next();
+        let operand = product(chars)?;
+        if op == '+' {
+            result += operand;
+        } else {
+            result -= operand;
+        }
+    }
+    Some(result)
+}
+
+fn product(chars: &mut Peekable<Chars>) -> Option<f64> {
+    let mut result = number(chars)?;
+    while let Some(op @ ('*' | '/')) = chars.peek().copied() {
+        chars.next();
+        let operand = number(chars)?;
+        if op == '*' {
+            result *= operand;
+        } else {
+            result /= operand;
+        }
+    }
+    Some(result)
+}
+
+fn number(chars: &mut Peekable<Chars>) -> Option<f64> {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+        number.push(c);
+    }
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arithmetic::evaluate;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(Some(42.1), evaluate("42.1"));
+        assert_eq!(Some(16.0), evaluate("12.50+3.50"));
+        assert_eq!(Some(7.5), evaluate("10-2.5"));
+        assert_eq!(Some(7.5), evaluate("2.5*3"));
+        assert_eq!(Some(20.0), evaluate("60/3"));
+        assert_eq!(Some(14.0), evaluate("2+3*4"));
+        assert_eq!(Some(9.0), evaluate("10-4/4"));
+        assert_eq!(Some(5.0), evaluate("10-3-2"));
+        assert_eq!(Some(2.0), evaluate("12/3/2"));
+
+        assert_eq!(None, evaluate(""));
+        assert_eq!(None, evaluate("12+"));
+        assert_eq!(None, evaluate("*3"));
+        assert_eq!(None, evaluate("1++2"));
+        assert_eq!(None, evaluate("1.2.3"));
+        assert_eq!(None, evaluate("(1+2)"));
+        assert_eq!(None, evaluate("2x3"));
+        assert_eq!(None, evaluate("5/0"));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{format_amount, parse_days, INVALID_ARGS};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate};
+
+pub const BALANCE_HISTORY_USAGE: &str = "!balance-history <Account> [Days]";
+
+const BALANCE_HISTORY_DEFAULT_DAYS: i64 = 30;
+const BALANCE_HISTORY_MAX_DAYS: i64 = 365;
+/// The most points drawn in a sparkline. Longer histories are sampled down to this many points.
+pub const SPARKLINE_MAX_POINTS: usize = 60;
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A request for an asset account's balance over the last `days` days
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceHistoryArgs {
+    pub account: String,
+    pub days: i64,
+}
+
+impl BalanceHistoryArgs {
+    /// Parses the account name, which may contain spaces, followed by an optional number of days
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let args = args.trim();
+
+        let (account, days) = match args.rsplit_once(' ') {
+            Some((account, days)) if days.chars().all(|c| c.is_ascii_digit()) => (
+                account.trim(),
+                parse_days(
+                    Some(days),
+                    BALANCE_HISTORY_DEFAULT_DAYS,
+                    BALANCE_HISTORY_MAX_DAYS,
+                )?,
+            ),
+            _ => (args, BALANCE_HISTORY_DEFAULT_DAYS),
+        };
+
+        if account.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {BALANCE_HISTORY_USAGE}"));
+        }
+
+        Ok(Self {
+            account: account.to_string(),
+            days,
+        })
+    }
+}
+
+/// Reconstructs the balance at the end of each day from `start` to `end`, inclusive, working back
+/// from the balance at the end of `end` and the signed changes to the balance on each date. Days
+/// without any changes carry the previous day's balance, so there are no gaps.
+pub fn daily_balances(
+    end_balance: f64,
+    changes: &[(NaiveDate, f64)],
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Vec<f64> {
+    let mut balances = Vec::new();
+    let mut balance = end_balance;
+    let mut day = end;
+
+    while day >= start {
+        balances.push(balance);
+        balance -= changes
+            .iter()
+            .filter(|(date, _)| *date == day)
+            .map(|(_, change)| change)
+            .sum::<f64>();
+        day -= Duration::days(1);
+    }
+
+    balances.reverse();
+    balances
+}
+
+/// Picks at most `max_points` evenly spaced values, always keeping the first and last
+pub fn downsample(values: &[f64], max_points: usize) -> Vec<f64> {
+    if values.len() <= max_points || max_points < 2 {
+        return values.to_vec();
+    }
+
+    let step = (values.len() - 1) as f64 / (max_points - 1) as f64;
+    (0..max_points)
+        .map(|i| values[(i as f64 * step).round() as usize])
+        .collect()
+}
+
+/// Draws the values as a line of bars scaled between the smallest and largest value. A flat
+/// series is drawn at mid height.
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = SPARKLINE_BARS.len() - 1;
+
+    values
+        .iter()
+        .map(|value| {
+            if max <= min {
+                SPARKLINE_BARS[top / 2]
+            } else {
+                SPARKLINE_BARS[(((value - min) / (max - min)) * top as f64).round() as usize]
+            }
+        })
+        .collect()
+}
+
+/// Formats an account's current balance with thousands separators, eg:
+/// "Checking: $1,234.56 (as of 2024-01-10)"
+pub fn format_balance(
+    account: &str,
+    balance: f64,
+    currency_symbol: Option<&str>,
+    as_of: Option<NaiveDate>,
+) -> String {
+    let cents = (balance.abs() * 100.0).round() as u64;
+    let digits = (cents / 100).to_string();
+    let mut whole = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            whole.push(',');
+        }
+        whole.push(digit);
+    }
+
+    let mut response = format!(
+        "{account}: {}{}{whole}.{:02}",
+        if balance < 0.0 && cents > 0 { "-" } else { "" },
+        currency_symbol.unwrap_or_default(),
+        cents % 100
+    );
+    if let Some(as_of) = as_of {
+        response.push_str(&format!(" (as of {as_of})"));
+    }
+    response
+}
+
+/// Formats the sparkline of the balances along with the first and last balances and the change
+/// between them
+pub fn format_balance_history(account: &str, days: i64, balances: &[f64]) -> String {
+    let (Some(first), Some(last)) = (balances.first(), balances.last()) else {
+        return format!("No balance history for {account}");
+    };
+    let change = last - first;
+
+    format!(
+        "{account} over the last {days} days:\n{}\n{} → {} ({}{})",
+        sparkline(&downsample(balances, SPARKLINE_MAX_POINTS)),
+        format_amount(*first, None),
+        format_amount(*last, None),
+        if change >= 0.0 { "+" } else { "" },
+        format_amount(change, None)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::balance::{
+        daily_balances, downsample, format_balance, format_balance_history, sparkline,
+        BalanceHistoryArgs,
+    };
+    use chrono::NaiveDate;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_format_balance() {
+        assert_eq!(
+            "Checking: $1,234.56 (as of 2024-03-10)",
+            format_balance("Checking", 1234.56, Some("$"), Some(date(10)))
+        );
+        assert_eq!(
+            "Savings: 1,234,567.00",
+            format_balance("Savings", 1234567.0, None, None)
+        );
+        assert_eq!(
+            "Credit card: -€999.50",
+            format_balance("Credit card", -999.5, Some("€"), None)
+        );
+        assert_eq!("Empty: 0.00", format_balance("Empty", -0.001, None, None));
+    }
+
+    #[test]
+    fn test_parse_balance_history() {
+        assert_eq!(
+            BalanceHistoryArgs {
+                account: "Savings account".to_string(),
+                days: 30,
+            },
+            BalanceHistoryArgs::parse(" Savings account ").unwrap()
+        );
+        assert_eq!(7, BalanceHistoryArgs::parse("Savings 7").unwrap().days);
+        assert_eq!(365, BalanceHistoryArgs::parse("Savings 1000").unwrap().days);
+        assert!(BalanceHistoryArgs::parse("Savings 0").is_err());
+        assert!(BalanceHistoryArgs::parse("").is_err());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(
+            "▁▂▃▄▅▆▇█",
+            sparkline(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0])
+        );
+        assert_eq!("█▁█", sparkline(&[-5.0, -10.0, -5.0]));
+        assert_eq!("▄▄▄", sparkline(&[3.0, 3.0, 3.0]));
+        assert_eq!("▄", sparkline(&[100.0]));
+        assert_eq!("", sparkline(&[]));
+    }
+
+    #[test]
+    fn test_daily_balances() {
+        let changes = vec![(date(2), -20.0), (date(4), 100.0), (date(4), -30.0)];
+
+        assert_eq!(
+            vec![100.0, 80.0, 80.0, 150.0, 150.0],
+            daily_balances(150.0, &changes, date(1), date(5))
+        );
+    }
+
+    #[test]
+    fn test_downsample() {
+        let values: Vec<f64> = (0..10).map(f64::from).collect();
+
+        assert_eq!(vec![0.0, 3.0, 6.0, 9.0], downsample(&values, 4));
+        assert_eq!(values, downsample(&values, 20));
+    }
+
+    #[test]
+    fn test_format_balance_history() {
+        assert_eq!(
+            "Savings over the last 3 days:\n▁▅█\n100.00 → 150.00 (+50.00)",
+            format_balance_history("Savings", 3, &[100.0, 125.0, 150.0])
+        );
+        assert_eq!(
+            "Savings over the last 2 days:\n█▁\n100.00 → 90.00 (-10.00)",
+            format_balance_history("Savings", 2, &[100.0, 90.0])
+        );
+    }
+}
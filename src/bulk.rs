@@ -0,0 +1,56 @@
+use crate::{format_amount, CONFIRM_REACTION};
+
+/// What a command that creates several transactions at once is about to do
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkPreview {
+    pub count: usize,
+    pub total: f64,
+}
+
+impl BulkPreview {
+    /// Summarizes the transactions with the given amounts
+    pub fn new(amounts: &[f64]) -> Self {
+        Self {
+            count: amounts.len(),
+            total: (amounts.iter().sum::<f64>() * 100.0).round() / 100.0,
+        }
+    }
+
+    /// Returns true if the operation is large enough to need confirming. A transaction count or
+    /// total of 0 disables that threshold.
+    pub fn needs_confirmation(&self, min_transactions: usize, min_total: f64) -> bool {
+        (min_transactions > 0 && self.count >= min_transactions)
+            || (min_total > 0.0 && self.total >= min_total)
+    }
+
+    /// The prompt asking the user to confirm the operation
+    pub fn prompt(&self) -> String {
+        format!(
+            "About to create {} transactions totaling {}. React {CONFIRM_REACTION} to proceed",
+            self.count,
+            format_amount(self.total, None)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bulk::BulkPreview;
+
+    #[test]
+    fn test_bulk_preview() {
+        let preview = BulkPreview::new(&[3.34, 3.33, 3.33]);
+        assert_eq!(3, preview.count);
+        assert_eq!(10.0, preview.total);
+        assert_eq!(
+            "About to create 3 transactions totaling 10.00. React 👍 to proceed",
+            preview.prompt()
+        );
+
+        assert!(!preview.needs_confirmation(0, 0.0));
+        assert!(preview.needs_confirmation(3, 0.0));
+        assert!(!preview.needs_confirmation(4, 0.0));
+        assert!(preview.needs_confirmation(0, 10.0));
+        assert!(!preview.needs_confirmation(4, 10.01));
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The Firefly data that a cached reply was built from. A reply is dropped from the cache when its
+/// data changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    Categories,
+    Transactions,
+}
+
+/// Holds the replies to read-only commands, keyed by the command and its args, so that repeated
+/// commands can be answered without waiting on Firefly
+pub struct ResultCache {
+    ttl: Duration,
+    entries: HashMap<String, (String, Dependency, Instant)>,
+}
+
+impl ResultCache {
+    /// Creates a cache that holds replies for `ttl`. A zero `ttl` disables the cache.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<String> {
+        let (reply, _, cached_at) = self.entries.get(key)?;
+
+        if cached_at.elapsed() >= self.ttl {
+            self.entries.remove(key);
+            return None;
+        }
+
+        Some(reply.clone())
+    }
+
+    pub fn insert(&mut self, key: String, dependency: Dependency, reply: String) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, (_, _, cached_at)| cached_at.elapsed() < ttl);
+        self.entries
+            .insert(key, (reply, dependency, Instant::now()));
+    }
+
+    /// Drops every reply that was built from the data
+    pub fn invalidate(&mut self, dependency: Dependency) {
+        self.entries
+            .retain(|_, (_, entry_dependency, _)| *entry_dependency != dependency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{Dependency, ResultCache};
+    use std::time::Duration;
+
+    #[test]
+    fn test_cache_hit() {
+        let mut cache = ResultCache::new(Duration::from_secs(60));
+        assert_eq!(None, cache.get("!categories"));
+
+        cache.insert(
+            "!categories".to_string(),
+            Dependency::Categories,
+            "Categories:".to_string(),
+        );
+        assert_eq!(Some("Categories:".to_string()), cache.get("!categories"));
+        assert_eq!(None, cache.get("!cattree"));
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let mut expired = ResultCache::new(Duration::from_nanos(1));
+        expired.insert(
+            "!categories".to_string(),
+            Dependency::Categories,
+            "Categories:".to_string(),
+        );
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(None, expired.get("!categories"));
+
+        let mut disabled = ResultCache::new(Duration::ZERO);
+        disabled.insert(
+            "!categories".to_string(),
+            Dependency::Categories,
+            "Categories:".to_string(),
+        );
+        assert_eq!(None, disabled.get("!categories"));
+    }
+
+    #[test]
+    fn test_cache_invalidation() {
+        let mut cache = ResultCache::new(Duration::from_secs(60));
+        cache.insert(
+            "!categories".to_string(),
+            Dependency::Categories,
+            "Categories:".to_string(),
+        );
+        cache.insert(
+            "!top 5 30".to_string(),
+            Dependency::Transactions,
+            "Top 5 expenses".to_string(),
+        );
+
+        cache.invalidate(Dependency::Transactions);
+        assert_eq!(None, cache.get("!top 5 30"));
+        assert_eq!(Some("Categories:".to_string()), cache.get("!categories"));
+
+        cache.invalidate(Dependency::Categories);
+        assert_eq!(None, cache.get("!categories"));
+    }
+}
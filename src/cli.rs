@@ -0,0 +1,82 @@
+use anyhow::anyhow;
+use std::path::PathBuf;
+
+/// What the bot was asked to do on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    /// Run the bot with the config file at the path
+    Run(PathBuf),
+    Version,
+    Help,
+}
+
+/// Returns the command line usage for the program
+pub fn usage(program: &str) -> String {
+    format!(
+        "Usage: {program} [--config] <PATH_TO_CONFIG>
+
+Options:
+  -c, --config <PATH>  The config file to run the bot with
+  -V, --version        Print the version and exit
+  -h, --help           Print this help and exit"
+    )
+}
+
+/// Parses the arguments, without the program name. The config path may be given either with
+/// `--config` or, as in earlier versions, as the only argument. `--help` and `--version` don't
+/// need a config path.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<CliCommand> {
+    let mut args = args.into_iter();
+    let mut config_path = None;
+
+    while let Some(arg) = args.next() {
+        let path = match arg.as_str() {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-V" | "--version" => return Ok(CliCommand::Version),
+            "-c" | "--config" => args
+                .next()
+                .ok_or_else(|| anyhow!("{arg} requires the path to the config file"))?,
+            _ => match arg.strip_prefix("--config=") {
+                Some(path) => path.to_string(),
+                None if arg.starts_with('-') => return Err(anyhow!("Unknown option: {arg}")),
+                None => arg,
+            },
+        };
+
+        if config_path.replace(PathBuf::from(path)).is_some() {
+            return Err(anyhow!("Only one config file may be given"));
+        }
+    }
+
+    config_path
+        .map(CliCommand::Run)
+        .ok_or_else(|| anyhow!("The path to the config file is required"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{parse_args, CliCommand};
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> anyhow::Result<CliCommand> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let run = CliCommand::Run(PathBuf::from("bot.toml"));
+        assert_eq!(run, parse(&["bot.toml"]).unwrap());
+        assert_eq!(run, parse(&["--config", "bot.toml"]).unwrap());
+        assert_eq!(run, parse(&["-c", "bot.toml"]).unwrap());
+        assert_eq!(run, parse(&["--config=bot.toml"]).unwrap());
+
+        assert_eq!(CliCommand::Version, parse(&["--version"]).unwrap());
+        assert_eq!(CliCommand::Help, parse(&["-h"]).unwrap());
+        assert_eq!(CliCommand::Help, parse(&["bot.toml", "--help"]).unwrap());
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["bot.toml", "other.toml"]).is_err());
+        assert!(parse(&["--verbose", "bot.toml"]).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use matrix_sdk::ruma::{OwnedUserId, UserId};
+use std::collections::{HashMap, VecDeque};
+
+pub const AGAIN_CMD: &str = "!again";
+
+/// The number of commands remembered for each user
+const COMMAND_HISTORY_SIZE: usize = 10;
+
+/// Remembers each user's most recent commands so that they can be repeated
+#[derive(Default)]
+pub struct CommandHistory {
+    entries: HashMap<OwnedUserId, VecDeque<String>>,
+}
+
+impl CommandHistory {
+    /// Records the command, forgetting the user's oldest command if they already have
+    /// `COMMAND_HISTORY_SIZE`
+    pub fn record(&mut self, user: &UserId, command: &str) {
+        let commands = self.entries.entry(user.to_owned()).or_default();
+        if commands.len() == COMMAND_HISTORY_SIZE {
+            commands.pop_front();
+        }
+        commands.push_back(command.to_string());
+    }
+
+    pub fn last(&self, user: &UserId) -> Option<String> {
+        self.entries.get(user)?.back().cloned()
+    }
+
+    /// Returns the user's commands, most recent first
+    pub fn list(&self, user: &UserId) -> Vec<String> {
+        self.entries
+            .get(user)
+            .map(|commands| commands.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the command that the message asks to run, which is the user's last command for
+    /// `!again`. Returns `None` for `!again` when the user has no commands to repeat.
+    pub fn resolve(&self, user: &UserId, message: &str) -> Option<String> {
+        if message.trim() == AGAIN_CMD {
+            self.last(user)
+        } else {
+            Some(message.to_string())
+        }
+    }
+}
+
+/// Formats the user's recent commands, most recent first
+pub fn format_command_history(commands: &[String]) -> String {
+    if commands.is_empty() {
+        return "You have no recent commands".to_string();
+    }
+
+    let mut output = String::from("Recent commands:");
+    for (i, command) in commands.iter().enumerate() {
+        output.push_str(&format!("\n {}. {command}", i + 1));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{format_command_history, CommandHistory, COMMAND_HISTORY_SIZE};
+    use matrix_sdk::ruma::OwnedUserId;
+
+    #[test]
+    fn test_command_history_ring_buffer() {
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let bob = OwnedUserId::try_from("@bob:example.org").unwrap();
+        let mut history = CommandHistory::default();
+
+        assert_eq!(None, history.last(&alice));
+        assert!(history.list(&alice).is_empty());
+
+        for i in 0..COMMAND_HISTORY_SIZE + 2 {
+            history.record(&alice, &format!("!top {}", i + 1));
+        }
+
+        let commands = history.list(&alice);
+        assert_eq!(COMMAND_HISTORY_SIZE, commands.len());
+        assert_eq!("!top 12", commands[0]);
+        assert_eq!("!top 3", commands[COMMAND_HISTORY_SIZE - 1]);
+        assert_eq!(Some("!top 12".to_string()), history.last(&alice));
+        assert_eq!(None, history.last(&bob));
+    }
+
+    #[test]
+    fn test_resolve_again() {
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let mut history = CommandHistory::default();
+
+        assert_eq!(None, history.resolve(&alice, "!again"));
+        assert_eq!(
+            Some("!add Food: 5".to_string()),
+            history.resolve(&alice, "!add Food: 5")
+        );
+
+        history.record(&alice, "!add Food: 5");
+        assert_eq!(
+            Some("!add Food: 5".to_string()),
+            history.resolve(&alice, "!again")
+        );
+    }
+
+    #[test]
+    fn test_format_command_history() {
+        assert_eq!("You have no recent commands", format_command_history(&[]));
+        assert_eq!(
+            "Recent commands:\n 1. !top\n 2. !ping",
+            format_command_history(&["!top".to_string(), "!ping".to_string()])
+        );
+    }
+}
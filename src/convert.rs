@@ -0,0 +1,98 @@
+use crate::INVALID_ARGS;
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const CONVERT_USAGE: &str = "!convert <Amount> <From> <To>";
+
+/// A request to convert an amount from one currency to another, by their ISO 4217 codes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertArgs {
+    pub amount: f64,
+    pub from: String,
+    pub to: String,
+}
+
+/// The response of the `rates_api_url`: the value of one unit of the requested currency in each
+/// other currency
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConversionRates {
+    pub rates: HashMap<String, f64>,
+}
+
+impl ConvertArgs {
+    /// Parses the amount and the two currency codes, which may be written in lower case
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        let [amount, from, to] = parts[..] else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {CONVERT_USAGE}"));
+        };
+
+        let amount = match f64::from_str(amount.strip_prefix('$').unwrap_or(amount)) {
+            Ok(amount) if amount.is_finite() && amount >= 0.0 => amount,
+            _ => return Err(anyhow!("Invalid amount: {amount}")),
+        };
+
+        Ok(Self {
+            amount,
+            from: parse_currency(from)?,
+            to: parse_currency(to)?,
+        })
+    }
+}
+
+fn parse_currency(code: &str) -> anyhow::Result<String> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_uppercase())
+    } else {
+        Err(anyhow!("Invalid currency: {code}. Use a code like EUR"))
+    }
+}
+
+/// Shows the amount in both currencies, eg: `50.00 EUR = 54.10 USD`
+pub fn format_conversion(args: &ConvertArgs, converted: f64) -> String {
+    format!(
+        "{:.2} {} = {converted:.2} {}",
+        args.amount, args.from, args.to
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{format_conversion, ConversionRates, ConvertArgs};
+
+    #[test]
+    fn test_parse_convert() {
+        let args = ConvertArgs::parse(" 50 eur USD ").unwrap();
+        assert_eq!(
+            ConvertArgs {
+                amount: 50.0,
+                from: "EUR".to_string(),
+                to: "USD".to_string(),
+            },
+            args
+        );
+        assert_eq!(12.5, ConvertArgs::parse("$12.50 USD GBP").unwrap().amount);
+
+        assert!(ConvertArgs::parse("50 EUR").is_err());
+        assert!(ConvertArgs::parse("50 EUR USD GBP").is_err());
+        assert!(ConvertArgs::parse("fifty EUR USD").is_err());
+        assert!(ConvertArgs::parse("-5 EUR USD").is_err());
+        assert!(ConvertArgs::parse("50 EURO USD").is_err());
+        assert!(ConvertArgs::parse("50 E1R USD").is_err());
+    }
+
+    #[test]
+    fn test_format_conversion() {
+        let rates: ConversionRates = serde_json::from_str(
+            r#"{"amount": 1.0, "base": "EUR", "date": "2024-05-01", "rates": {"USD": 1.082}}"#,
+        )
+        .unwrap();
+        let args = ConvertArgs::parse("50 EUR USD").unwrap();
+        assert_eq!(
+            "50.00 EUR = 54.10 USD",
+            format_conversion(&args, args.amount * rates.rates["USD"])
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{Datelike, Duration, NaiveDate};
+
+/// Returns the first and last days of the budget cycle containing `today`, where cycles start on
+/// `start_day` of each month. Months that are too short to have `start_day` start the cycle on
+/// their last day instead.
+pub fn budget_cycle(today: NaiveDate, start_day: u32) -> (NaiveDate, NaiveDate) {
+    let this_start = cycle_start(today.year(), today.month(), start_day);
+
+    let start = if today >= this_start {
+        this_start
+    } else {
+        let (year, month) = prev_month(today.year(), today.month());
+        cycle_start(year, month, start_day)
+    };
+
+    let (next_year, next_month) = next_month(start.year(), start.month());
+    let end = cycle_start(next_year, next_month, start_day) - Duration::days(1);
+
+    (start, end)
+}
+
+/// Returns true if `day` can be used as the day each budget cycle starts on
+pub fn is_valid_start_day(day: u32) -> bool {
+    (1..=31).contains(&day)
+}
+
+/// Returns the day the cycle starts in the given month
+fn cycle_start(year: i32, month: u32, start_day: u32) -> NaiveDate {
+    let day = start_day.clamp(1, days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = next_month(year, month);
+    (NaiveDate::from_ymd_opt(next_year, next_month, 1).unwrap() - Duration::days(1)).day()
+}
+
+fn prev_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cycle::{budget_cycle, is_valid_start_day};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_budget_cycle() {
+        assert_eq!(
+            (date(2024, 3, 1), date(2024, 3, 31)),
+            budget_cycle(date(2024, 3, 15), 1)
+        );
+        assert_eq!(
+            (date(2024, 3, 25), date(2024, 4, 24)),
+            budget_cycle(date(2024, 3, 25), 25)
+        );
+        assert_eq!(
+            (date(2024, 2, 25), date(2024, 3, 24)),
+            budget_cycle(date(2024, 3, 24), 25)
+        );
+        assert_eq!(
+            (date(2023, 12, 25), date(2024, 1, 24)),
+            budget_cycle(date(2024, 1, 10), 25)
+        );
+    }
+
+    #[test]
+    fn test_budget_cycle_short_months() {
+        assert_eq!(
+            (date(2024, 2, 29), date(2024, 3, 30)),
+            budget_cycle(date(2024, 3, 15), 31)
+        );
+        assert_eq!(
+            (date(2023, 2, 28), date(2023, 3, 30)),
+            budget_cycle(date(2023, 3, 1), 31)
+        );
+        assert_eq!(
+            (date(2024, 3, 31), date(2024, 4, 29)),
+            budget_cycle(date(2024, 4, 1), 31)
+        );
+        assert_eq!(
+            (date(2024, 4, 30), date(2024, 5, 30)),
+            budget_cycle(date(2024, 4, 30), 31)
+        );
+        assert_eq!(
+            (date(2024, 1, 30), date(2024, 2, 28)),
+            budget_cycle(date(2024, 2, 28), 30)
+        );
+        assert_eq!(
+            (date(2024, 2, 29), date(2024, 3, 29)),
+            budget_cycle(date(2024, 2, 29), 30)
+        );
+    }
+
+    #[test]
+    fn test_valid_start_day() {
+        assert!(is_valid_start_day(1));
+        assert!(is_valid_start_day(31));
+        assert!(!is_valid_start_day(0));
+        assert!(!is_valid_start_day(32));
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+}
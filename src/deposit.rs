@@ -0,0 +1,189 @@
+use crate::{AddArgs, NoteMode, INVALID_ARGS};
+use anyhow::anyhow;
+
+pub const DEPOSIT_USAGE: &str =
+    "!deposit <Category>: <Amount> [@Date] [from <Source> | Note] [#Tag...] | !deposit <Amount> [@Date] [Category] [#Tag...]";
+
+const SOURCE_PREFIX: &str = "from";
+
+/// Income paid into the source account from a revenue account
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepositArgs {
+    /// The name of the revenue account the income is from, if given
+    pub source: Option<String>,
+    pub add: AddArgs,
+}
+
+impl DepositArgs {
+    /// Parses the same arguments as an add, except that a note starting with "from" names the
+    /// revenue account instead. Without a colon, the arguments are the amount followed by the
+    /// category, which defaults to `default_category`, and there is no note or revenue account.
+    pub fn parse(
+        args: &str,
+        note_mode: NoteMode,
+        decimal_comma: bool,
+        default_category: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        if !args.contains(':') {
+            let add = AddArgs::parse_shorthand(args, decimal_comma, default_category)
+                .map_err(|_| anyhow!("{INVALID_ARGS} Usage: {DEPOSIT_USAGE}"))?;
+            return Ok(Self { source: None, add });
+        }
+
+        let mut add = AddArgs::parse(args, note_mode, decimal_comma)
+            .map_err(|_| anyhow!("{INVALID_ARGS} Usage: {DEPOSIT_USAGE}"))?;
+        // Income is paid by its source, not to a payee
+        if add.payee.is_some() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {DEPOSIT_USAGE}"));
+        }
+
+        let source = match add.note.as_deref() {
+            Some(note) if is_source(note) => {
+                let source = note[SOURCE_PREFIX.len()..].trim().to_string();
+                if source.is_empty() {
+                    return Err(anyhow!("{INVALID_ARGS} Usage: {DEPOSIT_USAGE}"));
+                }
+                add.note = None;
+                Some(source)
+            }
+            _ => None,
+        };
+
+        Ok(Self { source, add })
+    }
+}
+
+/// Returns true if the note is "from" followed by the name of a revenue account
+fn is_source(note: &str) -> bool {
+    let lower = note.to_lowercase();
+    lower == SOURCE_PREFIX || lower.starts_with(&format!("{SOURCE_PREFIX} "))
+}
+
+/// The type of transaction that an `!add` creates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddKind {
+    Withdrawal,
+    Deposit,
+}
+
+/// Infers whether an `!add` is income. It is when its category or one of its tags matches one of
+/// the `income_keywords`, ignoring case. `!deposit` is always a deposit, so this only applies to
+/// `!add`.
+pub fn infer_add_kind(category: &str, tags: &[String], income_keywords: &[String]) -> AddKind {
+    let is_keyword = |word: &str| {
+        income_keywords
+            .iter()
+            .any(|keyword| keyword.trim().eq_ignore_ascii_case(word.trim()))
+    };
+
+    if is_keyword(category) || tags.iter().any(|tag| is_keyword(tag)) {
+        AddKind::Deposit
+    } else {
+        AddKind::Withdrawal
+    }
+}
+
+/// Returns the name of the existing revenue account that matches `name`, ignoring case, or `name`
+/// itself so that Firefly creates a new revenue account with it
+pub fn resolve_revenue_account(name: &str, revenue_accounts: &[String]) -> String {
+    revenue_accounts
+        .iter()
+        .find(|account| account.eq_ignore_ascii_case(name.trim()))
+        .cloned()
+        .unwrap_or_else(|| name.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deposit::{infer_add_kind, resolve_revenue_account, AddKind, DepositArgs};
+    use crate::NoteMode;
+
+    fn parse(args: &str) -> anyhow::Result<DepositArgs> {
+        DepositArgs::parse(args, NoteMode::Standard, false, None)
+    }
+
+    #[test]
+    fn test_parse_deposit() {
+        let args = parse("Salary: 3000 from Employer Inc #work").unwrap();
+        assert_eq!(Some("Employer Inc".to_string()), args.source);
+        assert_eq!("Salary", args.add.category);
+        assert_eq!(3000.0, args.add.amount);
+        assert_eq!(None, args.add.note);
+        assert_eq!(vec!["work"], args.add.tags);
+
+        let args = parse("Gifts: 50 birthday money").unwrap();
+        assert_eq!(None, args.source);
+        assert_eq!(Some("birthday money".to_string()), args.add.note);
+
+        let args = parse("Salary: 3000").unwrap();
+        assert_eq!(None, args.source);
+        assert_eq!(None, args.add.note);
+
+        let args = parse("Salary: 3000 fromage").unwrap();
+        assert_eq!(None, args.source);
+        assert_eq!(Some("fromage".to_string()), args.add.note);
+
+        assert!(parse("Salary: 3000 from ").is_err());
+        assert!(parse("Salary 3000").is_err());
+        assert!(parse("Salary: 3000 >Employer").is_err());
+    }
+
+    #[test]
+    fn test_parse_deposit_shorthand() {
+        let args =
+            DepositArgs::parse("3000 Salary #work", NoteMode::Standard, false, None).unwrap();
+        assert_eq!(None, args.source);
+        assert_eq!("Salary", args.add.category);
+        assert_eq!(3000.0, args.add.amount);
+        assert_eq!(None, args.add.note);
+        assert_eq!(vec!["work"], args.add.tags);
+
+        let args = DepositArgs::parse(
+            "$25.50 @2024-01-05",
+            NoteMode::Standard,
+            false,
+            Some("Income"),
+        )
+        .unwrap();
+        assert_eq!("Income", args.add.category);
+        assert_eq!(25.5, args.add.amount);
+        assert!(args.add.date.is_some());
+
+        let args =
+            DepositArgs::parse("100 Side Gig", NoteMode::Standard, false, Some("Income")).unwrap();
+        assert_eq!("Side Gig", args.add.category);
+
+        let args = DepositArgs::parse("-50 Salary", NoteMode::Standard, false, None).unwrap();
+        assert!(args.add.refund);
+        assert_eq!(50.0, args.add.amount);
+        assert!(!parse("3000 Salary").unwrap().add.refund);
+
+        assert!(DepositArgs::parse("3000", NoteMode::Standard, false, None).is_err());
+        assert!(DepositArgs::parse("lots Salary", NoteMode::Standard, false, None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_revenue_account() {
+        let accounts = vec!["Employer".to_string(), "Side Gig".to_string()];
+
+        assert_eq!("Employer", resolve_revenue_account("employer", &accounts));
+        assert_eq!("Side Gig", resolve_revenue_account(" side gig", &accounts));
+        assert_eq!("Grandma", resolve_revenue_account("Grandma", &accounts));
+    }
+
+    #[test]
+    fn test_infer_add_kind() {
+        let keywords = vec!["Salary".to_string(), "Refund".to_string()];
+
+        assert_eq!(AddKind::Deposit, infer_add_kind("salary", &[], &keywords));
+        assert_eq!(
+            AddKind::Deposit,
+            infer_add_kind("Electronics", &["refund".to_string()], &keywords)
+        );
+        assert_eq!(
+            AddKind::Withdrawal,
+            infer_add_kind("Groceries", &["food".to_string()], &keywords)
+        );
+        assert_eq!(AddKind::Withdrawal, infer_add_kind("Salary", &[], &[]));
+    }
+}
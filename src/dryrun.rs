@@ -0,0 +1,141 @@
+use crate::{format_amount, Transaction, Transactions};
+
+/// Starts an add that is previewed instead of being sent to Firefly, eg:
+/// `!add --dry-run Groceries: 40 #food`
+pub const DRY_RUN_FLAG: &str = "--dry-run";
+
+/// Removes `--dry-run` from the start of the arguments. Returns whether it was there, and the
+/// rest of the arguments.
+pub fn split_dry_run(args: &str) -> (bool, &str) {
+    let args = args.trim_start();
+    match args.strip_prefix(DRY_RUN_FLAG) {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (true, rest),
+        _ => (false, args),
+    }
+}
+
+/// Describes each transaction that would be sent to Firefly
+pub fn format_dry_run(transactions: &Transactions) -> String {
+    let mut response = "Dry run, nothing was added. Firefly would be sent:".to_string();
+    if let Some(title) = &transactions.group_title {
+        response.push_str(&format!("\nGroup: {title}"));
+    }
+    for transaction in &transactions.transactions {
+        response.push_str(&format_transaction(transaction));
+    }
+    response
+}
+
+fn format_transaction(transaction: &Transaction) -> String {
+    let mut lines = vec![format!(
+        "\n{} {}:",
+        capitalize(&transaction.transaction_type),
+        transaction.description
+    )];
+    let amount = match &transaction.foreign_currency_code {
+        Some(currency) => format!("{} {currency}", format_amount(transaction.amount, None)),
+        None => format_amount(transaction.amount, None),
+    };
+    lines.push(format!(" - Amount: {amount}"));
+    if let Some(category) = &transaction.category_name {
+        lines.push(format!(" - Category: {category}"));
+    }
+    if let Some(source) = account(&transaction.source_id, &transaction.source_name) {
+        lines.push(format!(" - From: {source}"));
+    }
+    if let Some(destination) = account(&transaction.destination_id, &transaction.destination_name) {
+        lines.push(format!(" - To: {destination}"));
+    }
+    lines.push(format!(
+        " - Date: {}",
+        transaction.date.format("%Y-%m-%d %H:%M")
+    ));
+    if let Some(note) = &transaction.notes {
+        lines.push(format!(" - Note: {note}"));
+    }
+    if !transaction.tags.is_empty() {
+        lines.push(format!(" - Tags: {}", transaction.tags.join(", ")));
+    }
+    lines.join("\n")
+}
+
+/// Names an account by its name, or by its id when it's referred to by id
+fn account(id: &Option<i64>, name: &Option<String>) -> Option<String> {
+    match (id, name) {
+        (_, Some(name)) => Some(name.clone()),
+        (Some(id), None) => Some(format!("account {id}")),
+        (None, None) => None,
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dryrun::{format_dry_run, split_dry_run};
+    use crate::{PersonAttribution, Transaction, Transactions, DEFAULT_DESCRIPTION_TEMPLATE};
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn test_split_dry_run() {
+        assert_eq!(
+            (true, " Groceries: 40 #food"),
+            split_dry_run("--dry-run Groceries: 40 #food")
+        );
+        assert_eq!((true, ""), split_dry_run(" --dry-run"));
+        assert_eq!(
+            (false, "Groceries: 40 --dry-run"),
+            split_dry_run("Groceries: 40 --dry-run")
+        );
+        assert_eq!((false, "--dry-runs: 40"), split_dry_run("--dry-runs: 40"));
+    }
+
+    #[test]
+    fn test_format_dry_run() {
+        let withdrawal = |category: &str, amount| {
+            Transaction::withdrawal(
+                category.to_string(),
+                amount,
+                Local.with_ymd_and_hms(2024, 1, 5, 18, 30, 0).unwrap(),
+                3,
+                "General expense".to_string(),
+                "alice".to_string(),
+                PersonAttribution::Both,
+                DEFAULT_DESCRIPTION_TEMPLATE,
+                Some("milk".to_string()),
+                vec!["food".to_string()],
+            )
+        };
+
+        assert_eq!(
+            "Dry run, nothing was added. Firefly would be sent:
+Withdrawal Groceries by alice:
+ - Amount: 40.00 EUR
+ - Category: Groceries
+ - From: account 3
+ - To: General expense
+ - Date: 2024-01-05 18:30
+ - Note: milk
+ - Tags: food, alice",
+            format_dry_run(&Transactions::new(
+                withdrawal("Groceries", 40.0).in_currency(Some("EUR"))
+            ))
+        );
+        assert!(format_dry_run(&Transactions::batch(
+            "Groceries, Household".to_string(),
+            vec![withdrawal("Groceries", 40.0), withdrawal("Household", 15.0)],
+        ))
+        .starts_with(
+            "Dry run, nothing was added. Firefly would be sent:
+Group: Groceries, Household
+Withdrawal Groceries by alice:
+ - Amount: 40.00"
+        ));
+    }
+}
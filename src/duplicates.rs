@@ -0,0 +1,144 @@
+use matrix_sdk::ruma::{OwnedUserId, UserId};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// An expense that a user recently added
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentAdd {
+    pub category: String,
+    pub amount: f64,
+    pub added_at: SystemTime,
+}
+
+/// Remembers each user's recent adds so that accidental double entries can be caught
+pub struct RecentAdds {
+    window: Duration,
+    entries: HashMap<OwnedUserId, Vec<RecentAdd>>,
+}
+
+impl RecentAdds {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Records an add, and forgets any of the user's adds that are too old to be duplicated
+    pub fn record(&mut self, user: &UserId, add: RecentAdd) {
+        let window = self.window;
+        let adds = self.entries.entry(user.to_owned()).or_default();
+        adds.retain(|recent| within(recent.added_at, add.added_at, window));
+        adds.push(add);
+    }
+
+    /// Returns the user's most recent add that the new add looks like a duplicate of
+    pub fn find_duplicate(
+        &self,
+        user: &UserId,
+        category: &str,
+        amount: f64,
+        at: SystemTime,
+    ) -> Option<&RecentAdd> {
+        self.entries
+            .get(user)?
+            .iter()
+            .rev()
+            .find(|recent| is_duplicate(recent, category, amount, at, self.window))
+    }
+}
+
+/// An add looks like a duplicate of a recent one when it's to the same category, ignoring case,
+/// for the same amount, to the cent, within `window` of it
+pub fn is_duplicate(
+    recent: &RecentAdd,
+    category: &str,
+    amount: f64,
+    at: SystemTime,
+    window: Duration,
+) -> bool {
+    recent.category.eq_ignore_ascii_case(category)
+        && (recent.amount * 100.0).round() == (amount * 100.0).round()
+        && within(recent.added_at, at, window)
+}
+
+/// Returns true if `a` and `b` are no more than `window` apart
+fn within(a: SystemTime, b: SystemTime, window: Duration) -> bool {
+    let elapsed = b
+        .duration_since(a)
+        .or_else(|_| a.duration_since(b))
+        .unwrap_or_default();
+    elapsed <= window
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::duplicates::{is_duplicate, RecentAdd, RecentAdds};
+    use matrix_sdk::ruma::OwnedUserId;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_is_duplicate() {
+        let now = SystemTime::now();
+        let window = Duration::from_secs(600);
+        let recent = RecentAdd {
+            category: "Groceries".to_string(),
+            amount: 12.5,
+            added_at: now,
+        };
+
+        assert!(is_duplicate(&recent, "groceries", 12.50, now, window));
+        assert!(is_duplicate(
+            &recent,
+            "Groceries",
+            12.5,
+            now + Duration::from_secs(600),
+            window
+        ));
+        assert!(!is_duplicate(
+            &recent,
+            "Groceries",
+            12.5,
+            now + Duration::from_secs(601),
+            window
+        ));
+        assert!(!is_duplicate(&recent, "Groceries", 12.51, now, window));
+        assert!(!is_duplicate(&recent, "Dining", 12.5, now, window));
+    }
+
+    #[test]
+    fn test_recent_adds() {
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let bob = OwnedUserId::try_from("@bob:example.org").unwrap();
+        let now = SystemTime::now();
+        let mut recent = RecentAdds::new(Duration::from_secs(60));
+
+        recent.record(
+            &alice,
+            RecentAdd {
+                category: "Groceries".to_string(),
+                amount: 10.0,
+                added_at: now,
+            },
+        );
+
+        assert!(recent
+            .find_duplicate(&alice, "Groceries", 10.0, now + Duration::from_secs(30))
+            .is_some());
+        assert!(recent
+            .find_duplicate(&bob, "Groceries", 10.0, now + Duration::from_secs(30))
+            .is_none());
+
+        recent.record(
+            &alice,
+            RecentAdd {
+                category: "Dining".to_string(),
+                amount: 5.0,
+                added_at: now + Duration::from_secs(120),
+            },
+        );
+        assert!(recent
+            .find_duplicate(&alice, "Groceries", 10.0, now)
+            .is_none());
+    }
+}
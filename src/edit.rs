@@ -0,0 +1,128 @@
+use crate::{parse_amount, split_note_and_tags, INVALID_ARGS};
+use anyhow::anyhow;
+use serde::Serialize;
+
+pub const EDIT_USAGE: &str =
+    "!edit amount <Amount> | !edit category <Category> | !edit note <Note> | !edit #Tag...";
+
+/// A change to one field of the last transaction a user added
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditArgs {
+    Amount(f64),
+    Category(String),
+    Note(String),
+    /// Replaces the tags
+    Tags(Vec<String>),
+}
+
+/// The body of Firefly's transaction update, which only changes the fields that are set
+#[derive(Serialize, Debug)]
+pub struct TransactionUpdate {
+    transactions: Vec<TransactionSplitUpdate>,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct TransactionSplitUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction_journal_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+}
+
+impl EditArgs {
+    pub fn parse(args: &str, decimal_comma: bool) -> anyhow::Result<Self> {
+        let args = args.trim();
+        if args.starts_with('#') {
+            return match split_note_and_tags(Some(args)) {
+                (None, tags) if !tags.is_empty() => Ok(Self::Tags(tags)),
+                _ => Err(anyhow!("{INVALID_ARGS} Usage: {EDIT_USAGE}")),
+            };
+        }
+
+        let (field, value) = args.split_once(' ').unwrap_or((args, ""));
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {EDIT_USAGE}"));
+        }
+
+        match field.to_lowercase().as_str() {
+            "amount" => match parse_amount(value, decimal_comma)? {
+                (amount, None, None) => Ok(Self::Amount(amount)),
+                _ => Err(anyhow!("Invalid amount: {value}")),
+            },
+            "category" => Ok(Self::Category(value.to_string())),
+            "note" => Ok(Self::Note(value.to_string())),
+            _ => Err(anyhow!("{INVALID_ARGS} Usage: {EDIT_USAGE}")),
+        }
+    }
+
+    /// Builds the update of the split with the journal id, or of the only split if there is no id
+    pub fn update(&self, transaction_journal_id: Option<String>) -> TransactionUpdate {
+        let mut split = TransactionSplitUpdate {
+            transaction_journal_id,
+            ..Default::default()
+        };
+        match self {
+            Self::Amount(amount) => split.amount = Some(format!("{amount:.2}")),
+            Self::Category(category) => split.category_name = Some(category.clone()),
+            Self::Note(note) => split.notes = Some(note.clone()),
+            Self::Tags(tags) => split.tags = Some(tags.clone()),
+        }
+        TransactionUpdate {
+            transactions: vec![split],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edit::EditArgs;
+
+    #[test]
+    fn test_parse_edit() {
+        assert_eq!(
+            EditArgs::Amount(45.0),
+            EditArgs::parse("amount 45", false).unwrap()
+        );
+        assert_eq!(
+            EditArgs::Amount(12.5),
+            EditArgs::parse("Amount 12,50", true).unwrap()
+        );
+        assert_eq!(
+            EditArgs::Category("Eating Out".to_string()),
+            EditArgs::parse("category Eating Out", false).unwrap()
+        );
+        assert_eq!(
+            EditArgs::Note("milk and eggs".to_string()),
+            EditArgs::parse("note milk and eggs", false).unwrap()
+        );
+        assert_eq!(
+            EditArgs::Tags(vec!["food".to_string(), "two words".to_string()]),
+            EditArgs::parse("#food #{two words}", false).unwrap()
+        );
+
+        assert!(EditArgs::parse("", false).is_err());
+        assert!(EditArgs::parse("amount", false).is_err());
+        assert!(EditArgs::parse("amount lots", false).is_err());
+        assert!(EditArgs::parse("amount 45 EUR", false).is_err());
+        assert!(EditArgs::parse("payee Costco", false).is_err());
+    }
+
+    #[test]
+    fn test_edit_update() {
+        assert_eq!(
+            serde_json::json!({"transactions": [{"transaction_journal_id": "7", "amount": "45.00"}]}),
+            serde_json::to_value(EditArgs::Amount(45.0).update(Some("7".to_string()))).unwrap()
+        );
+        assert_eq!(
+            serde_json::json!({"transactions": [{"tags": ["food"]}]}),
+            serde_json::to_value(EditArgs::Tags(vec!["food".to_string()]).update(None)).unwrap()
+        );
+    }
+}
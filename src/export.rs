@@ -0,0 +1,170 @@
+use crate::{parse_days, TransactionSplit, INVALID_ARGS, REPORT_MAX_DAYS};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+
+pub const CATEGORY_EXPORT_USAGE: &str = "!catexport [Days]";
+
+const CATEGORY_EXPORT_DEFAULT_DAYS: i64 = 30;
+const CSV_HEADER: &str = "category,spend,count,last_used";
+
+/// A request to export the spending in every category over the last `days` days
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryExportArgs {
+    pub days: i64,
+}
+
+/// A category's spending over the export window
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryRow {
+    pub category: String,
+    pub spend: f64,
+    pub count: usize,
+    /// The date of the category's latest expense in the window, or `None` if it had none
+    pub last_used: Option<NaiveDate>,
+}
+
+impl CategoryExportArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let mut parts = args.split_whitespace();
+
+        let days = parse_days(parts.next(), CATEGORY_EXPORT_DEFAULT_DAYS, REPORT_MAX_DAYS)?;
+
+        if parts.next().is_some() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {CATEGORY_EXPORT_USAGE}"));
+        }
+
+        Ok(Self { days })
+    }
+}
+
+/// Aggregates the expenses by category. Every category in `categories` has a row, even if it has
+/// no expenses, as does any category that only appears on an expense. Rows are ordered by spend,
+/// largest first, and then by name.
+pub fn category_rows(categories: &[String], expenses: &[TransactionSplit]) -> Vec<CategoryRow> {
+    let mut rows: Vec<CategoryRow> = categories
+        .iter()
+        .map(|category| CategoryRow {
+            category: category.clone(),
+            spend: 0.0,
+            count: 0,
+            last_used: None,
+        })
+        .collect();
+
+    for expense in expenses {
+        let category = expense.category_name.as_deref().unwrap_or("Uncategorized");
+        let index = match rows.iter().position(|row| row.category == category) {
+            Some(index) => index,
+            None => {
+                rows.push(CategoryRow {
+                    category: category.to_string(),
+                    spend: 0.0,
+                    count: 0,
+                    last_used: None,
+                });
+                rows.len() - 1
+            }
+        };
+
+        let row = &mut rows[index];
+        let date = expense.date.date_naive();
+        row.spend += expense.amount;
+        row.count += 1;
+        row.last_used = row.last_used.max(Some(date));
+    }
+
+    rows.sort_by(|a, b| {
+        b.spend
+            .total_cmp(&a.spend)
+            .then_with(|| a.category.cmp(&b.category))
+    });
+    rows
+}
+
+/// Formats the rows as CSV with a header line
+pub fn format_csv(rows: &[CategoryRow]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+
+    for row in rows {
+        csv.push_str(&format!(
+            "\n{},{:.2},{},{}",
+            csv_field(&row.category),
+            row.spend,
+            row.count,
+            row.last_used
+                .map(|date| date.to_string())
+                .unwrap_or_default()
+        ));
+    }
+
+    csv.push('\n');
+    csv
+}
+
+/// Quotes the field if it contains a character that is special in CSV
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{category_rows, format_csv, CategoryExportArgs};
+    use crate::{TransactionSplit, REPORT_MAX_DAYS};
+    use chrono::{Local, TimeZone};
+
+    fn expense(category: Option<&str>, amount: f64, day: u32) -> TransactionSplit {
+        TransactionSplit {
+            date: Local.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap(),
+            category_name: category.map(str::to_string),
+            ..TransactionSplit::fixture("withdrawal", amount)
+        }
+    }
+
+    #[test]
+    fn test_parse_category_export() {
+        assert_eq!(30, CategoryExportArgs::parse("").unwrap().days);
+        assert_eq!(7, CategoryExportArgs::parse(" 7 ").unwrap().days);
+        assert_eq!(
+            REPORT_MAX_DAYS,
+            CategoryExportArgs::parse("100000000").unwrap().days
+        );
+        assert!(CategoryExportArgs::parse("0").is_err());
+        assert!(CategoryExportArgs::parse("7 8").is_err());
+    }
+
+    #[test]
+    fn test_category_csv() {
+        let categories = vec![
+            "Groceries".to_string(),
+            "Bills".to_string(),
+            "Food, Drink".to_string(),
+        ];
+        let expenses = vec![
+            expense(Some("Groceries"), 10.5, 2),
+            expense(Some("Groceries"), 20.0, 5),
+            expense(Some("Food, Drink"), 4.0, 3),
+            expense(None, 1.25, 4),
+        ];
+
+        assert_eq!(
+            "category,spend,count,last_used\n\
+             Groceries,30.50,2,2024-03-05\n\
+             \"Food, Drink\",4.00,1,2024-03-03\n\
+             Uncategorized,1.25,1,2024-03-04\n\
+             Bills,0.00,0,\n",
+            format_csv(&category_rows(&categories, &expenses))
+        );
+    }
+
+    #[test]
+    fn test_empty_category_csv() {
+        assert_eq!(
+            "category,spend,count,last_used\n",
+            format_csv(&category_rows(&[], &[]))
+        );
+    }
+}
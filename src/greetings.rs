@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+const GREETED_TREE: &str = "greeted";
+
+/// How new members of the room are sent the help text
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GreetNewMembers {
+    /// New members are not greeted
+    #[default]
+    Off,
+    /// Mention the new member in the room
+    Mention,
+    /// Send the new member a DM
+    Dm,
+}
+
+/// Persists which members have been greeted in each room, so that nobody is greeted twice
+pub struct GreetedStore {
+    tree: sled::Tree,
+}
+
+impl GreetedStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(GREETED_TREE)?,
+        })
+    }
+
+    /// Records that the member was greeted in the room. Returns true if they hadn't been greeted
+    /// there before, and so should be.
+    pub fn mark_greeted(&self, room_id: &str, user_id: &str) -> anyhow::Result<bool> {
+        let key = format!("{room_id}|{user_id}");
+        let first = self
+            .tree
+            .compare_and_swap(key, None as Option<&[u8]>, Some(&[1u8][..]))?
+            .is_ok();
+        self.tree.flush()?;
+        Ok(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::greetings::GreetedStore;
+
+    #[test]
+    fn test_greet_once() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = GreetedStore::open(&db).unwrap();
+
+        assert!(store
+            .mark_greeted("!room:example.org", "@alice:example.org")
+            .unwrap());
+        assert!(!store
+            .mark_greeted("!room:example.org", "@alice:example.org")
+            .unwrap());
+        assert!(store
+            .mark_greeted("!room:example.org", "@bob:example.org")
+            .unwrap());
+        assert!(store
+            .mark_greeted("!other:example.org", "@alice:example.org")
+            .unwrap());
+
+        let reopened = GreetedStore::open(&db).unwrap();
+        assert!(!reopened
+            .mark_greeted("!room:example.org", "@alice:example.org")
+            .unwrap());
+    }
+}
@@ -0,0 +1,161 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{info, warn};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LIVENESS_PATH: &str = "/healthz";
+const READINESS_PATH: &str = "/readyz";
+/// How long `/readyz` waits for Firefly before reporting that it isn't ready
+const FIREFLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether the bot is up, for container liveness and readiness probes. The bot is live once it has
+/// completed its initial sync and is listening for messages, and ready when Firefly also responds.
+pub struct Health {
+    listening: AtomicBool,
+    http_client: reqwest::Client,
+    /// Firefly's `about` endpoint, which is requested to check that Firefly is reachable
+    about_url: String,
+    firefly_api_key: String,
+}
+
+impl Health {
+    pub fn new(http_client: reqwest::Client, about_url: String, firefly_api_key: String) -> Self {
+        Self {
+            listening: AtomicBool::new(false),
+            http_client,
+            about_url,
+            firefly_api_key,
+        }
+    }
+
+    pub fn set_listening(&self, listening: bool) {
+        self.listening.store(listening, Ordering::Relaxed);
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+
+    async fn is_firefly_reachable(&self) -> bool {
+        let response = self
+            .http_client
+            .get(&self.about_url)
+            .header("Authorization", format!("Bearer {}", self.firefly_api_key))
+            .timeout(FIREFLY_TIMEOUT)
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                warn!("Firefly is not ready: {}", response.status());
+                false
+            }
+            Err(e) => {
+                warn!("Firefly is not ready: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Serves `/healthz` and `/readyz` on the port, on every interface, until `shutdown` completes
+pub async fn serve(
+    health: Arc<Health>,
+    port: u16,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_service = make_service_fn(move |_| {
+        let health = Arc::clone(&health);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let health = Arc::clone(&health);
+                async move { Ok::<_, Infallible>(respond(&health, &request).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)?.serve(make_service);
+    info!("Serving health checks on {address}");
+    server.with_graceful_shutdown(shutdown).await?;
+    Ok(())
+}
+
+async fn respond(health: &Health, request: &Request<Body>) -> Response<Body> {
+    let status = if request.method() != Method::GET {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else {
+        match request.uri().path() {
+            LIVENESS_PATH if health.is_listening() => StatusCode::OK,
+            READINESS_PATH if health.is_listening() && health.is_firefly_reachable().await => {
+                StatusCode::OK
+            }
+            LIVENESS_PATH | READINESS_PATH => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::NOT_FOUND,
+        }
+    };
+
+    let mut response = Response::new(Body::from(
+        status.canonical_reason().unwrap_or_default().to_string(),
+    ));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::health::{respond, Health};
+    use hyper::{Body, Method, Request, StatusCode};
+
+    async fn status(health: &Health, method: Method, path: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        respond(health, &request).await.status()
+    }
+
+    #[tokio::test]
+    async fn test_health_status() {
+        let health = Health::new(
+            reqwest::Client::new(),
+            "http://127.0.0.1:0/api/v1/about".to_string(),
+            "key".to_string(),
+        );
+
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            status(&health, Method::GET, "/healthz").await
+        );
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            status(&health, Method::GET, "/readyz").await
+        );
+
+        health.set_listening(true);
+        assert_eq!(
+            StatusCode::OK,
+            status(&health, Method::GET, "/healthz").await
+        );
+        // Firefly can't be reached at port 0
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            status(&health, Method::GET, "/readyz").await
+        );
+
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            status(&health, Method::GET, "/metrics").await
+        );
+        assert_eq!(
+            StatusCode::METHOD_NOT_ALLOWED,
+            status(&health, Method::POST, "/healthz").await
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::balance::BALANCE_HISTORY_USAGE;
+use crate::commands::AGAIN_CMD;
+use crate::convert::CONVERT_USAGE;
+use crate::deposit::DEPOSIT_USAGE;
+use crate::edit::EDIT_USAGE;
+use crate::export::CATEGORY_EXPORT_USAGE;
+use crate::ledger::SETTLE_USAGE;
+use crate::limits::LIMIT_SET_USAGE;
+use crate::net::NET_USAGE;
+use crate::pauses::{PAUSE_USER_USAGE, UNPAUSE_USER_USAGE};
+use crate::recent::RECENT_USAGE;
+use crate::reminders::REMIND_USAGE;
+use crate::reportlink::REPORT_LINK_USAGE;
+use crate::spend::SPEND_USAGE;
+use crate::split::SPLIT_USAGE;
+use crate::summary::SUMMARY_USAGE;
+use crate::transfer::TRANSFER_USAGE;
+use crate::{
+    ACCOUNTS_CMD, ADD_CMD, ADD_USAGE, BALANCE_CMD, BALANCE_HISTORY_CMD, BUDGET_CMD, CATEGORIES_CMD,
+    CATEGORIES_USAGE, CATEGORY_EXPORT_CMD, CATEGORY_TREE_CMD, CONVERT_CMD, DELETE_CMD, DEPOSIT_CMD,
+    DIAG_CMD, DIAG_USAGE, EDIT_CMD, GOAL_ADD_CMD, GOAL_ADD_USAGE, HELP_CMD, HISTORY_CMD,
+    HISTORY_USAGE, LIMIT_SET_CMD, MONTH_SET_CMD, MONTH_SET_USAGE, NET_CMD, PAUSE_USER_CMD,
+    PING_CMD, RATES_CMD, RECENT_CMD, REFRESH_CMD, REMINDERS_CMD, REMIND_CMD, REPORT_LINK_CMD,
+    RETRY_CMD, SETTLE_CMD, SPEND_CMD, SPLIT_CMD, SUBSCRIBE_CMD, SUBSCRIBE_USAGE, SUMMARY_CMD,
+    TAGS_CMD, TOP_CMD, TOP_USAGE, TRANSFER_CMD, UNPAUSE_USER_CMD, UNSUBSCRIBE_CMD, WHO_OWES_CMD,
+};
+
+pub const HELP_USAGE: &str = "!help [Command] | !help examples";
+
+/// What a command does and how to use it, which is shown in the command list and by
+/// `!help <Command>`
+struct CommandHelp {
+    command: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    example: Option<&'static str>,
+}
+
+/// Every command, in the order they're listed
+const COMMAND_HELP: &[CommandHelp] = &[
+    CommandHelp {
+        command: ACCOUNTS_CMD,
+        usage: ACCOUNTS_CMD,
+        description: "List the asset accounts and their ids",
+        example: None,
+    },
+    CommandHelp {
+        command: ADD_CMD,
+        usage: ADD_USAGE,
+        description: "Add an expense to a category, paid from the room's source account",
+        example: Some("!add Groceries: 42.10 @yesterday >Costco milk and eggs #food"),
+    },
+    CommandHelp {
+        command: AGAIN_CMD,
+        usage: AGAIN_CMD,
+        description: "Repeat your last command",
+        example: None,
+    },
+    CommandHelp {
+        command: BALANCE_CMD,
+        usage: BALANCE_CMD,
+        description: "Show the balance of the room's source account",
+        example: None,
+    },
+    CommandHelp {
+        command: BALANCE_HISTORY_CMD,
+        usage: BALANCE_HISTORY_USAGE,
+        description: "Draw an asset account's balance at the end of each day",
+        example: Some("!balance-history Checking 14"),
+    },
+    CommandHelp {
+        command: BUDGET_CMD,
+        usage: BUDGET_CMD,
+        description: "Show what was spent from each budget this month, and what remains",
+        example: None,
+    },
+    CommandHelp {
+        command: CATEGORIES_CMD,
+        usage: CATEGORIES_USAGE,
+        description: "List the categories, or those containing the search, optionally fetching the latest first",
+        example: None,
+    },
+    CommandHelp {
+        command: CATEGORY_TREE_CMD,
+        usage: CATEGORY_TREE_CMD,
+        description: "List the categories as a tree, nested by their names",
+        example: None,
+    },
+    CommandHelp {
+        command: CATEGORY_EXPORT_CMD,
+        usage: CATEGORY_EXPORT_USAGE,
+        description: "Export the spending in each category as a CSV file",
+        example: Some("!catexport 90"),
+    },
+    CommandHelp {
+        command: CONVERT_CMD,
+        usage: CONVERT_USAGE,
+        description: "Convert an amount to another currency",
+        example: Some("!convert 50 EUR USD"),
+    },
+    CommandHelp {
+        command: DELETE_CMD,
+        usage: DELETE_CMD,
+        description: "Delete the transaction added by the add or deposit that's replied to",
+        example: None,
+    },
+    CommandHelp {
+        command: DEPOSIT_CMD,
+        usage: DEPOSIT_USAGE,
+        description: "Add income, optionally from a revenue account",
+        example: Some("!deposit Salary: 3000 from Employer"),
+    },
+    CommandHelp {
+        command: DIAG_CMD,
+        usage: DIAG_USAGE,
+        description: "Dump the bot's state for troubleshooting. Admins only",
+        example: None,
+    },
+    CommandHelp {
+        command: EDIT_CMD,
+        usage: EDIT_USAGE,
+        description: "Change the amount, category, note, or tags of the last transaction you added",
+        example: Some("!edit category Household"),
+    },
+    CommandHelp {
+        command: GOAL_ADD_CMD,
+        usage: GOAL_ADD_USAGE,
+        description: "Create a savings goal",
+        example: Some("!goaladd Vacation: 2000"),
+    },
+    CommandHelp {
+        command: HELP_CMD,
+        usage: HELP_USAGE,
+        description: "List the commands, show how to use one, or show examples",
+        example: Some("!help add"),
+    },
+    CommandHelp {
+        command: HISTORY_CMD,
+        usage: HISTORY_USAGE,
+        description: "Page through your recent expenses, or list your recent commands",
+        example: Some("!history commands"),
+    },
+    CommandHelp {
+        command: LIMIT_SET_CMD,
+        usage: LIMIT_SET_USAGE,
+        description: "Set, remove, or list the spending limits per budget cycle",
+        example: Some("!limitset Eating Out 150"),
+    },
+    CommandHelp {
+        command: MONTH_SET_CMD,
+        usage: MONTH_SET_USAGE,
+        description: "Set the day of the month that budget cycles start on",
+        example: Some("!monthset 25"),
+    },
+    CommandHelp {
+        command: NET_CMD,
+        usage: NET_USAGE,
+        description: "Show your income minus your expenses",
+        example: Some("!net 7"),
+    },
+    CommandHelp {
+        command: PAUSE_USER_CMD,
+        usage: PAUSE_USER_USAGE,
+        description: "Stop a user's commands for a while, or list paused users. Admins only",
+        example: Some("!pauseuser @alice:example.org 2h"),
+    },
+    CommandHelp {
+        command: PING_CMD,
+        usage: PING_CMD,
+        description: "Check that the bot is running",
+        example: None,
+    },
+    CommandHelp {
+        command: RATES_CMD,
+        usage: RATES_CMD,
+        description: "List Firefly's latest exchange rates",
+        example: None,
+    },
+    CommandHelp {
+        command: RECENT_CMD,
+        usage: RECENT_USAGE,
+        description: "List the source account's last transactions",
+        example: Some("!recent 10"),
+    },
+    CommandHelp {
+        command: REFRESH_CMD,
+        usage: REFRESH_CMD,
+        description: "Refresh the bot's copy of the categories, accounts, and tags",
+        example: None,
+    },
+    CommandHelp {
+        command: REMIND_CMD,
+        usage: REMIND_USAGE,
+        description: "Send yourself a reminder later, or cancel one",
+        example: Some("!remind 2h log the receipts"),
+    },
+    CommandHelp {
+        command: REMINDERS_CMD,
+        usage: REMINDERS_CMD,
+        description: "List your pending reminders",
+        example: None,
+    },
+    CommandHelp {
+        command: REPORT_LINK_CMD,
+        usage: REPORT_LINK_USAGE,
+        description: "Link to Firefly's report for a category, tag, or account",
+        example: Some("!report-link category Groceries"),
+    },
+    CommandHelp {
+        command: RETRY_CMD,
+        usage: RETRY_CMD,
+        description: "Retry your last failed add",
+        example: None,
+    },
+    CommandHelp {
+        command: SETTLE_CMD,
+        usage: SETTLE_USAGE,
+        description: "Record that one person paid another back",
+        example: Some("!settle bob alice 20"),
+    },
+    CommandHelp {
+        command: SPEND_CMD,
+        usage: SPEND_USAGE,
+        description: "Show the spending in a category, by week",
+        example: Some("!spend Groceries 60"),
+    },
+    CommandHelp {
+        command: SPLIT_CMD,
+        usage: SPLIT_USAGE,
+        description: "Split an expense evenly between people",
+        example: Some("!split alice,bob Dinner: 60 pizza #food"),
+    },
+    CommandHelp {
+        command: SUBSCRIBE_CMD,
+        usage: SUBSCRIBE_USAGE,
+        description: "Receive a summary of your spending by DM",
+        example: Some("!subscribe weekly"),
+    },
+    CommandHelp {
+        command: SUMMARY_CMD,
+        usage: SUMMARY_USAGE,
+        description: "Show the spending in each category for the budget cycle or a month, and the total",
+        example: Some("!summary 2024-01"),
+    },
+    CommandHelp {
+        command: TAGS_CMD,
+        usage: TAGS_CMD,
+        description: "List the tags",
+        example: None,
+    },
+    CommandHelp {
+        command: TOP_CMD,
+        usage: TOP_USAGE,
+        description: "List your biggest expenses",
+        example: Some("!top 10 7"),
+    },
+    CommandHelp {
+        command: TRANSFER_CMD,
+        usage: TRANSFER_USAGE,
+        description: "Move money between two asset accounts",
+        example: Some("!transfer Checking -> Savings: 250 monthly top up"),
+    },
+    CommandHelp {
+        command: UNPAUSE_USER_CMD,
+        usage: UNPAUSE_USER_USAGE,
+        description: "Allow a paused user's commands again. Admins only",
+        example: Some("!unpauseuser @alice:example.org"),
+    },
+    CommandHelp {
+        command: UNSUBSCRIBE_CMD,
+        usage: UNSUBSCRIBE_CMD,
+        description: "Stop receiving spending summaries",
+        example: None,
+    },
+    CommandHelp {
+        command: WHO_OWES_CMD,
+        usage: WHO_OWES_CMD,
+        description: "List who owes whom from splits",
+        example: None,
+    },
+];
+
+/// The example of each command that has one
+#[cfg(test)]
+pub fn command_examples() -> impl Iterator<Item = &'static str> {
+    COMMAND_HELP.iter().filter_map(|help| help.example)
+}
+
+/// Lists the available commands
+pub fn help_text() -> String {
+    let mut help = String::from("Available commands:");
+    for command in COMMAND_HELP {
+        help.push_str(&format!("\n - {}", command.usage));
+    }
+    help
+}
+
+/// Returns the name of the command as it's defined, eg: `!add` for `add` or `!ADD`, if there is a
+/// command with that name
+pub fn find_command(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches('!');
+    COMMAND_HELP
+        .iter()
+        .map(|help| help.command)
+        .find(|command| command[1..].eq_ignore_ascii_case(name))
+}
+
+/// Shows how to use the command, what it does, and an example of it
+pub fn command_help(command: &str) -> Option<String> {
+    let help = COMMAND_HELP.iter().find(|help| help.command == command)?;
+    let mut text = format!("{}\n{}", help.usage, help.description);
+    if let Some(example) = help.example {
+        text.push_str(&format!("\nExample: {example}"));
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::help::{command_help, find_command, help_text, COMMAND_HELP};
+    use crate::{ADD_CMD, ADD_USAGE, PING_CMD, TRANSFER_CMD};
+
+    #[test]
+    fn test_command_help() {
+        assert_eq!(Some(ADD_CMD), find_command("add"));
+        assert_eq!(Some(TRANSFER_CMD), find_command("!Transfer"));
+        assert_eq!(None, find_command("launch"));
+
+        assert_eq!(
+            format!(
+                "{ADD_USAGE}\nAdd an expense to a category, paid from the room's source account\nExample: !add Groceries: 42.10 @yesterday >Costco milk and eggs #food"
+            ),
+            command_help(ADD_CMD).unwrap()
+        );
+        assert_eq!(
+            "!ping\nCheck that the bot is running",
+            command_help(PING_CMD).unwrap()
+        );
+
+        let help = help_text();
+        assert_eq!(COMMAND_HELP.len() + 1, help.lines().count());
+        assert!(help.contains(&format!("\n - {ADD_USAGE}")));
+    }
+}
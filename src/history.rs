@@ -0,0 +1,162 @@
+use matrix_sdk::ruma::OwnedEventId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const PREV_REACTION: &str = "⬅\u{fe0f}";
+pub const NEXT_REACTION: &str = "➡\u{fe0f}";
+
+/// Splits lines of output into pages that can be turned one at a time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pager {
+    title: String,
+    lines: Vec<String>,
+    page_size: usize,
+    page: usize,
+}
+
+/// Holds the pager for each paginated message, keyed by the message's event id, until it expires
+pub struct PagerStore {
+    timeout: Duration,
+    entries: HashMap<OwnedEventId, (Pager, Instant)>,
+}
+
+impl Pager {
+    pub fn new(title: String, lines: Vec<String>, page_size: usize) -> Self {
+        Self {
+            title,
+            lines,
+            page_size: page_size.max(1),
+            page: 0,
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.lines.len().div_ceil(self.page_size).max(1)
+    }
+
+    /// Turns to the next page for ➡️ or the previous page for ⬅️. Returns true if the page changed.
+    pub fn turn(&mut self, reaction: &str) -> bool {
+        let page = match reaction {
+            NEXT_REACTION if self.page + 1 < self.page_count() => self.page + 1,
+            PREV_REACTION if self.page > 0 => self.page - 1,
+            _ => return false,
+        };
+        self.page = page;
+        true
+    }
+
+    /// Renders the current page
+    pub fn render(&self) -> String {
+        let mut output = format!(
+            "{} (page {}/{})",
+            self.title,
+            self.page + 1,
+            self.page_count()
+        );
+        for line in self
+            .lines
+            .iter()
+            .skip(self.page * self.page_size)
+            .take(self.page_size)
+        {
+            output.push_str("\n - ");
+            output.push_str(line);
+        }
+        output
+    }
+}
+
+impl PagerStore {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, message_id: OwnedEventId, pager: Pager) {
+        let timeout = self.timeout;
+        self.entries
+            .retain(|_, (_, created_at)| created_at.elapsed() < timeout);
+        self.entries.insert(message_id, (pager, Instant::now()));
+    }
+
+    /// Turns the message's pager, returning the rendered page if it changed. Expired pagers are
+    /// removed.
+    pub fn turn(&mut self, message_id: &OwnedEventId, reaction: &str) -> Option<String> {
+        let (pager, created_at) = self.entries.get_mut(message_id)?;
+
+        if created_at.elapsed() >= self.timeout {
+            self.entries.remove(message_id);
+            return None;
+        }
+
+        if pager.turn(reaction) {
+            Some(pager.render())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::{Pager, PagerStore, NEXT_REACTION, PREV_REACTION};
+    use matrix_sdk::ruma::OwnedEventId;
+    use std::time::Duration;
+
+    #[test]
+    fn test_page_transitions() {
+        let lines = (1..=5).map(|i| i.to_string()).collect();
+        let mut pager = Pager::new("History".to_string(), lines, 2);
+
+        assert_eq!(3, pager.page_count());
+        assert_eq!("History (page 1/3)\n - 1\n - 2", pager.render());
+        assert!(!pager.turn(PREV_REACTION));
+
+        assert!(pager.turn(NEXT_REACTION));
+        assert!(pager.turn(NEXT_REACTION));
+        assert_eq!("History (page 3/3)\n - 5", pager.render());
+        assert!(!pager.turn(NEXT_REACTION));
+
+        assert!(pager.turn(PREV_REACTION));
+        assert_eq!("History (page 2/3)\n - 3\n - 4", pager.render());
+        assert!(!pager.turn("👍"));
+
+        let empty = Pager::new("History".to_string(), vec![], 2);
+        assert_eq!(1, empty.page_count());
+        assert_eq!("History (page 1/1)", empty.render());
+    }
+
+    #[test]
+    fn test_pager_store() {
+        let first = OwnedEventId::try_from("$first:example.org").unwrap();
+        let second = OwnedEventId::try_from("$second:example.org").unwrap();
+        let lines: Vec<String> = (1..=3).map(|i| i.to_string()).collect();
+
+        let mut store = PagerStore::new(Duration::from_secs(60));
+        store.insert(first.clone(), Pager::new("A".to_string(), lines.clone(), 1));
+        store.insert(
+            second.clone(),
+            Pager::new("B".to_string(), lines.clone(), 1),
+        );
+
+        assert_eq!(
+            Some("A (page 2/3)\n - 2".to_string()),
+            store.turn(&first, NEXT_REACTION)
+        );
+        assert_eq!(
+            Some("B (page 2/3)\n - 2".to_string()),
+            store.turn(&second, NEXT_REACTION)
+        );
+        assert_eq!(
+            Some("A (page 3/3)\n - 3".to_string()),
+            store.turn(&first, NEXT_REACTION)
+        );
+        assert_eq!(None, store.turn(&first, NEXT_REACTION));
+
+        let mut expired = PagerStore::new(Duration::ZERO);
+        expired.insert(first.clone(), Pager::new("A".to_string(), lines, 1));
+        assert_eq!(None, expired.turn(&first, NEXT_REACTION));
+    }
+}
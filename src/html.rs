@@ -0,0 +1,157 @@
+/// The prefix of the matrix.to links that clients render pills with
+const MATRIX_TO_PREFIX: &str = "https://matrix.to/#/";
+/// Elements that end a line of text
+const BLOCK_ELEMENTS: [&str; 3] = ["p", "div", "li"];
+
+/// Converts the HTML `formatted_body` of a message to the plain text that commands are parsed
+/// from. The rich reply fallback is dropped, line breaks and paragraphs become newlines, and the
+/// text of every other element is kept. Pills that link to a room alias, which some clients create
+/// when a tag is autocompleted, become the tag, eg: `#food:example.org` becomes `#food`.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let (closing, name) = tag_name(tag);
+        match name.as_str() {
+            "mx-reply" if !closing => rest = skip_past(rest, "</mx-reply>"),
+            "br" => text.push('\n'),
+            "a" if !closing => {
+                if let Some(tag) = href(tag).and_then(room_alias_tag) {
+                    text.push_str(&tag);
+                    rest = skip_past(rest, "</a>");
+                }
+            }
+            name if closing && BLOCK_ELEMENTS.contains(&name) => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    text.push_str(&decode_entities(rest));
+    text.trim().to_string()
+}
+
+/// Whether the tag closes an element, and the element's lower case name
+fn tag_name(tag: &str) -> (bool, String) {
+    let tag = tag.trim();
+    let closing = tag.starts_with('/');
+    let name = tag
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    (closing, name)
+}
+
+/// The text after the first `end`, or nothing if it never appears
+fn skip_past<'a>(text: &'a str, end: &str) -> &'a str {
+    match text.find(end) {
+        Some(index) => &text[index + end.len()..],
+        None => "",
+    }
+}
+
+/// The quoted value of the tag's `href` attribute
+fn href(tag: &str) -> Option<&str> {
+    let value = &tag[tag.find("href=")? + "href=".len()..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+    Some(&value[..value.find(quote)?])
+}
+
+/// Converts a matrix.to link to a room alias to a tag of the alias' name, without its server
+fn room_alias_tag(href: &str) -> Option<String> {
+    let alias = percent_decode(href.strip_prefix(MATRIX_TO_PREFIX)?);
+    if !alias.starts_with('#') {
+        return None;
+    }
+    let name = alias.split(':').next().unwrap_or_default();
+    (name.len() > 1).then(|| name.to_string())
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::html_to_text;
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            "!add Groceries 45 milk & eggs",
+            html_to_text("!add <b>Groceries</b> 45 milk &amp; eggs")
+        );
+        assert_eq!(
+            "!add Groceries 45 #food",
+            html_to_text(
+                "!add Groceries 45 <a href=\"https://matrix.to/#/%23food:example.org\">#food:example.org</a>"
+            )
+        );
+        assert_eq!(
+            "!add Groceries 45 #food",
+            html_to_text(
+                "!add Groceries 45 <a href='https://matrix.to/#/#food:example.org'>Food room</a>"
+            )
+        );
+        assert_eq!(
+            "!add Groceries 45 with alice",
+            html_to_text(
+                "!add Groceries 45 with <a href=\"https://matrix.to/#/@alice:example.org\">alice</a>"
+            )
+        );
+        assert_eq!(
+            "!undo",
+            html_to_text(
+                "<mx-reply><blockquote><a href=\"https://matrix.to/#/!room:example.org/$event\">In reply to</a> !add Groceries 45</blockquote></mx-reply>!undo"
+            )
+        );
+        assert_eq!(
+            "!add Groceries 45\n!balance",
+            html_to_text("<p>!add Groceries 45</p><p>!balance</p>")
+        );
+        assert_eq!(
+            "!add Groceries 45\n!balance",
+            html_to_text("!add Groceries 45<br/>!balance")
+        );
+        assert_eq!("1 < 2", html_to_text("1 &lt; 2"));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{format_amount, INVALID_ARGS};
+use anyhow::anyhow;
+use log::warn;
+use std::str::FromStr;
+
+const LEDGER_TREE: &str = "ledger";
+const KEY_SEPARATOR: char = '\n';
+
+pub const SETTLE_USAGE: &str = "!settle <Payer> <Payee> <Amount>";
+
+/// A debt between two people
+#[derive(Debug, Clone, PartialEq)]
+pub struct Debt {
+    pub debtor: String,
+    pub creditor: String,
+    pub amount: f64,
+}
+
+/// A payment from one person to another that pays off debt
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettleArgs {
+    pub payer: String,
+    pub payee: String,
+    pub amount: f64,
+}
+
+/// Tracks what people owe each other from splits. Each pair of people has a single net balance, in
+/// cents, keyed by their names in order. A positive balance means that the first person owes the
+/// second. Names are compared ignoring case.
+pub struct Ledger {
+    tree: sled::Tree,
+}
+
+impl SettleArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        let [payer, payee, amount] = parts[..] else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {SETTLE_USAGE}"));
+        };
+
+        if payer.eq_ignore_ascii_case(payee) {
+            return Err(anyhow!("{payer} can't pay themselves"));
+        }
+
+        let amount_str = amount.strip_prefix('$').unwrap_or(amount);
+        match f64::from_str(amount_str) {
+            Ok(amount) if amount.is_finite() && amount > 0.0 => Ok(Self {
+                payer: payer.to_string(),
+                payee: payee.to_string(),
+                amount,
+            }),
+            _ => Err(anyhow!("Invalid amount: {amount}")),
+        }
+    }
+}
+
+impl Ledger {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(LEDGER_TREE)?,
+        })
+    }
+
+    /// Records that `debtor` owes `creditor` the amount, eg: their share of a split `creditor` paid
+    pub fn add_debt(&self, debtor: &str, creditor: &str, amount: f64) -> anyhow::Result<()> {
+        let (key, sign) = pair_key(debtor, creditor);
+        let balance = self.balance(&key)? + sign * to_cents(amount);
+
+        if balance == 0 {
+            self.tree.remove(key)?;
+        } else {
+            self.tree.insert(key, serde_json::to_vec(&balance)?)?;
+        }
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Returns how much `debtor` owes `creditor`, which is negative if `creditor` owes `debtor`
+    pub fn owed(&self, debtor: &str, creditor: &str) -> anyhow::Result<f64> {
+        let (key, sign) = pair_key(debtor, creditor);
+        Ok(from_cents(sign * self.balance(&key)?))
+    }
+
+    /// Records that `payer` paid `payee` the amount. The amount may not be more than `payer` owes.
+    pub fn settle(&self, args: &SettleArgs) -> anyhow::Result<()> {
+        self.check_settle(args)?;
+        self.add_debt(&args.payee, &args.payer, args.amount)
+    }
+
+    /// Checks that `payer` owes `payee` at least the amount, without recording the settlement
+    pub fn check_settle(&self, args: &SettleArgs) -> anyhow::Result<()> {
+        let owed = self.owed(&args.payer, &args.payee)?;
+
+        if owed <= 0.0 {
+            return Err(anyhow!(
+                "{} doesn't owe {} anything",
+                args.payer,
+                args.payee
+            ));
+        }
+        if to_cents(args.amount) > to_cents(owed) {
+            return Err(anyhow!(
+                "{} only owes {} {}",
+                args.payer,
+                args.payee,
+                format_amount(owed, None)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns every outstanding debt, ordered by debtor and then creditor
+    pub fn debts(&self) -> Vec<Debt> {
+        let mut debts: Vec<Debt> = self
+            .tree
+            .iter()
+            .filter_map(|entry| {
+                let (key, value) = entry
+                    .map_err(|e| warn!("Skipping unreadable ledger entry: {e}"))
+                    .ok()?;
+                let key = String::from_utf8(key.to_vec()).ok()?;
+                let (first, second) = key.split_once(KEY_SEPARATOR)?;
+                let balance: i64 = serde_json::from_slice(&value)
+                    .map_err(|e| warn!("Skipping unreadable ledger entry: {e}"))
+                    .ok()?;
+
+                let (debtor, creditor) = if balance > 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                Some(Debt {
+                    debtor: debtor.to_string(),
+                    creditor: creditor.to_string(),
+                    amount: from_cents(balance.abs()),
+                })
+            })
+            .collect();
+
+        debts.sort_by(|a, b| (&a.debtor, &a.creditor).cmp(&(&b.debtor, &b.creditor)));
+        debts
+    }
+
+    fn balance(&self, key: &str) -> anyhow::Result<i64> {
+        Ok(match self.tree.get(key)? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => 0,
+        })
+    }
+}
+
+/// Formats the debts, eg: "alice owes bob 30.00"
+pub fn format_debts(debts: &[Debt]) -> String {
+    if debts.is_empty() {
+        return "Nobody owes anyone anything".to_string();
+    }
+
+    let mut output = String::from("Debts:");
+    for debt in debts {
+        output.push_str(&format!(
+            "\n - {} owes {} {}",
+            debt.debtor,
+            debt.creditor,
+            format_amount(debt.amount, None)
+        ));
+    }
+    output
+}
+
+/// Returns the key for the pair of people, and the sign to apply to amounts that `debtor` owes
+fn pair_key(debtor: &str, creditor: &str) -> (String, i64) {
+    let debtor = debtor.trim().to_lowercase();
+    let creditor = creditor.trim().to_lowercase();
+
+    if debtor < creditor {
+        (format!("{debtor}{KEY_SEPARATOR}{creditor}"), 1)
+    } else {
+        (format!("{creditor}{KEY_SEPARATOR}{debtor}"), -1)
+    }
+}
+
+fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn from_cents(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::{format_debts, Debt, Ledger, SettleArgs};
+
+    #[test]
+    fn test_settle_adjusts_ledger() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let ledger = Ledger::open(&db).unwrap();
+
+        ledger.add_debt("bob", "alice", 30.0).unwrap();
+        ledger.add_debt("carol", "alice", 30.0).unwrap();
+        ledger.add_debt("alice", "Bob", 10.0).unwrap();
+        assert_eq!(20.0, ledger.owed("Bob", "alice").unwrap());
+        assert_eq!(-20.0, ledger.owed("alice", "bob").unwrap());
+
+        let settle = |args: &str| ledger.settle(&SettleArgs::parse(args).unwrap());
+
+        assert!(settle("alice bob 5").is_err());
+        assert!(settle("bob alice 25").is_err());
+        ledger
+            .check_settle(&SettleArgs::parse("Bob alice 15").unwrap())
+            .unwrap();
+        assert_eq!(20.0, ledger.owed("bob", "alice").unwrap());
+        settle("Bob alice 15").unwrap();
+        assert_eq!(5.0, ledger.owed("bob", "alice").unwrap());
+        assert_eq!(
+            vec![
+                Debt {
+                    debtor: "bob".to_string(),
+                    creditor: "alice".to_string(),
+                    amount: 5.0
+                },
+                Debt {
+                    debtor: "carol".to_string(),
+                    creditor: "alice".to_string(),
+                    amount: 30.0
+                },
+            ],
+            ledger.debts()
+        );
+
+        settle("bob alice 5").unwrap();
+        settle("carol alice $30").unwrap();
+        assert!(ledger.debts().is_empty());
+        assert_eq!("Nobody owes anyone anything", format_debts(&ledger.debts()));
+    }
+
+    #[test]
+    fn test_parse_settle() {
+        assert_eq!(
+            SettleArgs {
+                payer: "Alice".to_string(),
+                payee: "Bob".to_string(),
+                amount: 30.0
+            },
+            SettleArgs::parse("Alice Bob 30").unwrap()
+        );
+        assert!(SettleArgs::parse("Alice alice 30").is_err());
+        assert!(SettleArgs::parse("Alice Bob 0").is_err());
+        assert!(SettleArgs::parse("Alice Bob").is_err());
+        assert!(SettleArgs::parse("Alice Bob 30 extra").is_err());
+    }
+}
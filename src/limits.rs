@@ -0,0 +1,222 @@
+use crate::{format_amount, INVALID_ARGS};
+use anyhow::anyhow;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+const LIMITS_TREE: &str = "limits";
+
+pub const LIMIT_SET_USAGE: &str =
+    "!limitset <Category> <Amount> | !limitset <Category> remove | !limitset list";
+
+const REMOVE_ARG: &str = "remove";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitSetArgs {
+    /// Set the category's monthly spending limit
+    Set { category: String, amount: f64 },
+    /// Remove the category's spending limit
+    Remove(String),
+    /// List the spending limits
+    List,
+}
+
+impl LimitSetArgs {
+    /// Parses the category, which may contain spaces, followed by the amount or "remove"
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let args = args.trim();
+        if args == "list" {
+            return Ok(LimitSetArgs::List);
+        }
+
+        let Some((category, value)) = args.rsplit_once(' ') else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {LIMIT_SET_USAGE}"));
+        };
+        let category = category.trim().to_string();
+        if category.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {LIMIT_SET_USAGE}"));
+        }
+
+        if value.eq_ignore_ascii_case(REMOVE_ARG) {
+            return Ok(LimitSetArgs::Remove(category));
+        }
+
+        match f64::from_str(value) {
+            Ok(amount) if amount.is_finite() && amount > 0.0 => {
+                Ok(LimitSetArgs::Set { category, amount })
+            }
+            _ => Err(anyhow!(
+                "Invalid limit: {value}. The limit must be a positive amount"
+            )),
+        }
+    }
+}
+
+/// A limit set with `!limitset`. A limit without an amount was removed, and hides any limit on
+/// the category in the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct StoredLimit {
+    category: String,
+    amount: Option<f64>,
+}
+
+/// Persists the spending limits that are changed with `!limitset`, which take precedence over the
+/// `spending_limits` in the config
+pub struct LimitStore {
+    tree: sled::Tree,
+}
+
+impl LimitStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(LIMITS_TREE)?,
+        })
+    }
+
+    pub fn set(&self, category: &str, amount: f64) -> anyhow::Result<()> {
+        self.store(category, Some(amount))
+    }
+
+    pub fn remove(&self, category: &str) -> anyhow::Result<()> {
+        self.store(category, None)
+    }
+
+    fn store(&self, category: &str, amount: Option<f64>) -> anyhow::Result<()> {
+        let limit = StoredLimit {
+            category: category.to_string(),
+            amount,
+        };
+        self.tree
+            .insert(category.to_lowercase(), serde_json::to_vec(&limit)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Returns the spending limit of each category by category name, combining the limits in the
+    /// config with the ones set with `!limitset`. Category names are matched ignoring case.
+    pub fn limits(&self, configured: &HashMap<String, f64>) -> BTreeMap<String, f64> {
+        let mut limits: HashMap<String, (String, Option<f64>)> = configured
+            .iter()
+            .map(|(category, amount)| {
+                (
+                    category.to_lowercase(),
+                    (category.to_string(), Some(*amount)),
+                )
+            })
+            .collect();
+
+        for entry in self.tree.iter() {
+            match entry.map_err(anyhow::Error::from).and_then(|(key, value)| {
+                Ok((
+                    String::from_utf8_lossy(&key).to_string(),
+                    serde_json::from_slice::<StoredLimit>(&value)?,
+                ))
+            }) {
+                Ok((key, limit)) => {
+                    limits.insert(key, (limit.category, limit.amount));
+                }
+                Err(e) => warn!("Ignoring unreadable spending limit: {e}"),
+            }
+        }
+
+        limits
+            .into_values()
+            .filter_map(|(category, amount)| Some((category, amount?)))
+            .collect()
+    }
+
+    /// Returns the category's spending limit, if it has one
+    pub fn limit(&self, category: &str, configured: &HashMap<String, f64>) -> Option<f64> {
+        self.limits(configured)
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(category))
+            .map(|(_, amount)| amount)
+    }
+}
+
+/// Returns a warning if `spent` in the category's current budget cycle is over its limit
+pub fn limit_warning(category: &str, spent: f64, limit: f64) -> Option<String> {
+    if spent > limit {
+        Some(format!(
+            "⚠️ {category} is over its limit: {} of {} spent this cycle",
+            format_amount(spent, None),
+            format_amount(limit, None)
+        ))
+    } else {
+        None
+    }
+}
+
+pub fn format_limits(limits: &BTreeMap<String, f64>) -> String {
+    if limits.is_empty() {
+        return "No spending limits are set".to_string();
+    }
+
+    let mut output = String::from("Spending limits:");
+    for (category, amount) in limits {
+        output.push_str(&format!(
+            "\n - {category}: {}",
+            format_amount(*amount, None)
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::{limit_warning, LimitSetArgs, LimitStore};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_limit_set_args() {
+        assert_eq!(
+            LimitSetArgs::Set {
+                category: "Eating Out".to_string(),
+                amount: 150.5
+            },
+            LimitSetArgs::parse("Eating Out 150.5").unwrap()
+        );
+        assert_eq!(
+            LimitSetArgs::Remove("Groceries".to_string()),
+            LimitSetArgs::parse("Groceries remove").unwrap()
+        );
+        assert_eq!(LimitSetArgs::List, LimitSetArgs::parse("list").unwrap());
+        assert!(LimitSetArgs::parse("Groceries").is_err());
+        assert!(LimitSetArgs::parse("Groceries 0").is_err());
+        assert!(LimitSetArgs::parse("Groceries -5").is_err());
+        assert!(LimitSetArgs::parse("Groceries lots").is_err());
+    }
+
+    #[test]
+    fn test_limit_persistence() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let configured = HashMap::from([
+            ("Groceries".to_string(), 300.0),
+            ("Bills".to_string(), 200.0),
+        ]);
+
+        let store = LimitStore::open(&db).unwrap();
+        assert_eq!(Some(300.0), store.limit("groceries", &configured));
+
+        store.set("Groceries", 400.0).unwrap();
+        store.set("Fun", 50.0).unwrap();
+        store.remove("Bills").unwrap();
+
+        let reopened = LimitStore::open(&db).unwrap();
+        let limits = reopened.limits(&configured);
+        assert_eq!(2, limits.len());
+        assert_eq!(Some(&400.0), limits.get("Groceries"));
+        assert_eq!(Some(&50.0), limits.get("Fun"));
+        assert_eq!(None, reopened.limit("Bills", &configured));
+    }
+
+    #[test]
+    fn test_limit_warning() {
+        assert_eq!(None, limit_warning("Groceries", 400.0, 400.0));
+        assert_eq!(
+            Some("⚠️ Groceries is over its limit: 420.00 of 400.00 spent this cycle".to_string()),
+            limit_warning("Groceries", 420.0, 400.0)
+        );
+    }
+}
@@ -1,58 +1,472 @@
+mod added;
+mod arithmetic;
+mod balance;
+mod bulk;
+mod cache;
+mod cli;
+mod commands;
+mod convert;
+mod cycle;
+mod deposit;
+mod dryrun;
+mod duplicates;
+mod edit;
+mod export;
+mod greetings;
+mod health;
+mod help;
+mod history;
+mod html;
+mod ledger;
+mod limits;
+mod metrics;
+mod mirror;
+mod net;
+mod notes;
+mod pauses;
+mod recent;
+mod reminders;
+mod reportlink;
+mod retry;
+mod settings;
+mod spend;
+mod split;
+mod subscriptions;
+mod summary;
+mod transfer;
+
+use crate::added::AddedStore;
+use crate::balance::{daily_balances, format_balance, format_balance_history, BalanceHistoryArgs};
+use crate::bulk::BulkPreview;
+use crate::cache::{Dependency, ResultCache};
+use crate::cli::{parse_args, usage, CliCommand};
+use crate::commands::{format_command_history, CommandHistory, AGAIN_CMD};
+use crate::convert::{format_conversion, ConversionRates, ConvertArgs};
+use crate::cycle::{budget_cycle, is_valid_start_day};
+use crate::deposit::{infer_add_kind, resolve_revenue_account, AddKind, DepositArgs};
+use crate::dryrun::{format_dry_run, split_dry_run, DRY_RUN_FLAG};
+use crate::duplicates::{RecentAdd, RecentAdds};
+use crate::edit::EditArgs;
+use crate::export::{category_rows, format_csv, CategoryExportArgs};
+use crate::greetings::{GreetNewMembers, GreetedStore};
+use crate::health::Health;
+use crate::help::{command_help, find_command, help_text};
+use crate::history::{Pager, PagerStore, NEXT_REACTION, PREV_REACTION};
+use crate::html::html_to_text;
+use crate::ledger::{format_debts, Ledger, SettleArgs};
+use crate::limits::{format_limits, limit_warning, LimitSetArgs, LimitStore};
+use crate::metrics::Metrics;
+use crate::mirror::{Mirror, MirrorData, MirroredAccount};
+use crate::net::{format_net, net_flow, NetArgs};
+use crate::notes::{DefaultNotes, NoteSelection};
+use crate::pauses::{format_pauses, parse_unpause_user, PauseStore, PauseUserArgs};
+use crate::recent::{format_recent, RecentArgs};
+use crate::reminders::{format_reminders, RemindArgs, ReminderStore};
+use crate::reportlink::{report_url, transaction_url, LinkKind, ReportLinkArgs};
+use crate::retry::with_retries;
+use crate::settings::SettingsStore;
+use crate::spend::{category_spend, format_spend, SpendArgs};
+use crate::split::{
+    add_shares, format_split_summary, split_shares, ShareResult, SplitArgs, SplitFeedback,
+    SplitRemainder,
+};
+use crate::subscriptions::{Frequency, SubscriptionStore};
+use crate::summary::{format_summary, period_label, SummaryArgs};
+use crate::transfer::{resolve_account, TransferArgs};
 use anyhow::anyhow;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDate};
 use log::{debug, error, info, warn, LevelFilter};
+use matrix_sdk::attachment::AttachmentConfig;
 use matrix_sdk::config::SyncSettings;
-use matrix_sdk::room::{Joined, Room};
-use matrix_sdk::ruma::events::reaction::{ReactionEventContent, Relation};
+use matrix_sdk::room::{Invited, Joined, Room};
+use matrix_sdk::ruma::api::client::room::create_room::v3::{
+    Request as CreateRoomRequest, RoomPreset,
+};
+use matrix_sdk::ruma::events::reaction::{
+    OriginalSyncReactionEvent, ReactionEventContent, Relation,
+};
+use matrix_sdk::ruma::events::room::member::{
+    MembershipChange, MembershipState, OriginalSyncRoomMemberEvent, StrippedRoomMemberEvent,
+};
+use matrix_sdk::ruma::events::room::message::sanitize::remove_plain_reply_fallback;
 use matrix_sdk::ruma::events::room::message::{
-    MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+    MessageFormat, MessageType, OriginalSyncRoomMessageEvent, Relation as MessageRelation,
+    Replacement, RoomMessageEventContent, TextMessageEventContent,
 };
 use matrix_sdk::ruma::exports::http::StatusCode;
-use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId};
-use matrix_sdk::Client as MatrixClient;
+use matrix_sdk::ruma::{EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use matrix_sdk::{Client as MatrixClient, RoomType, Session};
 use reqwest::Client as HttpClient;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::fs::File;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
 use std::io::Read;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
+use tokio::sync::oneshot;
 
 // Based on example at: https://github.com/matrix-org/matrix-rust-sdk/tree/main/examples/command_bot
 
 const CACHE_DIR: &str = "matrix-firefly-bot";
+const BOT_STORE_DIR: &str = "bot-store";
 const BOT_NAME: &str = "firefly bot";
 
 const FIREFLY_GENERAL_EXPENSE: &str = "General expense";
+/// The description of a withdrawal when the person is attributed in it, eg: "Groceries by alice"
+const DEFAULT_DESCRIPTION_TEMPLATE: &str = "{category} by {person}";
 
-const FIREFLY_TRANSACTIONS_API: &str = "api/v1/transactions";
-const FIREFLY_CATEGORIES_API: &str = "api/v1/categories";
+const FIREFLY_ABOUT_API: &str = "about";
+const FIREFLY_TRANSACTIONS_API: &str = "transactions";
+const FIREFLY_CATEGORIES_API: &str = "categories";
+const FIREFLY_ACCOUNTS_API: &str = "accounts";
+const FIREFLY_TAGS_API: &str = "tags";
+const FIREFLY_EXCHANGE_RATES_API: &str = "exchange-rates";
+const FIREFLY_PIGGY_BANKS_API: &str = "piggy-banks";
+const FIREFLY_BUDGETS_API: &str = "budgets";
 
+/// The prefix that the commands are defined with. A different `command_prefix` is swapped for it
+/// before parsing, and swapped in when showing commands to users.
+const DEFAULT_COMMAND_PREFIX: &str = "!";
+const ACCOUNTS_CMD: &str = "!accounts";
 const ADD_CMD: &str = "!add";
+const BALANCE_CMD: &str = "!balance";
+const BALANCE_HISTORY_CMD: &str = "!balance-history";
+const BUDGET_CMD: &str = "!budget";
 const CATEGORIES_CMD: &str = "!categories";
+const CATEGORIES_USAGE: &str = "!categories [refresh | Search]";
+const CATEGORY_TREE_CMD: &str = "!cattree";
+const CATEGORY_EXPORT_CMD: &str = "!catexport";
+const CONVERT_CMD: &str = "!convert";
+const DELETE_CMD: &str = "!delete";
+const DEPOSIT_CMD: &str = "!deposit";
+const DIAG_CMD: &str = "!diag";
+const EDIT_CMD: &str = "!edit";
+const GOAL_ADD_CMD: &str = "!goaladd";
 const HELP_CMD: &str = "!help";
+const HISTORY_CMD: &str = "!history";
+const HISTORY_USAGE: &str = "!history [commands]";
+const LIMIT_SET_CMD: &str = "!limitset";
+const MONTH_SET_CMD: &str = "!monthset";
+const NET_CMD: &str = "!net";
+const PAUSE_USER_CMD: &str = "!pauseuser";
 const PING_CMD: &str = "!ping";
+const RATES_CMD: &str = "!rates";
+const RECENT_CMD: &str = "!recent";
+const REPORT_LINK_CMD: &str = "!report-link";
+const REFRESH_CMD: &str = "!refresh";
+const REMIND_CMD: &str = "!remind";
+const REMINDERS_CMD: &str = "!reminders";
+const RETRY_CMD: &str = "!retry";
+const SETTLE_CMD: &str = "!settle";
+const SPEND_CMD: &str = "!spend";
+const SPLIT_CMD: &str = "!split";
+const SUBSCRIBE_CMD: &str = "!subscribe";
+const SUMMARY_CMD: &str = "!summary";
+const TAGS_CMD: &str = "!tags";
+const TOP_CMD: &str = "!top";
+const TRANSFER_CMD: &str = "!transfer";
+const UNPAUSE_USER_CMD: &str = "!unpauseuser";
+const UNSUBSCRIBE_CMD: &str = "!unsubscribe";
+const WHO_OWES_CMD: &str = "!whoowes";
+
+const MAINTENANCE_MESSAGE: &str = "Firefly is under maintenance, try again shortly";
+const CONFIRM_REACTION: &str = "👍";
+const CANCEL_REACTION: &str = "❌";
+const UNAUTHORIZED_REACTION: &str = "🚫";
+/// The reaction to a command that couldn't be parsed, or was invalid
+const INVALID_REACTION: &str = "⚠️";
+/// The reaction to a command that failed because Firefly, or another service, did
+const FAILED_REACTION: &str = "❌";
+const DELETED_REACTION: &str = "🗑️";
+const EDITED_REACTION: &str = "✏️";
+const REFUND_REACTION: &str = "♻️";
+const NEW_CATEGORY_REACTION: &str = "➕";
+/// Reactions used to pick an option, in order
+const NUMBER_REACTIONS: [&str; 9] = [
+    "1\u{fe0f}\u{20e3}",
+    "2\u{fe0f}\u{20e3}",
+    "3\u{fe0f}\u{20e3}",
+    "4\u{fe0f}\u{20e3}",
+    "5\u{fe0f}\u{20e3}",
+    "6\u{fe0f}\u{20e3}",
+    "7\u{fe0f}\u{20e3}",
+    "8\u{fe0f}\u{20e3}",
+    "9\u{fe0f}\u{20e3}",
+];
+/// The largest edit distance at which a category is considered a fuzzy match
+const MAX_CATEGORY_DISTANCE: usize = 2;
 
-const ADD_USAGE: &str = "!add <Category>: <Amount> [Note] [#Tag...]";
+const DIAG_USAGE: &str = "!diag dump";
+const ADD_USAGE: &str =
+    "!add <Category>: <Amount>[, <Category>: <Amount>...] [@Date] [>Payee] [Note] [#Tag...]";
+const GOAL_ADD_USAGE: &str = "!goaladd <Name>: <Target>";
+const MONTH_SET_USAGE: &str = "!monthset <Day>";
+const SUBSCRIBE_USAGE: &str = "!subscribe <daily|weekly|monthly>";
+const TOP_USAGE: &str = "!top [Count] [Days]";
 const INVALID_ARGS: &str = "Invalid arguments.";
 
-#[derive(Debug, PartialEq)]
+/// Config fields that are masked in diagnostics
+const SECRET_CONFIG_FIELDS: &[&str] =
+    &["matrix_password", "matrix_access_token", "firefly_api_key"];
+const REDACTED: &str = "********";
+
+/// Example commands and what they do, shown by `!help examples`. Every example must parse.
+const EXAMPLES: &[(&str, &str)] = &[
+    ("!add Groceries: 42.10", "Add an expense"),
+    (
+        "!add Groceries: $42.10 milk and eggs",
+        "Add an expense with a note",
+    ),
+    (
+        "!add Dining: 25 #date-night #weekend",
+        "Add an expense with tags",
+    ),
+    (
+        "!add Groceries: 42.10 milk and eggs #food",
+        "Add an expense with a note and a tag",
+    ),
+    (
+        "!add Travel: 50 EUR hotel",
+        "Add an expense in another currency",
+    ),
+    (
+        "!add Groceries: 42.10 @yesterday",
+        "Add an expense from yesterday",
+    ),
+    (
+        "!split alice,bob Dinner: 60 pizza #food",
+        "Split an expense evenly between alice and bob",
+    ),
+    (
+        "!deposit Salary: 3000 from Employer",
+        "Add income from the Employer revenue account",
+    ),
+    (
+        "!goaladd Vacation: 2000",
+        "Create a savings goal with a target of 2000",
+    ),
+    (
+        "!top 10 7",
+        "List your 10 biggest expenses from the last 7 days",
+    ),
+    (
+        "!transfer Checking -> Savings: 250",
+        "Move 250 from the Checking account to Savings",
+    ),
+    (
+        "!budget",
+        "Show what was spent from each budget, and what remains",
+    ),
+    (
+        "!subscribe weekly",
+        "Receive a weekly summary of your spending by DM",
+    ),
+];
+
+const HISTORY_DAYS: i64 = 90;
+const HISTORY_PAGE_SIZE: usize = 10;
+
+const TOP_DEFAULT_COUNT: usize = 5;
+const TOP_MAX_COUNT: usize = 25;
+const TOP_DEFAULT_DAYS: i64 = 30;
+/// The longest window, in days, that a report can look back over. Longer windows are clamped to it.
+const REPORT_MAX_DAYS: i64 = 10 * 365;
+
+const RATES_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+const SUBSCRIPTION_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const REMINDER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Currency symbols that can be written with an amount, and their ISO 4217 codes. `$` is taken to be
+/// the account's own currency.
+const CURRENCY_SYMBOLS: [(char, &str); 3] = [('€', "EUR"), ('£', "GBP"), ('¥', "JPY")];
+/// The ISO 4217 codes of the currencies in circulation, which are the words that are taken for a
+/// currency when they follow an amount
+const CURRENCY_CODES: [&str; 155] = [
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWL",
+];
+/// The delay before the first retry of a failed Firefly request, which doubles with each retry
+const FIREFLY_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How many times to retry joining a room the bot was invited to
+const JOIN_MAX_RETRIES: u32 = 4;
+/// The delay before the first retry of a join, which doubles with each retry
+const JOIN_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
 struct AddArgs {
     category: String,
     amount: f64,
+    /// The date given with `@`, which replaces the message's timestamp
+    date: Option<DateTime<Local>>,
     note: Option<String>,
     tags: Vec<String>,
+    /// The ISO code of the currency the amount is in, if it's not the account's currency
+    currency: Option<String>,
+    /// The expense account that was paid, given with `>`
+    payee: Option<String>,
+    /// The categories and amounts after the first, which are added as splits of the same
+    /// transaction
+    splits: Vec<CategoryAmount>,
+    /// Whether to reply with what would be sent to Firefly instead of adding it
+    dry_run: bool,
+    /// Whether the amount, which was written as a negative amount, was refunded to the account
+    refund: bool,
+}
+
+/// A category and the amount of an add that goes to it, eg: `Household: 15` in
+/// `!add Groceries: 40, Household: 15`
+#[derive(Debug, Clone, PartialEq)]
+struct CategoryAmount {
+    category: String,
+    amount: f64,
+}
+
+/// A savings goal to create as a piggy bank
+#[derive(Debug, PartialEq)]
+struct GoalArgs {
+    name: String,
+    target: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct TopArgs {
+    count: usize,
+    days: i64,
+}
+
+/// A category in the hierarchy that is encoded in category names, eg: "Food:Groceries"
+#[derive(Debug, PartialEq)]
+struct CategoryNode {
+    name: String,
+    children: Vec<CategoryNode>,
 }
 
 #[derive(Debug)]
 enum Cmd {
     Ping,
-    Help,
+    /// The command list, or the help for one command, by its name as it's defined
+    Help(Option<&'static str>),
+    HelpExamples,
+    History,
+    CommandHistory,
     Add(AddArgs),
-    Categories,
+    BalanceHistory(BalanceHistoryArgs),
+    AddGoal(GoalArgs),
+    Balance,
+    Budget,
+    Delete,
+    Edit(EditArgs),
+    Summary(SummaryArgs),
+    /// List the categories, fetching them from Firefly first when `refresh` is set
+    Categories {
+        refresh: bool,
+        /// Only list the categories whose names contain this, ignoring case
+        search: Option<String>,
+    },
+    Tags,
+    Accounts,
+    CategoryTree,
+    CategoryExport(CategoryExportArgs),
+    Deposit(DepositArgs),
+    DiagDump,
+    LimitSet(LimitSetArgs),
+    MonthSet(u32),
+    Net(NetArgs),
+    PauseUser(PauseUserArgs),
+    Rates,
+    Convert(ConvertArgs),
+    Recent(RecentArgs),
+    Refresh,
+    ReportLink(ReportLinkArgs),
+    Remind(RemindArgs),
+    Reminders,
+    Retry,
+    Settle(SettleArgs),
+    Spend(SpendArgs),
+    Split(SplitArgs),
+    Subscribe(Frequency),
+    Top(TopArgs),
+    Transfer(TransferArgs),
+    UnpauseUser(String),
+    Unsubscribe,
+    WhoOwes,
+}
+
+/// An add command along with the time it was originally sent
+#[derive(Debug, Clone, PartialEq)]
+struct AddRequest {
+    args: AddArgs,
+    timestamp: SystemTime,
+    /// The account chosen from `source_accounts` to pay from, or `None` for
+    /// `firefly_source_account_id`
+    source_account_id: Option<i64>,
+}
+
+/// An action that is waiting for the user who triggered it to react to the bot's prompt
+#[derive(Debug, Clone, PartialEq)]
+enum Prompt {
+    /// Create the add's category, which does not exist yet. `event_id` is the add command's event.
+    CreateCategory {
+        request: AddRequest,
+        event_id: OwnedEventId,
+    },
+    /// Add to the category matching the number reaction. `event_id` is the add command's event.
+    ChooseCategory {
+        request: AddRequest,
+        event_id: OwnedEventId,
+        options: Vec<String>,
+    },
+    /// Add despite it looking like a duplicate. `event_id` is the add command's event.
+    ConfirmDuplicate {
+        request: AddRequest,
+        event_id: OwnedEventId,
+    },
+    /// Add to the account chosen from `options`, which are names in `source_accounts`. `event_id`
+    /// is the add command's event.
+    ChooseSource {
+        request: AddRequest,
+        event_id: OwnedEventId,
+        options: Vec<String>,
+    },
+    /// Add all of the split's shares. `event_id` is the split command's event.
+    ConfirmSplit {
+        args: SplitArgs,
+        payer: String,
+        timestamp: SystemTime,
+        event_id: OwnedEventId,
+    },
+}
+
+/// Holds prompts, keyed by the event id of the prompt message, until they're answered or expire
+struct PromptStore {
+    timeout: Duration,
+    entries: HashMap<OwnedEventId, (OwnedUserId, Prompt, Instant)>,
+}
+
+/// Holds each user's last failed add so that it can be retried
+struct RetryStore {
+    timeout: Duration,
+    entries: HashMap<OwnedUserId, (AddRequest, Instant)>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -62,15 +476,29 @@ struct Transaction {
     date: DateTime<Local>,
     amount: f64,
     description: String,
-    category_name: String,
-    source_id: i64,
-    destination_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination_name: Option<String>,
     tags: Vec<String>,
     notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreign_amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreign_currency_code: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Transactions {
+    /// Firefly requires a title for groups of more than one transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_title: Option<String>,
     transactions: Vec<Transaction>,
 }
 
@@ -83,6 +511,82 @@ struct Pagination {
     pub total_pages: i64,
 }
 
+#[derive(Deserialize)]
+struct Meta {
+    pagination: Pagination,
+}
+
+/// A single page of a paginated Firefly list response
+#[derive(Deserialize)]
+struct Page<T> {
+    data: Vec<T>,
+    meta: Meta,
+}
+
+/// A transaction as returned by Firefly, which is one split of a transaction group
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct TransactionSplit {
+    #[serde(rename = "type")]
+    transaction_type: String,
+    date: DateTime<Local>,
+    #[serde(deserialize_with = "deserialize_amount")]
+    amount: f64,
+    description: String,
+    category_name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    notes: Option<String>,
+    currency_symbol: Option<String>,
+    source_id: Option<String>,
+    destination_id: Option<String>,
+    #[serde(default)]
+    transaction_journal_id: Option<String>,
+}
+
+#[cfg(test)]
+impl TransactionSplit {
+    /// A transaction for tests, dated 2024-01-01, that has none of the optional fields set. Tests
+    /// set the fields they need with struct update syntax.
+    fn fixture(transaction_type: &str, amount: f64) -> Self {
+        Self {
+            transaction_type: transaction_type.to_string(),
+            date: DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00")
+                .unwrap()
+                .with_timezone(&Local),
+            amount,
+            description: "Test".to_string(),
+            category_name: None,
+            tags: Vec::new(),
+            notes: None,
+            currency_symbol: None,
+            source_id: None,
+            destination_id: None,
+            transaction_journal_id: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TransactionGroupAttributes {
+    transactions: Vec<TransactionSplit>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TransactionGroup {
+    attributes: TransactionGroupAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct StoredTransactionGroupData {
+    id: String,
+}
+
+/// Firefly's response to adding a transaction
+#[derive(Deserialize, Debug)]
+struct StoredTransactionGroup {
+    data: StoredTransactionGroupData,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct Attributes {
     name: String,
@@ -94,12 +598,134 @@ struct Category {
     attributes: Attributes,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ExchangeRateAttributes {
+    from_currency_code: String,
+    to_currency_code: String,
+    #[serde(deserialize_with = "deserialize_amount")]
+    rate: f64,
+    date: DateTime<Local>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ExchangeRate {
+    attributes: ExchangeRateAttributes,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct NewPiggyBank {
+    name: String,
+    account_id: String,
+    target_amount: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct NewCategory {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PiggyBank {
+    attributes: Attributes,
+}
+
+/// A Firefly response containing a single object
+#[derive(Deserialize)]
+struct Single<T> {
+    data: T,
+}
+
+/// The versions that Firefly reports at its `about` endpoint
+#[derive(Deserialize, Debug)]
+struct About {
+    version: String,
+    api_version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Account {
+    id: String,
+    attributes: Attributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct AccountBalanceAttributes {
+    name: String,
+    #[serde(deserialize_with = "deserialize_amount")]
+    current_balance: f64,
+    #[serde(default)]
+    current_balance_date: Option<DateTime<Local>>,
+    #[serde(default)]
+    currency_symbol: Option<String>,
+}
+
+/// An account along with its balance as of today
+#[derive(Deserialize, Debug)]
+struct AccountBalance {
+    id: String,
+    attributes: AccountBalanceAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct SingleAccountBalance {
+    data: AccountBalance,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TagAttributes {
+    tag: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
-struct ListCategories {
-    data: Vec<Category>,
+struct Tag {
+    id: String,
+    attributes: TagAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct BudgetSpent {
+    #[serde(deserialize_with = "deserialize_amount")]
+    sum: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct BudgetAttributes {
+    name: String,
+    /// What was spent from the budget in the requested period, per currency. Firefly records
+    /// spending as negative sums.
+    #[serde(default)]
+    spent: Vec<BudgetSpent>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Budget {
+    id: String,
+    attributes: BudgetAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct BudgetLimitAttributes {
+    #[serde(deserialize_with = "deserialize_amount")]
+    amount: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct BudgetLimit {
+    attributes: BudgetLimitAttributes,
+}
+
+/// A budget's spending against its limits for the current month
+#[derive(Debug, Clone, PartialEq)]
+struct BudgetStatus {
+    name: String,
+    spent: f64,
+    /// The total of the budget's limits in the month, if it has any
+    limit: Option<f64>,
 }
 
 impl Transaction {
+    /// Builds a withdrawal whose description is rendered from the `description_template` when the
+    /// person is attributed in the description, or is the category otherwise
     #[allow(clippy::too_many_arguments)]
     fn withdrawal(
         category: String,
@@ -108,20 +734,113 @@ impl Transaction {
         source_id: i64,
         destination_name: String,
         person: String,
+        attribution: PersonAttribution,
+        description_template: &str,
         notes: Option<String>,
         mut tags: Vec<String>,
     ) -> Self {
-        tags.push(person.clone());
+        let description = if attribution.in_description() {
+            render_description(description_template, &category, &person, notes.as_deref())
+        } else {
+            category.clone()
+        };
+        if attribution.in_tags() {
+            tags.push(person);
+        }
         Self {
             transaction_type: "withdrawal".to_string(),
             date,
             amount,
-            description: format!("{category} by {person}"),
-            category_name: category,
-            source_id,
-            destination_name,
+            description,
+            category_name: Some(category),
+            source_id: Some(source_id),
+            source_name: None,
+            destination_id: None,
+            destination_name: Some(destination_name),
+            notes,
+            tags,
+            foreign_amount: None,
+            foreign_currency_code: None,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn deposit(
+        category: String,
+        amount: f64,
+        date: DateTime<Local>,
+        source_name: String,
+        destination_id: i64,
+        person: String,
+        attribution: PersonAttribution,
+        notes: Option<String>,
+        mut tags: Vec<String>,
+    ) -> Self {
+        if attribution.in_tags() {
+            tags.push(person);
+        }
+        Self {
+            transaction_type: "deposit".to_string(),
+            date,
+            amount,
+            description: format!("{category} from {source_name}"),
+            category_name: Some(category),
+            source_id: None,
+            source_name: Some(source_name),
+            destination_id: Some(destination_id),
+            destination_name: None,
             notes,
             tags,
+            foreign_amount: None,
+            foreign_currency_code: None,
+        }
+    }
+}
+
+impl Transaction {
+    /// Records the amount as being in the given currency, which Firefly converts to the account's
+    /// currency. Without a currency, the amount is in the account's currency.
+    fn in_currency(mut self, currency: Option<&str>) -> Self {
+        if let Some(currency) = currency {
+            self.foreign_amount = Some(self.amount);
+            self.foreign_currency_code = Some(currency.to_string());
+        }
+        self
+    }
+
+    /// Moves money from one of the household's asset accounts to another
+    fn transfer(
+        amount: f64,
+        date: DateTime<Local>,
+        source_id: i64,
+        destination_id: i64,
+        description: String,
+        notes: Option<String>,
+    ) -> Self {
+        Self {
+            transaction_type: "transfer".to_string(),
+            date,
+            amount,
+            description,
+            category_name: None,
+            source_id: Some(source_id),
+            source_name: None,
+            destination_id: Some(destination_id),
+            destination_name: None,
+            notes,
+            tags: Vec::new(),
+            foreign_amount: None,
+            foreign_currency_code: None,
+        }
+    }
+}
+
+impl NewPiggyBank {
+    fn new(goal: &GoalArgs, account_id: i64) -> Self {
+        Self {
+            name: goal.name.clone(),
+            account_id: account_id.to_string(),
+            target_amount: format!("{:.2}", goal.target),
         }
     }
 }
@@ -129,424 +848,7653 @@ impl Transaction {
 impl Transactions {
     fn new(transaction: Transaction) -> Self {
         Self {
+            group_title: None,
             transactions: vec![transaction],
         }
     }
+
+    /// Groups the transactions so that they're created together in a single request
+    fn batch(group_title: String, transactions: Vec<Transaction>) -> Self {
+        Self {
+            group_title: (transactions.len() > 1).then_some(group_title),
+            transactions,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct Config {
-    matrix_homeserver_url: String,
-    matrix_username: String,
-    matrix_password: String,
-    matrix_room_id: String,
-    firefly_url: String,
-    firefly_api_key: String,
-    firefly_source_account_id: i64,
+/// A way of determining the name used to tag a person's transactions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum PersonSource {
+    /// The user's display name in the room
+    DisplayName,
+    /// The user's entry in `user_tags`
+    UserTag,
+    /// The localpart of the user's id
+    Localpart,
 }
 
-struct MatrixFireflyBot {
-    config: Config,
-    http_client: HttpClient,
+/// Where the person who made a transaction is recorded on it
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum PersonAttribution {
+    /// In both the description and the tags
+    #[default]
+    Both,
+    /// Appended to the description, eg: "Groceries by alice"
+    Description,
+    /// As a tag
+    Tags,
+    /// Not recorded
+    None,
 }
 
-impl MatrixFireflyBot {
-    fn new(config: Config) -> Self {
-        Self {
-            config,
-            http_client: reqwest::Client::new(),
+impl PersonAttribution {
+    fn in_description(&self) -> bool {
+        matches!(
+            self,
+            PersonAttribution::Both | PersonAttribution::Description
+        )
+    }
+
+    fn in_tags(&self) -> bool {
+        matches!(self, PersonAttribution::Both | PersonAttribution::Tags)
+    }
+
+    /// The same attribution in the description, but with or without the person's tag
+    fn with_tag(self, tag: bool) -> Self {
+        match (self.in_description(), tag) {
+            (true, true) => PersonAttribution::Both,
+            (true, false) => PersonAttribution::Description,
+            (false, true) => PersonAttribution::Tags,
+            (false, false) => PersonAttribution::None,
         }
     }
+}
 
-    async fn start(self) -> anyhow::Result<()> {
-        info!("Initializing...");
+/// Overrides that apply when the bot is used in a DM room
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct DmConfig {
+    /// Reply with a message instead of reacting to adds
+    #[serde(default)]
+    text_confirmations: bool,
+    /// Allow the DM's other member to send commands, even if they aren't in `allowed_users`
+    #[serde(default)]
+    authorize_all: bool,
+}
 
-        let home = dirs::data_dir().unwrap().join(CACHE_DIR);
+/// How to respond to a command from a user who is not in `allowed_users`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum UnauthorizedAction {
+    /// Silently ignore the command
+    Ignore,
+    /// React to the command with 🚫
+    #[default]
+    React,
+    /// Reply with `unauthorized_message`
+    Message,
+}
 
-        let client = MatrixClient::builder()
-            .homeserver_url(&self.config.matrix_homeserver_url)
-            .sled_store(home, None)?
-            .build()
-            .await?;
+/// How the bot confirms that an add or deposit was added, or reports that it failed
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ReplyMode {
+    /// React to the command with ✅ or ❌
+    #[default]
+    Reaction,
+    /// Reply with a message in a thread started from the command
+    Thread,
+    /// Reply to the command with a message
+    Reply,
+}
 
-        client
-            .login_username(&self.config.matrix_username, &self.config.matrix_password)
-            .initial_device_display_name(BOT_NAME)
-            .send()
-            .await?;
+/// A response to a command that is either a reaction to it or a message
+#[derive(Debug, PartialEq)]
+enum Response {
+    Reaction(String),
+    Message(String),
+}
 
-        let response = client.sync_once(SyncSettings::default()).await?;
+/// Why a command failed, which decides how the bot responds to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    /// The command couldn't be parsed, or named something that doesn't exist
+    Parse,
+    /// Firefly, or another service the command needed, failed
+    Upstream,
+}
 
-        let room_id = OwnedRoomId::try_from(self.config.matrix_room_id.as_str())?;
+/// How to handle an amount with more than two decimal places, eg: `3.14159`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum AmountRounding {
+    /// Reject the command, because the extra digits are likely a typo
+    #[default]
+    Reject,
+    /// Round the amount to two decimal places
+    Round,
+}
 
-        let self_arc = Arc::new(self);
-        client.add_room_event_handler(&room_id, {
-            let self_arc = Arc::clone(&self_arc);
-            move |event: OriginalSyncRoomMessageEvent, room: Room| {
-                let self_arc = Arc::clone(&self_arc);
-                async move {
-                    if let Err(e) = self_arc.on_room_message(event, room).await {
-                        error!("Failed to process message: {e}");
-                    }
-                }
-            }
-        });
+/// How the text after an add's amount is split into the note and tags
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteMode {
+    /// The note is the text before the first `#`, and each `#` starts a tag
+    #[default]
+    Standard,
+    /// The note is everything before the first `#`, verbatim
+    Strict,
+}
 
-        info!("Listening for messages...");
+/// Settings for one of the rooms the bot listens to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct RoomConfig {
+    room_id: String,
+    /// The account to use instead of `firefly_source_account_id` for adds in the room
+    #[serde(default)]
+    source_account_id: Option<i64>,
+}
 
-        let settings = SyncSettings::default().token(response.next_batch);
-        client.sync(settings).await?;
+/// The credentials the bot logs in to Matrix with
+#[derive(Debug, PartialEq)]
+enum MatrixLogin<'a> {
+    Password(&'a str),
+    /// Restores the session of a device that was already logged in
+    AccessToken {
+        access_token: &'a str,
+        device_id: &'a str,
+    },
+}
 
-        Ok(())
-    }
+/// How to handle an add to a category that does not exist in Firefly
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum CategoryCreation {
+    /// Send the add as is, and let Firefly create the category
+    #[default]
+    Implicit,
+    /// Ask the user to confirm the new category with a reaction before adding
+    Confirm,
+    /// Reject the add, and point the user to the existing categories
+    Reject,
+}
 
-    async fn on_room_message(
-        &self,
-        event: OriginalSyncRoomMessageEvent,
-        room: Room,
-    ) -> anyhow::Result<()> {
-        debug!("Received event: {event:?}");
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    matrix_homeserver_url: String,
+    matrix_username: String,
+    #[serde(default)]
+    matrix_password: Option<String>,
+    #[serde(default)]
+    matrix_access_token: Option<String>,
+    #[serde(default)]
+    matrix_device_id: Option<String>,
+    #[serde(default)]
+    matrix_room_id: Option<String>,
+    #[serde(default)]
+    matrix_room_ids: Vec<String>,
+    #[serde(default)]
+    rooms: Vec<RoomConfig>,
+    /// The account to use for each user's adds, by Matrix user id, which takes precedence over
+    /// the room's account
+    #[serde(default)]
+    user_accounts: HashMap<String, i64>,
+    /// Whether to accept invites to the configured rooms
+    #[serde(default)]
+    auto_join: bool,
+    /// Whether to send a link to each added transaction in Firefly
+    #[serde(default)]
+    link_transactions: bool,
+    /// Only accept invites from users in `allowed_users`, when it isn't empty
+    #[serde(default)]
+    auto_join_allowed_users_only: bool,
+    firefly_url: String,
+    firefly_api_key: String,
+    #[serde(default = "default_firefly_api_version")]
+    firefly_api_version: String,
+    firefly_source_account_id: i64,
+    #[serde(default = "default_command_prefix")]
+    command_prefix: String,
+    #[serde(default = "default_retry_timeout_secs")]
+    retry_timeout_secs: u64,
+    #[serde(default = "default_firefly_max_retries")]
+    firefly_max_retries: u32,
+    #[serde(default = "default_delete_retention_days")]
+    delete_retention_days: u32,
+    #[serde(default)]
+    category_creation: CategoryCreation,
+    #[serde(default = "default_prompt_timeout_secs")]
+    prompt_timeout_secs: u64,
+    #[serde(default)]
+    user_tags: HashMap<String, String>,
+    #[serde(default = "default_person_sources")]
+    person_sources: Vec<PersonSource>,
+    #[serde(default = "default_display_name_timeout_ms")]
+    display_name_timeout_ms: u64,
+    #[serde(default = "default_category_separator")]
+    category_separator: String,
+    #[serde(default)]
+    split_feedback: SplitFeedback,
+    #[serde(default)]
+    split_remainder: SplitRemainder,
+    #[serde(default)]
+    split_atomic: bool,
+    #[serde(default)]
+    greet_new_members: GreetNewMembers,
+    #[serde(default)]
+    confirm_bulk_transactions: usize,
+    #[serde(default)]
+    confirm_bulk_total: f64,
+    #[serde(default)]
+    default_notes: Vec<String>,
+    #[serde(default)]
+    default_note_selection: NoteSelection,
+    #[serde(default = "default_mirror_refresh_secs")]
+    mirror_refresh_secs: u64,
+    #[serde(default = "default_category_cache_ttl_secs")]
+    category_cache_ttl_secs: u64,
+    #[serde(default = "default_persist_mirror")]
+    persist_mirror: bool,
+    #[serde(default)]
+    allow_zero_amounts: bool,
+    #[serde(default = "default_max_output_lines")]
+    max_output_lines: usize,
+    #[serde(default)]
+    required_tags: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    allowed_users: Vec<String>,
+    #[serde(default)]
+    admin_users: Vec<String>,
+    #[serde(default)]
+    dm: DmConfig,
+    #[serde(default)]
+    unauthorized_action: UnauthorizedAction,
+    #[serde(default = "default_unauthorized_message")]
+    unauthorized_message: String,
+    #[serde(default = "default_budget_cycle_start_day")]
+    budget_cycle_start_day: u32,
+    #[serde(default)]
+    spending_limits: HashMap<String, f64>,
+    #[serde(default)]
+    category_picker: bool,
+    #[serde(default)]
+    auto_create_categories: bool,
+    #[serde(default = "default_revenue_account")]
+    default_revenue_account: String,
+    #[serde(default)]
+    income_keywords: Vec<String>,
+    #[serde(default)]
+    default_income_category: Option<String>,
+    #[serde(default)]
+    batch_transactions: bool,
+    #[serde(default)]
+    note_mode: NoteMode,
+    #[serde(default)]
+    decimal_comma: bool,
+    #[serde(default)]
+    amount_rounding: AmountRounding,
+    #[serde(default)]
+    settle_accounts: HashMap<String, i64>,
+    #[serde(default)]
+    source_accounts: BTreeMap<String, i64>,
+    #[serde(default)]
+    prompt_for_source: bool,
+    #[serde(default)]
+    auto_redact_commands: bool,
+    #[serde(default)]
+    reply_mode: ReplyMode,
+    #[serde(default)]
+    cache_dir_fallback: bool,
+    #[serde(default)]
+    person_attribution: PersonAttribution,
+    /// Overrides whether `person_attribution` tags transactions with the person
+    #[serde(default)]
+    tag_with_person: Option<bool>,
+    #[serde(default)]
+    warn_duplicates: bool,
+    #[serde(default)]
+    warn_new_tags: bool,
+    #[serde(default = "default_duplicate_window_secs")]
+    duplicate_window_secs: u64,
+    #[serde(default)]
+    result_cache_secs: u64,
+    #[serde(default)]
+    rates_api_url: Option<String>,
+    #[serde(default = "default_parse_error_messages")]
+    parse_error_messages: bool,
+    #[serde(default)]
+    metrics_port: Option<u16>,
+    #[serde(default)]
+    health_port: Option<u16>,
+    #[serde(default = "default_description_template")]
+    description_template: String,
+}
 
-        if let Room::Joined(room) = room {
-            let MessageType::Text(message) = event.content.msgtype else {
-                return Ok(());
-            };
+fn default_firefly_api_version() -> String {
+    "v1".to_string()
+}
 
-            let content = message.body;
+fn default_command_prefix() -> String {
+    DEFAULT_COMMAND_PREFIX.to_string()
+}
 
-            if !content.starts_with('!') {
-                return Ok(());
-            }
+fn default_retry_timeout_secs() -> u64 {
+    3600
+}
 
-            let username = event.sender.localpart();
-            let timestamp = event
-                .origin_server_ts
-                .to_system_time()
-                .ok_or_else(|| anyhow!("Failed to extract message timestamp"))?;
+fn default_firefly_max_retries() -> u32 {
+    3
+}
 
-            let cmd = match Cmd::parse(&content) {
-                Ok(cmd) => cmd,
+fn default_delete_retention_days() -> u32 {
+    30
+}
+
+fn default_prompt_timeout_secs() -> u64 {
+    300
+}
+
+fn default_person_sources() -> Vec<PersonSource> {
+    vec![PersonSource::UserTag, PersonSource::Localpart]
+}
+
+fn default_display_name_timeout_ms() -> u64 {
+    500
+}
+
+fn default_category_separator() -> String {
+    ":".to_string()
+}
+
+fn default_mirror_refresh_secs() -> u64 {
+    900
+}
+
+fn default_category_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_persist_mirror() -> bool {
+    true
+}
+
+fn default_max_output_lines() -> usize {
+    15
+}
+
+fn default_unauthorized_message() -> String {
+    "You are not allowed to use this bot".to_string()
+}
+
+fn default_revenue_account() -> String {
+    "General revenue".to_string()
+}
+
+fn default_budget_cycle_start_day() -> u32 {
+    1
+}
+
+fn default_description_template() -> String {
+    DEFAULT_DESCRIPTION_TEMPLATE.to_string()
+}
+
+fn default_parse_error_messages() -> bool {
+    true
+}
+
+fn default_duplicate_window_secs() -> u64 {
+    600
+}
+
+impl Config {
+    /// Builds the URL to a Firefly API endpoint, eg: `transactions`, under the configured API
+    /// version
+    fn api_url(&self, endpoint: &str) -> String {
+        format!(
+            "{}/api/{}/{endpoint}",
+            self.firefly_url.trim_end_matches('/'),
+            self.firefly_api_version
+        )
+    }
+
+    /// Where the person is recorded on transactions, after applying `tag_with_person`
+    fn attribution(&self) -> PersonAttribution {
+        match self.tag_with_person {
+            Some(tag) => self.person_attribution.with_tag(tag),
+            None => self.person_attribution,
+        }
+    }
+
+    /// Checks the config for problems that would otherwise only surface once the bot is running.
+    /// Returns a description of each problem, naming the field, or nothing if it's valid.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (field, url) in [
+            ("matrix_homeserver_url", Some(&self.matrix_homeserver_url)),
+            ("firefly_url", Some(&self.firefly_url)),
+            ("rates_api_url", self.rates_api_url.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(field, url)| Some((field, url?)))
+        {
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+                Ok(_) => problems.push(format!(
+                    "{field} must be an http or https URL, but was '{url}'"
+                )),
+                Err(e) => problems.push(format!("{field} is not a valid URL, '{url}': {e}")),
+            }
+        }
+
+        if self.firefly_api_key.trim().is_empty() {
+            problems.push("firefly_api_key must not be empty".to_string());
+        }
+
+        if let Err(e) = matrix_login(self) {
+            problems.push(e.to_string());
+        }
+
+        let room_fields = non_empty(&self.matrix_room_id)
+            .map(|room_id| ("matrix_room_id", room_id))
+            .into_iter()
+            .chain(
+                self.matrix_room_ids
+                    .iter()
+                    .map(|room_id| ("matrix_room_ids", room_id.as_str())),
+            )
+            .chain(
+                self.rooms
+                    .iter()
+                    .map(|room| ("rooms.room_id", room.room_id.as_str())),
+            );
+        let mut invalid_room = false;
+        for (field, room_id) in room_fields {
+            if let Err(e) = OwnedRoomId::try_from(room_id) {
+                invalid_room = true;
+                problems.push(format!("{field} is not a valid room id, '{room_id}': {e}"));
+            }
+        }
+        if !invalid_room {
+            if let Err(e) = room_ids(self) {
+                problems.push(e.to_string());
+            }
+        }
+
+        let mut users: Vec<&String> = self.user_accounts.keys().collect();
+        users.sort();
+        for user_id in users {
+            if let Err(e) = OwnedUserId::try_from(user_id.as_str()) {
+                problems.push(format!(
+                    "user_accounts has an invalid user id, '{user_id}': {e}"
+                ));
+            }
+        }
+
+        if self.command_prefix.is_empty() || self.command_prefix.contains(char::is_whitespace) {
+            problems.push(format!(
+                "command_prefix must not be empty or contain whitespace, but was '{}'",
+                self.command_prefix
+            ));
+        }
+        if !is_valid_start_day(self.budget_cycle_start_day) {
+            problems.push(format!(
+                "budget_cycle_start_day must be between 1 and 31, but was {}",
+                self.budget_cycle_start_day
+            ));
+        }
+        if self.mirror_refresh_secs == 0 {
+            problems.push("mirror_refresh_secs must be greater than 0".to_string());
+        }
+        if self.category_separator.is_empty() {
+            problems.push("category_separator must not be empty".to_string());
+        }
+
+        problems
+    }
+
+    /// Returns the account that the user's adds in the room are paid from and deposited to, which
+    /// is the user's account in `user_accounts`, the room's `source_account_id` in `rooms`, or,
+    /// failing both, `firefly_source_account_id`
+    fn source_account_id(&self, room_id: &RoomId, user_id: &UserId) -> i64 {
+        if let Some(account_id) = self.user_accounts.get(user_id.as_str()) {
+            return *account_id;
+        }
+        self.rooms
+            .iter()
+            .find(|room| room.room_id == room_id.as_str())
+            .and_then(|room| room.source_account_id)
+            .unwrap_or(self.firefly_source_account_id)
+    }
+}
+
+struct MatrixFireflyBot {
+    config: Config,
+    http_client: HttpClient,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    failed_adds: Mutex<RetryStore>,
+    recent_adds: Mutex<RecentAdds>,
+    commands: Mutex<CommandHistory>,
+    default_notes: DefaultNotes,
+    results: Mutex<ResultCache>,
+    prompts: Mutex<PromptStore>,
+    pagers: Mutex<PagerStore>,
+    mirror: Mirror,
+    subscriptions: SubscriptionStore,
+    settings: SettingsStore,
+    ledger: Ledger,
+    limits: LimitStore,
+    reminders: ReminderStore,
+    greeted: GreetedStore,
+    pauses: PauseStore,
+    rates_cache: Mutex<Option<(Vec<ExchangeRate>, Instant)>>,
+    /// The rates from the `rates_api_url` for each base currency, and when they were fetched
+    conversion_rates: Mutex<HashMap<String, (ConversionRates, Instant)>>,
+    /// When the mirror's categories were last fetched from Firefly in this run
+    categories_fetched_at: Mutex<Option<Instant>>,
+    added: AddedStore,
+    /// The bot's own store, which holds the trees of the stores above
+    store: sled::Db,
+    cache_dir: PathBuf,
+    /// The bot's own user id, which is known once it has logged in
+    user_id: Option<OwnedUserId>,
+    /// The rooms the bot listens to, which are known once it has started
+    room_ids: Vec<OwnedRoomId>,
+}
+
+impl MatrixFireflyBot {
+    fn new(config: Config) -> anyhow::Result<Self> {
+        let retry_timeout = Duration::from_secs(config.retry_timeout_secs);
+        let prompt_timeout = Duration::from_secs(config.prompt_timeout_secs);
+        let duplicate_window = Duration::from_secs(config.duplicate_window_secs);
+        let result_cache_ttl = Duration::from_secs(config.result_cache_secs);
+        let default_notes =
+            DefaultNotes::new(config.default_notes.clone(), config.default_note_selection);
+
+        let cache_dir = resolve_cache_dir(&config)?;
+        let store = sled::open(cache_dir.join(BOT_STORE_DIR))?;
+        let mirror = Mirror::open(&store, config.persist_mirror)?;
+        let delete_retention_days = config.delete_retention_days as i64;
+        let http_client = reqwest::Client::new();
+        let health = Health::new(
+            http_client.clone(),
+            config.api_url(FIREFLY_ABOUT_API),
+            config.firefly_api_key.clone(),
+        );
+        Ok(Self {
+            config,
+            http_client,
+            metrics: Arc::new(Metrics::default()),
+            health: Arc::new(health),
+            failed_adds: Mutex::new(RetryStore::new(retry_timeout)),
+            recent_adds: Mutex::new(RecentAdds::new(duplicate_window)),
+            commands: Mutex::new(CommandHistory::default()),
+            default_notes,
+            results: Mutex::new(ResultCache::new(result_cache_ttl)),
+            prompts: Mutex::new(PromptStore::new(prompt_timeout)),
+            pagers: Mutex::new(PagerStore::new(prompt_timeout)),
+            mirror,
+            subscriptions: SubscriptionStore::open(&store)?,
+            settings: SettingsStore::open(&store)?,
+            ledger: Ledger::open(&store)?,
+            limits: LimitStore::open(&store)?,
+            reminders: ReminderStore::open(&store)?,
+            greeted: GreetedStore::open(&store)?,
+            pauses: PauseStore::open(&store)?,
+            rates_cache: Mutex::new(None),
+            conversion_rates: Mutex::new(HashMap::new()),
+            categories_fetched_at: Mutex::new(None),
+            added: AddedStore::open(&store, ChronoDuration::days(delete_retention_days))?,
+            store,
+            cache_dir,
+            user_id: None,
+            room_ids: Vec::new(),
+        })
+    }
+
+    async fn start(mut self) -> anyhow::Result<()> {
+        info!("Initializing...");
+
+        // Started first so that probes get a 503 rather than no answer while the bot syncs
+        let health_server = self.config.health_port.map(|port| {
+            let (stop, stopped) = oneshot::channel::<()>();
+            let server = tokio::spawn({
+                let health = Arc::clone(&self.health);
+                async move {
+                    let shutdown = async {
+                        stopped.await.ok();
+                    };
+                    if let Err(e) = health::serve(health, port, shutdown).await {
+                        error!("Failed to serve health checks on port {port}: {e}");
+                    }
+                }
+            });
+            (stop, server)
+        });
+
+        // A wrong URL or key would otherwise only surface on the first command
+        self.check_firefly().await?;
+
+        let client = MatrixClient::builder()
+            .homeserver_url(&self.config.matrix_homeserver_url)
+            .sled_store(&self.cache_dir, None)?
+            .build()
+            .await?;
+
+        match matrix_login(&self.config)? {
+            MatrixLogin::AccessToken {
+                access_token,
+                device_id,
+            } => {
+                info!("Logging in with an access token");
+                client
+                    .restore_login(Session {
+                        access_token: access_token.to_string(),
+                        refresh_token: None,
+                        user_id: UserId::parse(&self.config.matrix_username)?,
+                        device_id: device_id.into(),
+                    })
+                    .await?;
+            }
+            MatrixLogin::Password(password) => {
+                client
+                    .login_username(&self.config.matrix_username, password)
+                    .initial_device_display_name(BOT_NAME)
+                    .send()
+                    .await?;
+            }
+        }
+
+        let response = client.sync_once(SyncSettings::default()).await?;
+
+        self.user_id = client.user_id().map(ToOwned::to_owned);
+        let room_ids = room_ids(&self.config)?;
+        self.room_ids = room_ids.clone();
+
+        // The handlers are registered either way, but only hear rooms the bot has joined
+        for room_id in &room_ids {
+            match client.get_room(room_id) {
+                Some(Room::Invited(room)) if self.config.auto_join => {
+                    if let Err(e) = accept_invite(&room).await {
+                        error!("Failed to join {room_id}: {e}");
+                    }
+                }
+                room => {
+                    let room_type = room.map(|room| room.room_type());
+                    if let Some(problem) = membership_problem(room_id, room_type) {
+                        error!("{problem}");
+                    }
+                }
+            }
+        }
+
+        let self_arc = Arc::new(self);
+        for room_id in &room_ids {
+            info!("Listening to room {room_id}");
+            client.add_room_event_handler(room_id, {
+                let self_arc = Arc::clone(&self_arc);
+                move |event: OriginalSyncRoomMessageEvent, room: Room| {
+                    let self_arc = Arc::clone(&self_arc);
+                    async move {
+                        if let Err(e) = self_arc.on_room_message(event, room).await {
+                            error!("Failed to process message: {e}");
+                        }
+                    }
+                }
+            });
+            client.add_room_event_handler(room_id, {
+                let self_arc = Arc::clone(&self_arc);
+                move |event: OriginalSyncReactionEvent, room: Room| {
+                    let self_arc = Arc::clone(&self_arc);
+                    async move {
+                        if let Err(e) = self_arc.on_reaction(event, room).await {
+                            error!("Failed to process reaction: {e}");
+                        }
+                    }
+                }
+            });
+            client.add_room_event_handler(room_id, {
+                let self_arc = Arc::clone(&self_arc);
+                move |event: OriginalSyncRoomMemberEvent, room: Room| {
+                    let self_arc = Arc::clone(&self_arc);
+                    async move {
+                        if let Err(e) = self_arc.on_member(event, room).await {
+                            error!("Failed to process membership change: {e}");
+                        }
+                    }
+                }
+            });
+        }
+
+        if self_arc.config.auto_join {
+            client.add_event_handler({
+                let self_arc = Arc::clone(&self_arc);
+                move |event: StrippedRoomMemberEvent, room: Room| {
+                    let self_arc = Arc::clone(&self_arc);
+                    async move {
+                        if let Err(e) = self_arc.on_invite(event, room).await {
+                            error!("Failed to process invite: {e}");
+                        }
+                    }
+                }
+            });
+        }
+
+        tokio::spawn({
+            let self_arc = Arc::clone(&self_arc);
+            async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(self_arc.config.mirror_refresh_secs));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = self_arc.refresh_mirror().await {
+                        warn!("Failed to refresh mirror: {e}");
+                    }
+                }
+            }
+        });
+
+        tokio::spawn({
+            let self_arc = Arc::clone(&self_arc);
+            let client = client.clone();
+            async move {
+                let mut interval = tokio::time::interval(SUBSCRIPTION_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    self_arc.send_due_summaries(&client).await;
+                }
+            }
+        });
+
+        tokio::spawn({
+            let self_arc = Arc::clone(&self_arc);
+            let client = client.clone();
+            async move {
+                let mut interval = tokio::time::interval(REMINDER_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    self_arc.send_due_reminders(&client).await;
+                }
+            }
+        });
+
+        if let Some(port) = self_arc.config.metrics_port {
+            tokio::spawn({
+                let metrics = Arc::clone(&self_arc.metrics);
+                async move {
+                    if let Err(e) = metrics::serve(metrics, port).await {
+                        error!("Failed to serve metrics on port {port}: {e}");
+                    }
+                }
+            });
+        }
+
+        info!("Listening for messages...");
+        self_arc.health.set_listening(true);
+
+        let settings = SyncSettings::default().token(response.next_batch);
+        let result = tokio::select! {
+            result = client.sync(settings) => result.map_err(anyhow::Error::from),
+            signal = shutdown_signal() => signal.map(|signal| info!("Received {signal}")),
+        };
+
+        info!("Shutting down");
+        self_arc.health.set_listening(false);
+        if let Some((stop, server)) = health_server {
+            stop.send(()).ok();
+            if let Err(e) = server.await {
+                warn!("Failed to stop the health check server: {e}");
+            }
+        }
+        result?;
+        self_arc.store.flush_async().await?;
+
+        Ok(())
+    }
+
+    /// Accepts invites of the bot to the rooms it listens to, when `auto_join` is set
+    async fn on_invite(&self, event: StrippedRoomMemberEvent, room: Room) -> anyhow::Result<()> {
+        let Room::Invited(room) = room else {
+            return Ok(());
+        };
+        if event.content.membership != MembershipState::Invite
+            || self.user_id.as_ref() != Some(&event.state_key)
+        {
+            return Ok(());
+        }
+
+        if should_accept_invite(&self.config, &self.room_ids, room.room_id(), &event.sender) {
+            accept_invite(&room).await
+        } else {
+            info!(
+                "Ignoring the invite to {} from {}",
+                room.room_id(),
+                event.sender
+            );
+            Ok(())
+        }
+    }
+
+    async fn on_room_message(
+        &self,
+        event: OriginalSyncRoomMessageEvent,
+        room: Room,
+    ) -> anyhow::Result<()> {
+        debug!("Received event: {event:?}");
+
+        // The bot never responds to itself, so that its own messages can't start a loop
+        if self.user_id.as_ref() == Some(&event.sender) {
+            return Ok(());
+        }
+
+        if let Room::Joined(room) = room {
+            let reply_to = match &event.content.relates_to {
+                Some(MessageRelation::Reply { in_reply_to }) => Some(in_reply_to.event_id.clone()),
+                _ => None,
+            };
+            let MessageType::Text(message) = event.content.msgtype else {
+                return Ok(());
+            };
+
+            let content = message_text(&message);
+            let content = content.trim_start();
+
+            // Commands are parsed with the default prefix, whatever the configured one is
+            let Some(content) = content
+                .strip_prefix(&self.config.command_prefix)
+                .map(|command| format!("{DEFAULT_COMMAND_PREFIX}{command}"))
+            else {
+                return Ok(());
+            };
+
+            if !self.is_authorized(&event.sender, &room) {
+                warn!("Rejecting command from unauthorized user {}", event.sender);
+                match unauthorized_response(&self.config) {
+                    Some(Response::Reaction(reaction)) => {
+                        send_reaction(reaction, event.event_id.clone(), &room).await?;
+                    }
+                    Some(Response::Message(message)) => send_message(message, &room).await?,
+                    None => {}
+                }
+                return Ok(());
+            }
+
+            let timestamp = event
+                .origin_server_ts
+                .to_system_time()
+                .ok_or_else(|| anyhow!("Failed to extract message timestamp"))?;
+
+            let repeated = content.trim() == AGAIN_CMD;
+            let resolved = self
+                .commands
+                .lock()
+                .unwrap()
+                .resolve(&event.sender, &content);
+            let Some(content) = resolved else {
+                send_message("You have no command to repeat".to_string(), &room).await?;
+                return Ok(());
+            };
+
+            let cmd = match Cmd::parse(&content, &self.config).and_then(|cmd| {
+                cmd.validate(&self.config)?;
+                Ok(cmd)
+            }) {
+                Ok(cmd) => cmd,
                 Err(e) => {
                     warn!("Failed to parse: '{content}'. {e}");
-                    send_message(e.to_string(), &room).await?;
+                    self.respond_error(
+                        ErrorKind::Parse,
+                        Some(self.with_prefix(&e.to_string())),
+                        event.event_id.clone(),
+                        &room,
+                    )
+                    .await?;
                     return Ok(());
                 }
             };
 
-            info!("Received command: {cmd:?}");
+            info!("Received command: {cmd:?}");
+            if let Some(command) = content.split_whitespace().next() {
+                self.metrics
+                    .record_command(&command[DEFAULT_COMMAND_PREFIX.len()..].to_lowercase());
+            }
+
+            // A date given with @ replaces the message's timestamp
+            let timestamp = match &cmd {
+                Cmd::Add(args)
+                | Cmd::Split(SplitArgs { add: args, .. })
+                | Cmd::Deposit(DepositArgs { add: args, .. }) => {
+                    args.date.map(SystemTime::from).unwrap_or(timestamp)
+                }
+                _ => timestamp,
+            };
+
+            // A repeated delete wouldn't be a reply, so it's not recorded
+            if !repeated && !matches!(cmd, Cmd::CommandHistory | Cmd::Delete) {
+                self.commands
+                    .lock()
+                    .unwrap()
+                    .record(&event.sender, &content);
+            }
+
+            // Typing while the command runs shows that it was received before the reply lands
+            set_typing(&room, true).await;
+            let result = async {
+                match cmd {
+                    Cmd::Ping => send_message("pong".to_string(), &room).await?,
+                    Cmd::Help(command) => {
+                        let help = command.and_then(command_help).unwrap_or_else(help_text);
+                        send_message(self.with_prefix(&help), &room).await?
+                    }
+                    Cmd::HelpExamples => {
+                        room.send(
+                            RoomMessageEventContent::text_markdown(
+                                self.with_prefix(&format_examples()),
+                            ),
+                            None,
+                        )
+                        .await?;
+                    }
+                    Cmd::Balance => {
+                        let account_id = self.config.source_account_id(room.room_id(), &event.sender);
+                        match self
+                            .cached_reply(
+                                format!("{BALANCE_CMD} {account_id}"),
+                                Dependency::Transactions,
+                                async {
+                                    let account = self.get_account_balance(account_id).await?;
+                                    Ok(format_balance(
+                                        &account.attributes.name,
+                                        account.attributes.current_balance,
+                                        account.attributes.currency_symbol.as_deref(),
+                                        account
+                                            .attributes
+                                            .current_balance_date
+                                            .map(|date| date.date_naive()),
+                                    ))
+                                },
+                            )
+                            .await
+                        {
+                            Ok(response) => send_message(response, &room).await?,
+                            Err(e) => {
+                                error!("Failed to get account balance: {e}");
+                                self.respond_error(
+                                    ErrorKind::Upstream,
+                                    Some(failure_message(&e, "Failed to get account balance")),
+                                    event.event_id.clone(),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Cmd::Budget => self.handle_budget(event.event_id.clone(), &room).await?,
+                    Cmd::Categories { refresh, search } => {
+                        if refresh {
+                            self.categories_fetched_at.lock().unwrap().take();
+                            self.results
+                                .lock()
+                                .unwrap()
+                                .invalidate(Dependency::Categories);
+                        }
+                        match self
+                            .cached_reply(
+                                match &search {
+                                    Some(search) => {
+                                        format!("{CATEGORIES_CMD} {}", search.to_lowercase())
+                                    }
+                                    None => CATEGORIES_CMD.to_string(),
+                                },
+                                Dependency::Categories,
+                                async {
+                                    Ok(format_categories(
+                                        &self.categories().await?,
+                                        search.as_deref(),
+                                    ))
+                                },
+                            )
+                            .await
+                        {
+                            Ok(response) => {
+                                self.send_output(response, "categories.txt", &room).await?;
+                            }
+                            Err(e) => {
+                                error!("Failed to list categories: {}", e);
+                                self.respond_error(
+                                    ErrorKind::Upstream,
+                                    Some(failure_message(&e, "Failed to list categories")),
+                                    event.event_id.clone(),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Cmd::Tags => match self
+                        .cached_reply(TAGS_CMD.to_string(), Dependency::Transactions, async {
+                            Ok(format_tags(self.tags().await?))
+                        })
+                        .await
+                    {
+                        Ok(response) => {
+                            self.send_output(response, "tags.txt", &room).await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to list tags: {e}");
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to list tags")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::Accounts => match self
+                        .cached_reply(ACCOUNTS_CMD.to_string(), Dependency::Transactions, async {
+                            Ok(format_accounts(self.list_accounts().await?))
+                        })
+                        .await
+                    {
+                        Ok(response) => {
+                            self.send_output(response, "accounts.txt", &room).await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to list accounts: {e}");
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to list accounts")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::CategoryTree => match self
+                        .cached_reply(
+                            CATEGORY_TREE_CMD.to_string(),
+                            Dependency::Categories,
+                            async {
+                                let categories = self.categories().await?;
+                                let mut response = String::new();
+                                response.push_str("Categories:");
+                                render_category_tree(
+                                    &build_category_tree(&categories, &self.config.category_separator),
+                                    1,
+                                    &mut response,
+                                );
+                                Ok(response)
+                            },
+                        )
+                        .await
+                    {
+                        Ok(response) => {
+                            self.send_output(response, "categories.txt", &room).await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to list categories: {}", e);
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to list categories")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::BalanceHistory(args) => {
+                        self.handle_balance_history(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::CategoryExport(args) => {
+                        self.handle_category_export(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::Add(args) if args.dry_run => {
+                        self.handle_dry_run(args, &event.sender, timestamp, &room)
+                            .await?
+                    }
+                    Cmd::Add(args) => {
+                        let request = AddRequest {
+                            args,
+                            timestamp,
+                            source_account_id: None,
+                        };
+                        let duplicate = if self.config.warn_duplicates {
+                            self.recent_adds
+                                .lock()
+                                .unwrap()
+                                .find_duplicate(
+                                    &event.sender,
+                                    &request.args.category,
+                                    request.args.amount,
+                                    timestamp,
+                                )
+                                .cloned()
+                        } else {
+                            None
+                        };
+
+                        match duplicate {
+                            Some(duplicate) => {
+                                let prompt_id = room
+                                    .send(
+                                        RoomMessageEventContent::text_plain(format!(
+                                            "Looks like a duplicate of {} to {} at {}. React {CONFIRM_REACTION} to add anyway",
+                                            format_amount(duplicate.amount, None),
+                                            duplicate.category,
+                                            DateTime::<Local>::from(duplicate.added_at).format("%H:%M")
+                                        )),
+                                        None,
+                                    )
+                                    .await?
+                                    .event_id;
+                                self.prompts.lock().unwrap().insert(
+                                    prompt_id,
+                                    event.sender.clone(),
+                                    Prompt::ConfirmDuplicate {
+                                        request,
+                                        event_id: event.event_id.clone(),
+                                    },
+                                );
+                            }
+                            None => {
+                                self.start_add(request, &event.sender, event.event_id.clone(), &room)
+                                    .await?;
+                            }
+                        }
+                    }
+                    Cmd::Delete => {
+                        self.handle_delete(reply_to, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::Edit(args) => {
+                        self.handle_edit(args, &event.sender, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::Deposit(args) => {
+                        let person = self.resolve_person_tag(&event.sender, &room).await;
+                        let (amount, category) = (args.add.amount, args.add.category.clone());
+                        let result = self
+                            .add_deposit(
+                                args,
+                                self.config.source_account_id(room.room_id(), &event.sender),
+                                &person,
+                                timestamp,
+                            )
+                            .await;
+                        if let Err(e) = &result {
+                            error!("Failed to add deposit: {e}");
+                        }
+
+                        if self.config.reply_mode == ReplyMode::Reaction {
+                            let reaction = if result.is_ok() { "✅" } else { FAILED_REACTION };
+                            send_reaction(reaction.to_owned(), event.event_id.clone(), &room)
+                                .await?;
+                            if let Ok(id) = result {
+                                let mut event_ids = vec![event.event_id.clone()];
+                                if let Some(link) = self.transaction_link(id.as_deref()) {
+                                    event_ids.push(send_text(link, &room).await?);
+                                }
+                                self.record_added(event_ids, id, &event.sender, &room);
+                            }
+                        } else {
+                            let amount = format_amount(amount, None);
+                            let text = match &result {
+                                Ok(id) => {
+                                    let mut text =
+                                        format!("Added {amount} to {category} for {person}");
+                                    if let Some(link) = self.transaction_link(id.as_deref()) {
+                                        text.push_str(&format!("\n{link}"));
+                                    }
+                                    text
+                                }
+                                Err(_) => format!("Failed to add {amount} to {category}"),
+                            };
+                            let confirmation_id = self
+                                .send_confirmation(text, &event.event_id, false, &room)
+                                .await?;
+                            if let Ok(id) = result {
+                                self.record_added(
+                                    [event.event_id.clone(), confirmation_id],
+                                    id,
+                                    &event.sender,
+                                    &room,
+                                );
+                            }
+                        }
+                    }
+                    Cmd::AddGoal(goal) => match self.add_goal(&goal).await {
+                        Ok(Some(name)) => {
+                            send_message(
+                                format!(
+                                    "Created goal {name} with a target of {}",
+                                    format_amount(goal.target, None)
+                                ),
+                                &room,
+                            )
+                            .await?;
+                        }
+                        Ok(None) => {
+                            send_message(format!("A goal named {} already exists", goal.name), &room)
+                                .await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to create goal: {e}");
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to create goal")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::Top(TopArgs { count, days }) => {
+                        let person = self.resolve_person_tag(&event.sender, &room).await;
+                        let end = Local::now().date_naive();
+                        let start = report_start(end, days);
+                        let reply = self
+                            .cached_reply(
+                                format!("{TOP_CMD} {person} {count} {days}"),
+                                Dependency::Transactions,
+                                async {
+                                    let expenses = self.list_expenses(&person, start, end).await?;
+                                    Ok(format_top(top_expenses(expenses, count), days))
+                                },
+                            )
+                            .await;
+                        match reply {
+                            Ok(response) => {
+                                self.send_output(response, "top.txt", &room).await?;
+                            }
+                            Err(e) => {
+                                error!("Failed to list expenses: {e}");
+                                self.respond_error(
+                                    ErrorKind::Upstream,
+                                    Some(failure_message(&e, "Failed to list expenses")),
+                                    event.event_id.clone(),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Cmd::Net(NetArgs { days }) => {
+                        let person = self.resolve_person_tag(&event.sender, &room).await;
+                        let end = Local::now().date_naive();
+                        let start = report_start(end, days);
+                        let reply = self
+                            .cached_reply(
+                                format!("{NET_CMD} {person} {days}"),
+                                Dependency::Transactions,
+                                async {
+                                    let transactions =
+                                        self.list_transactions("all", start, end).await?;
+                                    Ok(format_net(&net_flow(&transactions, &person), days))
+                                },
+                            )
+                            .await;
+                        match reply {
+                            Ok(response) => send_message(response, &room).await?,
+                            Err(e) => {
+                                error!("Failed to list transactions: {e}");
+                                self.respond_error(
+                                    ErrorKind::Upstream,
+                                    Some(failure_message(&e, "Failed to list transactions")),
+                                    event.event_id.clone(),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Cmd::Recent(RecentArgs { count }) => {
+                        let account_id =
+                            self.config.source_account_id(room.room_id(), &event.sender);
+                        let reply = self
+                            .cached_reply(
+                                format!("{RECENT_CMD} {account_id} {count}"),
+                                Dependency::Transactions,
+                                async {
+                                    let transactions =
+                                        self.list_recent_transactions(account_id, count).await?;
+                                    Ok(format_recent(&transactions))
+                                },
+                            )
+                            .await;
+                        match reply {
+                            Ok(response) => send_message(response, &room).await?,
+                            Err(e) => {
+                                error!("Failed to list transactions: {e}");
+                                self.respond_error(
+                                    ErrorKind::Upstream,
+                                    Some(failure_message(&e, "Failed to list transactions")),
+                                    event.event_id.clone(),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Cmd::DiagDump => {
+                        if self.is_admin(&event.sender) {
+                            let dump = self.diagnostics(&room).await;
+                            self.send_output(dump, "diag.txt", &room).await?;
+                        } else {
+                            send_message(format!("Only admins may use {DIAG_CMD}"), &room).await?;
+                        }
+                    }
+                    Cmd::Rates => match self.exchange_rates().await {
+                        Ok(rates) if rates.is_empty() => {
+                            send_message("Firefly has no exchange rates".to_string(), &room).await?;
+                        }
+                        Ok(rates) => {
+                            self.send_output(format_rates(&latest_rates(rates)), "rates.txt", &room)
+                                .await?;
+                        }
+                        Err(e) if is_status(&e, StatusCode::NOT_FOUND) => {
+                            send_message(
+                                "This Firefly server does not provide exchange rates".to_string(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to get exchange rates: {e}");
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to get exchange rates")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::Convert(args) => {
+                        self.handle_convert(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::ReportLink(args) => {
+                        self.handle_report_link(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::Refresh => match self.refresh_mirror().await {
+                        Ok(data) => {
+                            send_message(
+                                format!(
+                                    "Refreshed {} categories, {} accounts, and {} tags",
+                                    data.categories.len(),
+                                    data.accounts.len(),
+                                    data.tags.len()
+                                ),
+                                &room,
+                            )
+                            .await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to refresh mirror: {e}");
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(&e, "Failed to refresh")),
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    },
+                    Cmd::LimitSet(args) => {
+                        self.handle_limit_set(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::MonthSet(day) => {
+                        self.settings.set_cycle_start_day(day)?;
+                        send_message(
+                            format!("Budget cycles now start on day {day} of each month"),
+                            &room,
+                        )
+                        .await?;
+                    }
+                    Cmd::Remind(RemindArgs::Set { delay, text }) => {
+                        let reminder = self.reminders.add(
+                            event.sender.as_str(),
+                            room.room_id().as_str(),
+                            Local::now() + delay,
+                            text,
+                        )?;
+                        send_message(
+                            format!(
+                                "Reminder {} set for {}",
+                                reminder.id,
+                                reminder.due.format("%Y-%m-%d %H:%M")
+                            ),
+                            &room,
+                        )
+                        .await?;
+                    }
+                    Cmd::Remind(RemindArgs::Cancel(id)) => {
+                        let response = if self.reminders.cancel(event.sender.as_str(), id)? {
+                            format!("Cancelled reminder {id}")
+                        } else {
+                            format!("You have no reminder {id}")
+                        };
+                        send_message(response, &room).await?;
+                    }
+                    Cmd::PauseUser(args) => {
+                        if !self.is_admin(&event.sender) {
+                            send_message(format!("Only admins may use {PAUSE_USER_CMD}"), &room)
+                                .await?;
+                            return Ok(());
+                        }
+
+                        let response = match args {
+                            PauseUserArgs::Pause { user_id, duration } => {
+                                let until = Local::now() + duration;
+                                self.pauses.pause(&user_id, until)?;
+                                format!(
+                                    "Ignoring commands from {user_id} until {}",
+                                    until.format("%Y-%m-%d %H:%M")
+                                )
+                            }
+                            PauseUserArgs::List => format_pauses(&self.pauses.list(Local::now())),
+                        };
+                        send_message(response, &room).await?;
+                    }
+                    Cmd::UnpauseUser(user_id) => {
+                        let response = if !self.is_admin(&event.sender) {
+                            format!("Only admins may use {UNPAUSE_USER_CMD}")
+                        } else if self.pauses.unpause(&user_id)? {
+                            format!("{user_id} is no longer paused")
+                        } else {
+                            format!("{user_id} is not paused")
+                        };
+                        send_message(response, &room).await?;
+                    }
+                    Cmd::Reminders => {
+                        send_message(
+                            format_reminders(&self.reminders.list_for(event.sender.as_str())),
+                            &room,
+                        )
+                        .await?;
+                    }
+                    Cmd::Subscribe(frequency) => {
+                        self.subscriptions
+                            .subscribe(event.sender.as_str(), frequency, Local::now())?;
+                        send_message(
+                            format!(
+                                "Subscribed. You'll receive a {} summary of your spending by DM.",
+                                frequency.name()
+                            ),
+                            &room,
+                        )
+                        .await?;
+                    }
+                    Cmd::Unsubscribe => {
+                        let response = if self.subscriptions.unsubscribe(event.sender.as_str())? {
+                            "Unsubscribed"
+                        } else {
+                            "You are not subscribed"
+                        };
+                        send_message(response.to_string(), &room).await?;
+                    }
+                    Cmd::Split(args) => {
+                        let payer = self.resolve_person_tag(&event.sender, &room).await;
+                        let preview = BulkPreview::new(&split_shares(
+                            args.add.amount,
+                            &args.participants,
+                            self.config.split_remainder,
+                            &payer,
+                        ));
+                        if preview.needs_confirmation(
+                            self.config.confirm_bulk_transactions,
+                            self.config.confirm_bulk_total,
+                        ) {
+                            let prompt_id = room
+                                .send(RoomMessageEventContent::text_plain(preview.prompt()), None)
+                                .await?
+                                .event_id;
+                            self.prompts.lock().unwrap().insert(
+                                prompt_id,
+                                event.sender.clone(),
+                                Prompt::ConfirmSplit {
+                                    args,
+                                    payer,
+                                    timestamp,
+                                    event_id: event.event_id.clone(),
+                                },
+                            );
+                        } else {
+                            self.handle_split(
+                                args,
+                                &payer,
+                                &event.sender,
+                                timestamp,
+                                event.event_id.clone(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    }
+                    Cmd::Transfer(args) => {
+                        self.handle_transfer(args, timestamp, event.event_id.clone(), &room)
+                            .await?;
+                    }
+                    Cmd::Settle(args) => {
+                        let person = self.resolve_person_tag(&event.sender, &room).await;
+                        if self.is_admin(&event.sender)
+                            || person.eq_ignore_ascii_case(&args.payer)
+                            || person.eq_ignore_ascii_case(&args.payee)
+                        {
+                            self.handle_settle(args, timestamp, event.event_id.clone(), &room)
+                                .await?;
+                        } else {
+                            send_message(
+                                "Only the payer, the payee, or an admin may record a settlement"
+                                    .to_string(),
+                                &room,
+                            )
+                            .await?;
+                        }
+                    }
+                    Cmd::Spend(args) => {
+                        self.handle_spend(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::Summary(args) => {
+                        self.handle_summary(args, event.event_id.clone(), &room)
+                            .await?
+                    }
+                    Cmd::History => self.handle_history(event.event_id.clone(), &room).await?,
+                    Cmd::CommandHistory => {
+                        let commands = self.commands.lock().unwrap().list(&event.sender);
+                        send_message(format_command_history(&commands), &room).await?;
+                    }
+                    Cmd::WhoOwes => {
+                        self.send_output(format_debts(&self.ledger.debts()), "debts.txt", &room)
+                            .await?;
+                    }
+                    Cmd::Retry => {
+                        let request = self.failed_adds.lock().unwrap().take(&event.sender);
+                        match request {
+                            Some(request) => {
+                                self.handle_add(request, &event.sender, event.event_id.clone(), &room)
+                                    .await?;
+                            }
+                            None => {
+                                send_message("Nothing to retry".to_string(), &room).await?;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            .await;
+            set_typing(&room, false).await;
+            return result;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the help text to members who join the room, once per member, when
+    /// `greet_new_members` is enabled
+    async fn on_member(
+        &self,
+        event: OriginalSyncRoomMemberEvent,
+        room: Room,
+    ) -> anyhow::Result<()> {
+        if self.config.greet_new_members == GreetNewMembers::Off
+            || !matches!(event.membership_change(), MembershipChange::Joined)
+        {
+            return Ok(());
+        }
+
+        let Room::Joined(room) = room else {
+            return Ok(());
+        };
+        let user_id = event.state_key;
+        if room.client().user_id() == Some(user_id.as_ref())
+            || !self
+                .greeted
+                .mark_greeted(room.room_id().as_str(), user_id.as_str())?
+        {
+            return Ok(());
+        }
+
+        info!("Greeting new member {user_id}");
+        let greeting = greeting_text(&user_id, &self.config.command_prefix);
+        match self.config.greet_new_members {
+            GreetNewMembers::Mention => send_message(greeting, &room).await,
+            GreetNewMembers::Dm => {
+                send_message(greeting, &dm_room(&room.client(), &user_id).await?).await
+            }
+            GreetNewMembers::Off => Ok(()),
+        }
+    }
+
+    async fn on_reaction(
+        &self,
+        event: OriginalSyncReactionEvent,
+        room: Room,
+    ) -> anyhow::Result<()> {
+        debug!("Received reaction: {event:?}");
+
+        if let Room::Joined(room) = room {
+            // Reactions answer prompts and turn pages, so they're limited to the same users as
+            // commands
+            if !self.is_authorized(&event.sender, &room) {
+                debug!("Ignoring reaction from unauthorized user {}", event.sender);
+                return Ok(());
+            }
+
+            let relation = &event.content.relates_to;
+            let prompt =
+                self.prompts
+                    .lock()
+                    .unwrap()
+                    .take(&relation.event_id, &event.sender, &relation.key);
+
+            if let Some(prompt) = prompt {
+                info!("Received answer to prompt: {prompt:?}");
+
+                match prompt {
+                    Prompt::CreateCategory { request, event_id } => {
+                        self.handle_add(request, &event.sender, event_id, &room)
+                            .await?;
+                    }
+                    Prompt::ConfirmDuplicate { request, event_id } => {
+                        self.start_add(request, &event.sender, event_id, &room)
+                            .await?;
+                    }
+                    Prompt::ConfirmSplit {
+                        args,
+                        payer,
+                        timestamp,
+                        event_id,
+                    } => {
+                        self.handle_split(args, &payer, &event.sender, timestamp, event_id, &room)
+                            .await?;
+                    }
+                    Prompt::ChooseSource {
+                        mut request,
+                        event_id,
+                        options,
+                    } => {
+                        let account_id = chosen_option(&options, &relation.key)
+                            .and_then(|name| self.config.source_accounts.get(name));
+                        match account_id {
+                            Some(account_id) => {
+                                request.source_account_id = Some(*account_id);
+                                self.start_add(request, &event.sender, event_id, &room)
+                                    .await?;
+                            }
+                            None => {
+                                send_message(
+                                    format!(
+                                        "Cancelled adding {} to {}",
+                                        format_amount(request.args.amount, None),
+                                        request.args.category
+                                    ),
+                                    &room,
+                                )
+                                .await?;
+                            }
+                        }
+                    }
+                    Prompt::ChooseCategory {
+                        mut request,
+                        event_id,
+                        options,
+                    } => {
+                        if let Some(category) = chosen_option(&options, &relation.key) {
+                            request.args.category = category.to_string();
+                            self.handle_add(request, &event.sender, event_id, &room)
+                                .await?;
+                        }
+                    }
+                }
+            } else if room.client().user_id() != Some(event.sender.as_ref()) {
+                let page = self
+                    .pagers
+                    .lock()
+                    .unwrap()
+                    .turn(&relation.event_id, &relation.key);
+                if let Some(page) = page {
+                    edit_message(page, relation.event_id.clone(), &room).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the expense, first resolving its source account and, unless categories are created
+    /// implicitly, its category
+    async fn start_add(
+        &self,
+        mut request: AddRequest,
+        sender: &UserId,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        if request.source_account_id.is_none() && !self.config.source_accounts.is_empty() {
+            match select_source_account(request.args.note.as_deref(), &self.config.source_accounts)
+            {
+                Some((account_id, note)) => {
+                    request.source_account_id = Some(account_id);
+                    request.args.note = note;
+                }
+                None if self.config.prompt_for_source => {
+                    return self
+                        .prompt_for_source(request, sender, event_id, room)
+                        .await;
+                }
+                None => {}
+            }
+        }
+
+        if self.config.category_creation != CategoryCreation::Implicit
+            || self.config.category_picker
+            || self.config.auto_create_categories
+        {
+            self.resolve_category_and_add(request, sender, event_id, room)
+                .await
+        } else {
+            self.handle_add(request, sender, event_id, room).await
+        }
+    }
+
+    /// Asks the user which of the `source_accounts` to pay from with numbered reactions, holding the
+    /// add until they choose or cancel
+    async fn prompt_for_source(
+        &self,
+        request: AddRequest,
+        sender: &UserId,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let options: Vec<String> = self
+            .config
+            .source_accounts
+            .keys()
+            .take(NUMBER_REACTIONS.len())
+            .cloned()
+            .collect();
+        let mut prompt = format!(
+            "Which account did {} to {} come from? React with its number, or {CANCEL_REACTION} to cancel:",
+            format_amount(request.args.amount, None),
+            request.args.category
+        );
+        for (reaction, name) in NUMBER_REACTIONS.iter().zip(&options) {
+            prompt.push_str(&format!("\n{reaction} {name}"));
+        }
+
+        let prompt_id = room
+            .send(RoomMessageEventContent::text_plain(prompt), None)
+            .await?
+            .event_id;
+        let option_count = options.len();
+        self.prompts.lock().unwrap().insert(
+            prompt_id.clone(),
+            sender.to_owned(),
+            Prompt::ChooseSource {
+                request,
+                event_id,
+                options,
+            },
+        );
+        for reaction in NUMBER_REACTIONS.iter().take(option_count) {
+            send_reaction(reaction.to_string(), prompt_id.clone(), room).await?;
+        }
+        send_reaction(CANCEL_REACTION.to_string(), prompt_id, room).await
+    }
+
+    /// Adds the expense if its category exists. Otherwise, when `category_picker` is enabled and
+    /// similar categories exist, asks the user to pick one. Failing that, the category is created
+    /// if `auto_create_categories` is enabled, or handled according to `category_creation`.
+    async fn resolve_category_and_add(
+        &self,
+        mut request: AddRequest,
+        sender: &UserId,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let categories = match self.categories().await {
+            Ok(categories) => categories,
+            Err(e) => {
+                error!("Failed to list categories: {e}");
+                self.respond_error(ErrorKind::Upstream, None, event_id, room)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        for split in &mut request.args.splits {
+            match categories
+                .iter()
+                .find(|category| category.eq_ignore_ascii_case(&split.category))
+            {
+                Some(category) => split.category = category.clone(),
+                None if self.config.auto_create_categories => {
+                    match self.create_category(&split.category).await {
+                        Ok(category) => {
+                            info!("Created category {category}");
+                            split.category = category;
+                        }
+                        Err(e) => {
+                            error!("Failed to create category {}: {e}", split.category);
+                            self.respond_error(
+                                ErrorKind::Upstream,
+                                Some(failure_message(
+                                    &e,
+                                    &format!("Failed to create category '{}'", split.category),
+                                )),
+                                event_id,
+                                room,
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                None => {
+                    self.respond_error(
+                        ErrorKind::Parse,
+                        Some(format!(
+                            "Category '{}' doesn't exist. Use {CATEGORIES_CMD} to list the existing categories",
+                            split.category
+                        )),
+                        event_id,
+                        room,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(category) = categories
+            .iter()
+            .find(|category| category.eq_ignore_ascii_case(&request.args.category))
+        {
+            request.args.category = category.clone();
+            return self.handle_add(request, sender, event_id, room).await;
+        }
+
+        let options = if self.config.category_picker {
+            fuzzy_category_matches(&request.args.category, &categories)
+        } else {
+            Vec::new()
+        };
+
+        if !options.is_empty() {
+            let prompt_id = room
+                .send(
+                    RoomMessageEventContent::text_plain(format_category_options(
+                        &request.args.category,
+                        &options,
+                    )),
+                    None,
+                )
+                .await?
+                .event_id;
+            let option_count = options.len();
+            self.prompts.lock().unwrap().insert(
+                prompt_id.clone(),
+                sender.to_owned(),
+                Prompt::ChooseCategory {
+                    request,
+                    event_id,
+                    options,
+                },
+            );
+            for reaction in NUMBER_REACTIONS.iter().take(option_count) {
+                send_reaction(reaction.to_string(), prompt_id.clone(), room).await?;
+            }
+        } else if self.config.auto_create_categories {
+            send_reaction(NEW_CATEGORY_REACTION.to_owned(), event_id.clone(), room).await?;
+            match self.create_category(&request.args.category).await {
+                Ok(category) => {
+                    info!("Created category {category}");
+                    request.args.category = category;
+                    self.handle_add(request, sender, event_id, room).await?;
+                }
+                Err(e) => {
+                    error!("Failed to create category {}: {e}", request.args.category);
+                    self.respond_error(
+                        ErrorKind::Upstream,
+                        Some(failure_message(
+                            &e,
+                            &format!("Failed to create category '{}'", request.args.category),
+                        )),
+                        event_id,
+                        room,
+                    )
+                    .await?;
+                }
+            }
+        } else if self.config.category_creation == CategoryCreation::Reject {
+            self.respond_error(
+                ErrorKind::Parse,
+                Some(format!(
+                    "Category '{}' doesn't exist. Use {CATEGORIES_CMD} to list the existing categories",
+                    request.args.category
+                )),
+                event_id,
+                room,
+            )
+            .await?;
+        } else if self.config.category_creation == CategoryCreation::Confirm {
+            let prompt_id = room
+                .send(
+                    RoomMessageEventContent::text_plain(format!(
+                        "Category '{}' doesn't exist. React {CONFIRM_REACTION} to create it",
+                        request.args.category
+                    )),
+                    None,
+                )
+                .await?
+                .event_id;
+            self.prompts.lock().unwrap().insert(
+                prompt_id,
+                sender.to_owned(),
+                Prompt::CreateCategory { request, event_id },
+            );
+        } else {
+            self.handle_add(request, sender, event_id, room).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds each person's share of the split, and records that they owe `payer` for it
+    async fn handle_split(
+        &self,
+        args: SplitArgs,
+        payer: &str,
+        sender: &UserId,
+        timestamp: SystemTime,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let source_account_id = self.config.source_account_id(room.room_id(), sender);
+        let shares = split_shares(
+            args.add.amount,
+            &args.participants,
+            self.config.split_remainder,
+            payer,
+        );
+        let results = if self.config.batch_transactions && shares.len() > 1 {
+            let group_title = format!(
+                "{} split between {}",
+                args.add.category,
+                args.participants.join(", ")
+            );
+            let transactions = args
+                .participants
+                .iter()
+                .zip(&shares)
+                .map(|(person, amount)| {
+                    self.expense_withdrawal(
+                        &args.add,
+                        &args.add.category,
+                        *amount,
+                        source_account_id,
+                        person,
+                        timestamp,
+                        args.add.note.clone(),
+                    )
+                })
+                .collect();
+
+            // Firefly creates all of the transactions in a group, or none of them
+            let added = match self
+                .post_transaction(Transactions::batch(group_title, transactions))
+                .await
+            {
+                Ok(_) => true,
+                Err(e) => {
+                    error!("Failed to add split: {e}");
+                    false
+                }
+            };
+            args.participants
+                .into_iter()
+                .zip(shares)
+                .map(|(person, amount)| ShareResult {
+                    person,
+                    amount,
+                    added,
+                })
+                .collect()
+        } else {
+            let expense = &args.add;
+            let outcome = add_shares(
+                args.participants.iter().cloned().zip(shares).collect(),
+                self.config.split_atomic,
+                |person, amount| async move {
+                    self.add_expense(expense, amount, source_account_id, &person, timestamp)
+                        .await
+                },
+                |id| async move { self.delete_transaction(&id).await },
+            )
+            .await;
+            if outcome.rolled_back > 0 {
+                send_message(
+                    format!(
+                        "A share of the split failed, so the {} shares that were added have been removed",
+                        outcome.rolled_back
+                    ),
+                    room,
+                )
+                .await?;
+            }
+            outcome.results
+        };
+
+        for result in results.iter().filter(|result| result.added) {
+            if !result.person.eq_ignore_ascii_case(payer) {
+                if let Err(e) = self.ledger.add_debt(&result.person, payer, result.amount) {
+                    error!("Failed to record {}'s debt to {payer}: {e}", result.person);
+                }
+            }
+        }
+
+        self.report_split(&results, event_id, room).await
+    }
+
+    /// Sends the first page of recent transactions, which can be paged through with reactions
+    async fn handle_history(&self, event_id: OwnedEventId, room: &Joined) -> anyhow::Result<()> {
+        let end = Local::now().date_naive();
+        let start = end - ChronoDuration::days(HISTORY_DAYS);
+
+        let mut transactions = match self.list_transactions("all", start, end).await {
+            Ok(transactions) => transactions,
+            Err(e) => {
+                error!("Failed to list transactions: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to list transactions")),
+                    event_id,
+                    room,
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+
+        if transactions.is_empty() {
+            send_message(
+                format!("No transactions in the last {HISTORY_DAYS} days"),
+                room,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.date));
+        let pager = Pager::new(
+            "Recent transactions".to_string(),
+            transactions.iter().map(format_history_line).collect(),
+            HISTORY_PAGE_SIZE,
+        );
+        let multiple_pages = pager.page_count() > 1;
+
+        let message_id = room
+            .send(RoomMessageEventContent::text_plain(pager.render()), None)
+            .await?
+            .event_id;
+
+        if multiple_pages {
+            self.pagers
+                .lock()
+                .unwrap()
+                .insert(message_id.clone(), pager);
+            send_reaction(PREV_REACTION.to_string(), message_id.clone(), room).await?;
+            send_reaction(NEXT_REACTION.to_string(), message_id, room).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports what was spent from each Firefly budget this budget cycle, and what remains of its
+    /// limits
+    async fn handle_budget(&self, event_id: OwnedEventId, room: &Joined) -> anyhow::Result<()> {
+        let (start, end) = budget_cycle(Local::now().date_naive(), self.cycle_start_day());
+        let period = [("start", start.to_string()), ("end", end.to_string())];
+
+        let result = self
+            .cached_reply(
+                format!("{BUDGET_CMD} {start}"),
+                Dependency::Transactions,
+                async {
+                    let budgets: Vec<Budget> =
+                        self.firefly_get_all(FIREFLY_BUDGETS_API, &period).await?;
+
+                    let mut statuses = Vec::with_capacity(budgets.len());
+                    for budget in budgets {
+                        let limits: Vec<BudgetLimit> = self
+                            .firefly_get_all(
+                                &format!("{FIREFLY_BUDGETS_API}/{}/limits", budget.id),
+                                &period,
+                            )
+                            .await?;
+                        statuses.push(BudgetStatus {
+                            name: budget.attributes.name,
+                            spent: -budget.attributes.spent.iter().map(|s| s.sum).sum::<f64>(),
+                            limit: (!limits.is_empty())
+                                .then(|| limits.iter().map(|l| l.attributes.amount).sum()),
+                        });
+                    }
+
+                    Ok(format_budgets(&statuses))
+                },
+            )
+            .await;
+
+        match result {
+            Ok(response) => self.send_output(response, "budgets.txt", room).await,
+            Err(e) => {
+                error!("Failed to get budgets: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to get budgets")),
+                    event_id,
+                    room,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Draws a sparkline of the asset account's balance at the end of each day in the window
+    async fn handle_balance_history(
+        &self,
+        args: BalanceHistoryArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let end = Local::now().date_naive();
+        let start = report_start(end, args.days);
+
+        let key = format!(
+            "{BALANCE_HISTORY_CMD} {} {}",
+            args.account.to_lowercase(),
+            args.days
+        );
+        let result = self
+            .cached_reply(key, Dependency::Transactions, async {
+                let accounts: Vec<AccountBalance> = self
+                    .firefly_get_all(FIREFLY_ACCOUNTS_API, &[("type", "asset".to_string())])
+                    .await?;
+                let Some(account) = accounts
+                    .into_iter()
+                    .find(|account| account.attributes.name.eq_ignore_ascii_case(&args.account))
+                else {
+                    return Ok(format!("Unknown account: {}", args.account));
+                };
+
+                let groups: Vec<TransactionGroup> = self
+                    .firefly_get_all(
+                        &format!("{FIREFLY_ACCOUNTS_API}/{}/transactions", account.id),
+                        &[("start", start.to_string()), ("end", end.to_string())],
+                    )
+                    .await?;
+                let changes: Vec<(NaiveDate, f64)> = groups
+                    .into_iter()
+                    .flat_map(|group| group.attributes.transactions)
+                    .map(|split| {
+                        let change = if split.destination_id.as_deref() == Some(account.id.as_str())
+                        {
+                            split.amount
+                        } else {
+                            -split.amount
+                        };
+                        (split.date.date_naive(), change)
+                    })
+                    .collect();
+
+                Ok(format_balance_history(
+                    &account.attributes.name,
+                    args.days,
+                    &daily_balances(account.attributes.current_balance, &changes, start, end),
+                ))
+            })
+            .await;
+
+        match result {
+            Ok(response) => send_message(response, room).await,
+            Err(e) => {
+                error!("Failed to get balance history: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to get balance history")),
+                    event_id,
+                    room,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Returns the reply to a read-only command from the result cache, or builds and caches it if
+    /// it isn't there. Failures are not cached.
+    async fn cached_reply(
+        &self,
+        key: String,
+        dependency: Dependency,
+        reply: impl Future<Output = anyhow::Result<String>>,
+    ) -> anyhow::Result<String> {
+        if let Some(cached) = self.results.lock().unwrap().get(&key) {
+            debug!("Replying to {key} from the result cache");
+            return Ok(cached);
+        }
+
+        let reply = reply.await?;
+        self.results
+            .lock()
+            .unwrap()
+            .insert(key, dependency, reply.clone());
+        Ok(reply)
+    }
+
+    /// Attaches a CSV of every category's spending over the window
+    async fn handle_category_export(
+        &self,
+        args: CategoryExportArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let end = Local::now().date_naive();
+        let start = report_start(end, args.days);
+
+        let result = async {
+            let categories = self.categories().await?;
+            let expenses = self.list_withdrawals(start, end).await?;
+            anyhow::Ok((categories, expenses))
+        }
+        .await;
+
+        match result {
+            Ok((_, expenses)) if expenses.is_empty() => {
+                send_message(format!("No expenses in the last {} days", args.days), room).await?;
+            }
+            Ok((categories, expenses)) => {
+                let csv = format_csv(&category_rows(&categories, &expenses));
+                send_message(format!("Spending by category from {start} to {end}"), room).await?;
+                room.send_attachment(
+                    &format!("categories-{start}-{end}.csv"),
+                    &mime::TEXT_CSV_UTF_8,
+                    csv.as_bytes(),
+                    AttachmentConfig::new(),
+                )
+                .await?;
+            }
+            Err(e) => {
+                error!("Failed to export categories: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to export categories")),
+                    event_id,
+                    room,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replies with a link to the Firefly page for the category, tag, or account, filtered to the
+    /// current budget cycle
+    async fn handle_report_link(
+        &self,
+        args: ReportLinkArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let kind = args.kind.name();
+
+        let result = async {
+            anyhow::Ok(match args.kind {
+                LinkKind::Category => self
+                    .firefly_get_all::<Category>(FIREFLY_CATEGORIES_API, &[])
+                    .await?
+                    .into_iter()
+                    .find(|category| category.attributes.name.eq_ignore_ascii_case(&args.name))
+                    .map(|category| category.id),
+                LinkKind::Tag => self
+                    .firefly_get_all::<Tag>(FIREFLY_TAGS_API, &[])
+                    .await?
+                    .into_iter()
+                    .find(|tag| tag.attributes.tag.eq_ignore_ascii_case(&args.name))
+                    .map(|tag| tag.id),
+                LinkKind::Account => self
+                    .list_accounts()
+                    .await?
+                    .into_iter()
+                    .find(|account| account.name.eq_ignore_ascii_case(&args.name))
+                    .map(|account| account.id.to_string()),
+            })
+        }
+        .await;
+
+        let response = match result {
+            Ok(Some(id)) => {
+                let (start, end) = budget_cycle(Local::now().date_naive(), self.cycle_start_day());
+                report_url(&self.config.firefly_url, args.kind, &id, start, end)
+            }
+            Ok(None) => format!("Unknown {kind}: {}", args.name),
+            Err(e) => {
+                error!("Failed to look up {kind} {}: {e}", args.name);
+                return self
+                    .respond_error(
+                        ErrorKind::Upstream,
+                        Some(failure_message(&e, &format!("Failed to look up {kind}"))),
+                        event_id,
+                        room,
+                    )
+                    .await;
+            }
+        };
+
+        send_message(response, room).await
+    }
+
+    /// Sets or removes the spending limit on a category, or lists the limits. A limit can only be
+    /// set on an existing category, whose name is matched ignoring case.
+    async fn handle_limit_set(
+        &self,
+        args: LimitSetArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let response = match args {
+            LimitSetArgs::List => format_limits(&self.limits.limits(&self.config.spending_limits)),
+            LimitSetArgs::Set { category, amount } => match self.categories().await {
+                Ok(categories) => match categories
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(&category))
+                {
+                    Some(category) => {
+                        self.limits.set(category, amount)?;
+                        format!(
+                            "{category} is now limited to {} per budget cycle",
+                            format_amount(amount, None)
+                        )
+                    }
+                    None => format!("Unknown category: {category}"),
+                },
+                Err(e) => {
+                    error!("Failed to list categories: {e}");
+                    return self
+                        .respond_error(
+                            ErrorKind::Upstream,
+                            Some(failure_message(&e, "Failed to list categories")),
+                            event_id,
+                            room,
+                        )
+                        .await;
+                }
+            },
+            LimitSetArgs::Remove(category) => {
+                match self.limits.limit(&category, &self.config.spending_limits) {
+                    Some(_) => {
+                        self.limits.remove(&category)?;
+                        format!("Removed the limit on {category}")
+                    }
+                    None => format!("{category} has no limit"),
+                }
+            }
+        };
+        send_message(response, room).await
+    }
+
+    /// Returns a warning if the category has a spending limit and its spending in the current
+    /// budget cycle is over it
+    async fn check_limit(&self, category: &str) -> Option<String> {
+        let limit = self.limits.limit(category, &self.config.spending_limits)?;
+        let (start, end) = budget_cycle(Local::now().date_naive(), self.cycle_start_day());
+        match self.list_withdrawals(start, end).await {
+            Ok(expenses) => {
+                let spent = category_spend(&expenses, category, start, end).total;
+                limit_warning(category, spent, limit)
+            }
+            Err(e) => {
+                warn!("Failed to check the spending limit on {category}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Reports the spending in the category, which is resolved against the existing categories
+    async fn handle_spend(
+        &self,
+        args: SpendArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let categories = match self.categories().await {
+            Ok(categories) => categories,
+            Err(e) => {
+                error!("Failed to list categories: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to list categories")),
+                    event_id,
+                    room,
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+
+        let category = match categories
+            .iter()
+            .find(|category| category.eq_ignore_ascii_case(&args.category))
+        {
+            Some(category) => category.clone(),
+            None => match &fuzzy_category_matches(&args.category, &categories)[..] {
+                [category] => category.clone(),
+                [] => {
+                    send_message(format!("Unknown category: {}", args.category), room).await?;
+                    return Ok(());
+                }
+                options => {
+                    send_message(
+                        format!(
+                            "Unknown category: {}. Did you mean one of: {}?",
+                            args.category,
+                            options.join(", ")
+                        ),
+                        room,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            },
+        };
+
+        let end = Local::now().date_naive();
+        let start = report_start(end, args.days);
+        let reply = self
+            .cached_reply(
+                format!("{SPEND_CMD} {category} {}", args.days),
+                Dependency::Transactions,
+                async {
+                    let expenses = self.list_withdrawals(start, end).await?;
+                    let report = category_spend(&expenses, &category, start, end);
+                    Ok(format_spend(&category, args.days, &report))
+                },
+            )
+            .await;
+        match reply {
+            Ok(response) => self.send_output(response, "spend.txt", room).await?,
+            Err(e) => {
+                error!("Failed to list expenses: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to list expenses")),
+                    event_id,
+                    room,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replies with the spending in each category over the budget cycle or month, largest first,
+    /// and the total
+    async fn handle_summary(
+        &self,
+        args: SummaryArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let (start, end) = args.period(Local::now().date_naive(), self.cycle_start_day());
+        let label = period_label(start, end);
+        let reply = self
+            .cached_reply(
+                format!("{SUMMARY_CMD} {label}"),
+                Dependency::Transactions,
+                async {
+                    let expenses = self.list_withdrawals(start, end).await?;
+                    Ok(format_summary(&label, &category_totals(&expenses)))
+                },
+            )
+            .await;
+        match reply {
+            Ok(response) => self.send_output(response, "summary.txt", room).await?,
+            Err(e) => {
+                error!("Failed to list expenses: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to list expenses")),
+                    event_id,
+                    room,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a transfer between two asset accounts, which are matched by name ignoring case
+    async fn handle_transfer(
+        &self,
+        args: TransferArgs,
+        timestamp: SystemTime,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let accounts = match self.list_accounts().await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                error!("Failed to list accounts: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to list accounts")),
+                    event_id,
+                    room,
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+
+        let (source_id, destination_id) = match (
+            resolve_account(&args.from, &accounts),
+            resolve_account(&args.to, &accounts),
+        ) {
+            (Some(source_id), Some(destination_id)) => (source_id, destination_id),
+            (source_id, destination_id) => {
+                let unknown: Vec<&str> = [(&args.from, source_id), (&args.to, destination_id)]
+                    .into_iter()
+                    .filter(|(_, id)| id.is_none())
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                self.respond_error(
+                    ErrorKind::Parse,
+                    Some(format!("Unknown account: {}", unknown.join(", "))),
+                    event_id,
+                    room,
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+
+        let transfer = Transaction::transfer(
+            args.amount,
+            timestamp.into(),
+            source_id,
+            destination_id,
+            format!("Transfer from {} to {}", args.from, args.to),
+            args.note,
+        );
+        match self.post_transaction(Transactions::new(transfer)).await {
+            Ok(_) => send_reaction("✅".to_owned(), event_id, room).await,
+            Err(e) => {
+                error!("Failed to add transfer: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to add transfer")),
+                    event_id,
+                    room,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Records the settlement in the ledger. When both people have `settle_accounts`, the payment
+    /// is first added to Firefly as a transfer between their accounts, and the ledger is only
+    /// changed once Firefly has it.
+    async fn handle_settle(
+        &self,
+        args: SettleArgs,
+        timestamp: SystemTime,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        if let Err(e) = self.ledger.check_settle(&args) {
+            self.respond_error(ErrorKind::Parse, Some(e.to_string()), event_id, room)
+                .await?;
+            return Ok(());
+        }
+
+        if let (Some(source_id), Some(destination_id)) = (
+            settle_account(&self.config.settle_accounts, &args.payer),
+            settle_account(&self.config.settle_accounts, &args.payee),
+        ) {
+            let transfer = Transaction::transfer(
+                args.amount,
+                timestamp.into(),
+                source_id,
+                destination_id,
+                format!("Settlement from {} to {}", args.payer, args.payee),
+                None,
+            );
+            if let Err(e) = self.post_transaction(Transactions::new(transfer)).await {
+                error!("Failed to add settlement: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to add settlement")),
+                    event_id,
+                    room,
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+
+        if let Err(e) = self.ledger.settle(&args) {
+            error!("Failed to record settlement: {e}");
+            self.respond_error(
+                ErrorKind::Upstream,
+                Some(format!(
+                    "Failed to record the settlement in the ledger: {e}"
+                )),
+                event_id,
+                room,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        send_reaction("✅".to_owned(), event_id, room).await?;
+        Ok(())
+    }
+
+    /// Adds each share of the split with its own request
+    async fn report_split(
+        &self,
+        results: &[ShareResult],
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        match self.config.split_feedback {
+            SplitFeedback::Reaction => {
+                let reaction = if results.iter().all(|result| result.added) {
+                    "✅"
+                } else {
+                    FAILED_REACTION
+                };
+                send_reaction(reaction.to_owned(), event_id, room).await?;
+            }
+            SplitFeedback::Summary => {
+                send_message(format_split_summary(results), room).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replies with what the add would send to Firefly, without sending it
+    async fn handle_dry_run(
+        &self,
+        args: AddArgs,
+        sender: &UserId,
+        timestamp: SystemTime,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let person = self.resolve_person_tag(sender, room).await;
+        let account_id = self.config.source_account_id(room.room_id(), sender);
+        let transactions =
+            match infer_add_kind(&args.category, &args.tags, &self.config.income_keywords) {
+                AddKind::Deposit => {
+                    let deposit = DepositArgs {
+                        source: None,
+                        add: args,
+                    };
+                    self.deposit_transaction(deposit, account_id, &person, timestamp)
+                        .await
+                }
+                AddKind::Withdrawal => {
+                    self.expense_transactions(&args, args.amount, account_id, &person, timestamp)
+                }
+            };
+        send_message(format_dry_run(&transactions), room).await
+    }
+
+    async fn handle_add(
+        &self,
+        request: AddRequest,
+        sender: &UserId,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let args = request.args.clone();
+        let person = self.resolve_person_tag(sender, room).await;
+        let kind = infer_add_kind(&args.category, &args.tags, &self.config.income_keywords);
+        let result = match kind {
+            AddKind::Deposit => {
+                let deposit = DepositArgs {
+                    source: None,
+                    add: args.clone(),
+                };
+                self.add_deposit(
+                    deposit,
+                    self.config.source_account_id(room.room_id(), sender),
+                    &person,
+                    request.timestamp,
+                )
+                .await
+            }
+            AddKind::Withdrawal => {
+                self.add_expense(
+                    &args,
+                    args.amount,
+                    request
+                        .source_account_id
+                        .unwrap_or_else(|| self.config.source_account_id(room.room_id(), sender)),
+                    &person,
+                    request.timestamp,
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(id) => {
+                self.failed_adds.lock().unwrap().clear(sender);
+                if self.config.warn_duplicates {
+                    self.recent_adds.lock().unwrap().record(
+                        sender,
+                        RecentAdd {
+                            category: args.category.clone(),
+                            amount: args.amount,
+                            added_at: request.timestamp,
+                        },
+                    );
+                }
+                let new_tags = if self.config.warn_new_tags {
+                    new_tags_note(&self.new_tags(&args.tags).await)
+                } else {
+                    None
+                };
+                let redact = should_redact(&self.config, true);
+                let link = self.transaction_link(id.as_deref());
+                // A reaction would be hidden along with the redacted command, so reply instead
+                if redact || self.replies_with_message(room) {
+                    let mut confirmation = if args.refund {
+                        format!("Added a refund of {} for {person}", args.describe_amounts())
+                    } else {
+                        format!("Added {} for {person}", args.describe_amounts())
+                    };
+                    if let Some(new_tags) = &new_tags {
+                        confirmation.push_str(&format!(" (created {new_tags})"));
+                    }
+                    if let Some(link) = link {
+                        confirmation.push_str(&format!("\n{link}"));
+                    }
+                    let confirmation_id = self
+                        .send_confirmation(confirmation, &event_id, redact, room)
+                        .await?;
+                    self.record_added([event_id.clone(), confirmation_id], id, sender, room);
+                } else {
+                    let reaction = if args.refund { REFUND_REACTION } else { "✅" };
+                    send_reaction(reaction.to_owned(), event_id.clone(), room).await?;
+                    let mut event_ids = vec![event_id.clone()];
+                    if let Some(link) = link {
+                        event_ids.push(send_text(link, room).await?);
+                    }
+                    self.record_added(event_ids, id, sender, room);
+                    if let Some(new_tags) = new_tags {
+                        send_message(format!("Created {new_tags}"), room).await?;
+                    }
+                }
+                if redact {
+                    if let Err(e) = room.redact(&event_id, Some(BOT_NAME), None).await {
+                        warn!("Failed to redact command {event_id}. Does the bot have permission to redact? {e}");
+                    }
+                }
+                // A refund only lowers the spending
+                if kind == AddKind::Withdrawal && !args.refund {
+                    for (category, _) in args.category_amounts() {
+                        if let Some(warning) = self.check_limit(category).await {
+                            send_message(warning, room).await?;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{e}");
+                self.failed_adds
+                    .lock()
+                    .unwrap()
+                    .store(sender.to_owned(), request);
+                if e.is::<MaintenanceError>() {
+                    send_message(
+                        format!("{MAINTENANCE_MESSAGE}. Use {RETRY_CMD} to add it once it's back."),
+                        room,
+                    )
+                    .await?;
+                } else if self.replies_with_message(room) {
+                    self.send_confirmation(
+                        format!(
+                            "Failed to add {}. Use {RETRY_CMD} to try again.",
+                            args.describe_amounts()
+                        ),
+                        &event_id,
+                        false,
+                        room,
+                    )
+                    .await?;
+                } else {
+                    self.respond_error(ErrorKind::Upstream, None, event_id, room)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if adds are confirmed with a message rather than a reaction, either because of
+    /// the `reply_mode` or because the room is a DM with `dm.text_confirmations`
+    fn replies_with_message(&self, room: &Joined) -> bool {
+        self.config.reply_mode != ReplyMode::Reaction
+            || (self.config.dm.text_confirmations && is_dm(room))
+    }
+
+    /// The link to an added transaction group in Firefly, if `link_transactions` is set and
+    /// Firefly returned the group's id
+    fn transaction_link(&self, transaction_id: Option<&str>) -> Option<String> {
+        transaction_id
+            .filter(|_| self.config.link_transactions)
+            .map(|id| {
+                format!(
+                    "View it in Firefly: {}",
+                    transaction_url(&self.config.firefly_url, id)
+                )
+            })
+    }
+
+    /// Sends the confirmation of the command as a reply or in a thread, depending on the
+    /// `reply_mode`. A command that is being redacted can't be replied to, so its confirmation is
+    /// sent as a plain message. Returns the confirmation's event id.
+    async fn send_confirmation(
+        &self,
+        text: String,
+        event_id: &EventId,
+        redacted: bool,
+        room: &Joined,
+    ) -> anyhow::Result<OwnedEventId> {
+        let reply_mode = if redacted {
+            ReplyMode::Reaction
+        } else {
+            self.config.reply_mode
+        };
+        let response = room
+            .send_raw(
+                confirmation_content(&text, event_id, reply_mode),
+                "m.room.message",
+                None,
+            )
+            .await?;
+        Ok(response.event_id)
+    }
+
+    /// Returns true if the user may send commands. Everyone is allowed when `allowed_users` is
+    /// empty, and DM rooms allow everyone when `dm.authorize_all` is set. Users paused with
+    /// `!pauseuser` are not allowed until their pause ends, unless they are admins.
+    fn is_authorized(&self, user_id: &UserId, room: &Joined) -> bool {
+        if !self.is_admin(user_id) && self.pauses.is_paused(user_id.as_str(), Local::now()) {
+            return false;
+        }
+
+        self.config.allowed_users.is_empty()
+            || self
+                .config
+                .allowed_users
+                .iter()
+                .any(|allowed| allowed == user_id.as_str())
+            || (self.config.dm.authorize_all && is_dm(room))
+    }
+
+    fn is_admin(&self, user_id: &UserId) -> bool {
+        self.config
+            .admin_users
+            .iter()
+            .any(|admin| admin == user_id.as_str())
+    }
+
+    /// Checks that Firefly can be reached with the configured URL and API key, and logs its version
+    async fn check_firefly(&self) -> anyhow::Result<()> {
+        let about: Single<About> = self
+            .firefly_get(FIREFLY_ABOUT_API, &[])
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to reach Firefly at {}. Check firefly_url and firefly_api_key: {e}",
+                    self.config.api_url(FIREFLY_ABOUT_API)
+                )
+            })?;
+        info!(
+            "Connected to Firefly {} (API {})",
+            about.data.version, about.data.api_version
+        );
+        Ok(())
+    }
+
+    /// Compiles a snapshot of the bot's state for troubleshooting. Secrets are never included.
+    async fn diagnostics(&self, room: &Joined) -> String {
+        let mut dump = format!("Version: {}", env!("CARGO_PKG_VERSION"));
+
+        let matrix = match room.client().whoami().await {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        let firefly = match self
+            .firefly_get::<serde_json::Value>(FIREFLY_ABOUT_API, &[])
+            .await
+        {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        dump.push_str(&format!(
+            "\nConnectivity:\n - Matrix: {matrix}\n - Firefly: {firefly}"
+        ));
+
+        let mirror = self.mirror.data();
+        dump.push_str(&format!(
+            "\nLast sync: {}",
+            mirror
+                .synced_at
+                .map(|synced_at| synced_at.to_rfc3339())
+                .unwrap_or_else(|| "never".to_string())
+        ));
+        let rates = self
+            .rates_cache
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(rates, _)| rates.len())
+            .unwrap_or(0);
+        dump.push_str(&format!(
+            "\nCaches:\n - Categories: {}\n - Accounts: {}\n - Tags: {}\n - Exchange rates: {rates}\n - Pending prompts: {}\n - Failed adds: {}\n - Subscriptions: {}",
+            mirror.categories.len(),
+            mirror.accounts.len(),
+            mirror.tags.len(),
+            self.prompts.lock().unwrap().len(),
+            self.failed_adds.lock().unwrap().len(),
+            self.subscriptions.list().len()
+        ));
+
+        match redacted_config(&self.config) {
+            Ok(config) => dump.push_str(&format!("\nConfig:\n{config}")),
+            Err(e) => dump.push_str(&format!("\nConfig: failed to serialize: {e}")),
+        }
+
+        dump
+    }
+
+    /// Sends the output of a command. Output longer than `max_output_lines` is cut short, and the
+    /// full output is attached as a text file.
+    async fn send_output(
+        &self,
+        output: String,
+        file_name: &str,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        match output_preview(&output, self.config.max_output_lines) {
+            Some(preview) => {
+                send_message(preview, room).await?;
+                room.send_attachment(
+                    file_name,
+                    &mime::TEXT_PLAIN_UTF_8,
+                    output.as_bytes(),
+                    AttachmentConfig::new(),
+                )
+                .await?;
+            }
+            None => send_message(output, room).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Sends a summary to each subscriber whose summary is due
+    async fn send_due_summaries(&self, client: &MatrixClient) {
+        let now = Local::now();
+        let cycle_start_day = self.cycle_start_day();
+
+        for subscription in self.subscriptions.list() {
+            if !subscription.is_due(now, cycle_start_day) {
+                continue;
+            }
+
+            let result = async {
+                let user_id = OwnedUserId::try_from(subscription.user_id.as_str())?;
+                let room = dm_room(client, &user_id).await?;
+                let person = self.resolve_person_tag(&user_id, &room).await;
+                let summary = self
+                    .personal_summary(&person, subscription.frequency)
+                    .await?;
+                send_message(summary, &room).await?;
+                self.subscriptions.mark_sent(&subscription.user_id, now)
+            }
+            .await;
+
+            match result {
+                Ok(_) => info!("Sent summary to {}", subscription.user_id),
+                Err(e) => error!("Failed to send summary to {}: {e}", subscription.user_id),
+            }
+        }
+    }
+
+    /// Sends each reminder that is due to the room it was set in, or by DM if the bot has left
+    /// that room
+    async fn send_due_reminders(&self, client: &MatrixClient) {
+        for reminder in self.reminders.due(Local::now()) {
+            let result = async {
+                let user_id = OwnedUserId::try_from(reminder.user_id.as_str())?;
+                let room_id = OwnedRoomId::try_from(reminder.room_id.as_str())?;
+                let room = match client.get_joined_room(&room_id) {
+                    Some(room) => room,
+                    None => dm_room(client, &user_id).await?,
+                };
+                send_message(format!("{user_id}: ⏰ {}", reminder.text), &room).await?;
+                self.reminders.remove(reminder.id)
+            }
+            .await;
+
+            match result {
+                Ok(_) => info!("Sent reminder {} to {}", reminder.id, reminder.user_id),
+                Err(e) => error!(
+                    "Failed to send reminder {} to {}: {e}",
+                    reminder.id, reminder.user_id
+                ),
+            }
+        }
+    }
+
+    async fn personal_summary(&self, person: &str, frequency: Frequency) -> anyhow::Result<String> {
+        let today = Local::now().date_naive();
+        let (start, end, period) = match frequency {
+            Frequency::Monthly => {
+                let (current_start, _) = budget_cycle(today, self.cycle_start_day());
+                let (start, end) = budget_cycle(
+                    current_start - ChronoDuration::days(1),
+                    self.cycle_start_day(),
+                );
+                (start, end, format!("{start} to {end}"))
+            }
+            _ => {
+                let days = frequency.days();
+                (
+                    today - ChronoDuration::days(days),
+                    today,
+                    format!("the last {days} days"),
+                )
+            }
+        };
+        let expenses = self.list_expenses(person, start, end).await?;
+
+        if expenses.is_empty() {
+            return Ok(format!("No expenses for {period}"));
+        }
+
+        let mut summary = format!("Your {} spending summary for {period}:", frequency.name());
+        let totals = category_totals(&expenses);
+        for (category, amount) in &totals {
+            summary.push_str(&format!(
+                "\n - {category}: {}",
+                format_amount(*amount, None)
+            ));
+        }
+        summary.push_str(&format!(
+            "\nTotal: {}",
+            format_amount(totals.iter().map(|(_, amount)| amount).sum(), None)
+        ));
+
+        Ok(summary)
+    }
+
+    /// Returns the day of the month that budget cycles start on, preferring the day set with
+    /// `!monthset` over the config
+    fn cycle_start_day(&self) -> u32 {
+        match self.settings.cycle_start_day() {
+            Ok(Some(day)) => day,
+            Ok(None) => self.config.budget_cycle_start_day,
+            Err(e) => {
+                warn!("Failed to read budget cycle start day: {e}");
+                self.config.budget_cycle_start_day
+            }
+        }
+    }
+
+    /// Returns the name used to tag the user's transactions, trying each of the configured
+    /// `person_sources` in order and falling back to the user id's localpart
+    async fn resolve_person_tag(&self, user_id: &UserId, room: &Joined) -> String {
+        let display_name = if self
+            .config
+            .person_sources
+            .contains(&PersonSource::DisplayName)
+        {
+            self.resolve_display_name(user_id, room).await
+        } else {
+            None
+        };
+
+        choose_person_tag(
+            &self.config.person_sources,
+            display_name.as_deref(),
+            self.config
+                .user_tags
+                .get(user_id.as_str())
+                .map(String::as_str),
+            user_id.localpart(),
+        )
+    }
+
+    /// Returns the user's display name in the room. The lookup gives up after
+    /// `display_name_timeout_ms` so that it never holds up a command.
+    async fn resolve_display_name(&self, user_id: &UserId, room: &Joined) -> Option<String> {
+        let timeout = Duration::from_millis(self.config.display_name_timeout_ms);
+
+        match tokio::time::timeout(timeout, room.get_member(user_id)).await {
+            Ok(Ok(member)) => member.and_then(|member| member.display_name().map(String::from)),
+            Ok(Err(e)) => {
+                warn!("Failed to get display name for {user_id}: {e}");
+                None
+            }
+            Err(_) => {
+                warn!("Timed out getting display name for {user_id}");
+                None
+            }
+        }
+    }
+
+    /// Adds the expense, or a share of it, paid from the source account. Expenses without a note
+    /// get one of the `default_notes`. An expense split across categories is added as one
+    /// transaction group, with a withdrawal for each category that shares the note and tags.
+    async fn add_expense(
+        &self,
+        expense: &AddArgs,
+        amount: f64,
+        source_account_id: i64,
+        username: &str,
+        timestamp: SystemTime,
+    ) -> anyhow::Result<Option<String>> {
+        // Firefly creates all of the splits in a group, or none of them
+        self.post_transaction(self.expense_transactions(
+            expense,
+            amount,
+            source_account_id,
+            username,
+            timestamp,
+        ))
+        .await
+    }
+
+    /// Builds the withdrawals of an expense, one for each of its categories, in one group. A
+    /// refund is a deposit to the account instead, from the payee or `default_revenue_account`.
+    fn expense_transactions(
+        &self,
+        expense: &AddArgs,
+        amount: f64,
+        source_account_id: i64,
+        username: &str,
+        timestamp: SystemTime,
+    ) -> Transactions {
+        let note = expense.note.clone().or_else(|| self.default_notes.pick());
+        if expense.refund {
+            return Transactions::new(
+                Transaction::deposit(
+                    expense.category.clone(),
+                    amount,
+                    timestamp.into(),
+                    expense
+                        .payee
+                        .clone()
+                        .unwrap_or_else(|| self.config.default_revenue_account.clone()),
+                    source_account_id,
+                    username.to_string(),
+                    self.config.attribution(),
+                    note,
+                    expense.tags.clone(),
+                )
+                .in_currency(expense.currency.as_deref()),
+            );
+        }
+
+        let mut transactions = vec![self.expense_withdrawal(
+            expense,
+            &expense.category,
+            amount,
+            source_account_id,
+            username,
+            timestamp,
+            note.clone(),
+        )];
+        for split in &expense.splits {
+            transactions.push(self.expense_withdrawal(
+                expense,
+                &split.category,
+                split.amount,
+                source_account_id,
+                username,
+                timestamp,
+                note.clone(),
+            ));
+        }
+
+        let group_title = expense
+            .category_amounts()
+            .map(|(category, _)| category)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Transactions::batch(group_title, transactions)
+    }
+
+    /// Builds the withdrawal for one of the expense's categories, or a share of it, paid to the
+    /// expense's payee or, if it has none, the general expense account
+    #[allow(clippy::too_many_arguments)]
+    fn expense_withdrawal(
+        &self,
+        expense: &AddArgs,
+        category: &str,
+        amount: f64,
+        source_account_id: i64,
+        username: &str,
+        timestamp: SystemTime,
+        note: Option<String>,
+    ) -> Transaction {
+        Transaction::withdrawal(
+            category.to_string(),
+            amount,
+            timestamp.into(),
+            source_account_id,
+            expense
+                .payee
+                .clone()
+                .unwrap_or_else(|| FIREFLY_GENERAL_EXPENSE.to_string()),
+            username.to_string(),
+            self.config.attribution(),
+            &self.config.description_template,
+            note,
+            expense.tags.clone(),
+        )
+        .in_currency(expense.currency.as_deref())
+    }
+
+    /// Adds income to the source account from the deposit's revenue account, or
+    /// `default_revenue_account` if it doesn't name one
+    async fn add_deposit(
+        &self,
+        args: DepositArgs,
+        destination_account_id: i64,
+        username: &str,
+        timestamp: SystemTime,
+    ) -> anyhow::Result<Option<String>> {
+        let transaction = self
+            .deposit_transaction(args, destination_account_id, username, timestamp)
+            .await;
+        self.post_transaction(transaction).await
+    }
+
+    /// Builds the deposit, paid from the revenue account that best matches the deposit's source
+    async fn deposit_transaction(
+        &self,
+        args: DepositArgs,
+        destination_account_id: i64,
+        username: &str,
+        timestamp: SystemTime,
+    ) -> Transactions {
+        let source = args
+            .source
+            .unwrap_or_else(|| self.config.default_revenue_account.clone());
+        let source = match self.list_revenue_accounts().await {
+            Ok(accounts) => resolve_revenue_account(&source, &accounts),
+            Err(e) => {
+                warn!("Failed to list revenue accounts: {e}");
+                source
+            }
+        };
+
+        let currency = args.add.currency.clone();
+        Transactions::new(
+            Transaction::deposit(
+                args.add.category,
+                args.add.amount,
+                timestamp.into(),
+                source,
+                destination_account_id,
+                username.to_string(),
+                self.config.attribution(),
+                args.add.note,
+                args.add.tags,
+            )
+            .in_currency(currency.as_deref()),
+        )
+    }
+
+    /// Adds the transaction to Firefly, retrying if Firefly is briefly unavailable. Returns the id
+    /// of the created transaction group, or `None` if Firefly's response didn't include it.
+    async fn post_transaction(&self, transaction: Transactions) -> anyhow::Result<Option<String>> {
+        let id = with_retries(
+            "add transaction",
+            self.config.firefly_max_retries,
+            FIREFLY_RETRY_DELAY,
+            is_transient_post,
+            || async {
+                let response = self
+                    .send_firefly(
+                        self.http_client
+                            .post(self.config.api_url(FIREFLY_TRANSACTIONS_API))
+                            .json(&transaction),
+                    )
+                    .await?;
+                read_transaction_response(response).await
+            },
+        )
+        .await?;
+        self.results
+            .lock()
+            .unwrap()
+            .invalidate(Dependency::Transactions);
+
+        Ok(id)
+    }
+
+    fn with_prefix(&self, text: &str) -> String {
+        with_prefix(text, &self.config.command_prefix)
+    }
+
+    /// Responds to the command that failed with the message, if any, and a reaction for the kind
+    /// of error, so that every failure looks the same in the room
+    async fn respond_error(
+        &self,
+        kind: ErrorKind,
+        message: Option<String>,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        for response in error_responses(kind, message, &self.config) {
+            match response {
+                Response::Message(message) => send_message(message, room).await?,
+                Response::Reaction(reaction) => {
+                    send_reaction(reaction, event_id.clone(), room).await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remembers the transaction that was added by the events, and as the last transaction the
+    /// sender added in the room, if Firefly returned its id
+    fn record_added(
+        &self,
+        event_ids: impl IntoIterator<Item = OwnedEventId>,
+        transaction_id: Option<String>,
+        sender: &UserId,
+        room: &Joined,
+    ) {
+        if let Some(transaction_id) = transaction_id {
+            let now = Local::now();
+            for event_id in event_ids {
+                if let Err(e) = self.added.record(event_id.as_str(), &transaction_id, now) {
+                    warn!("Failed to record the transaction added by {event_id}: {e}");
+                }
+            }
+            if let Err(e) = self.added.record_last(
+                room.room_id().as_str(),
+                sender.as_str(),
+                &transaction_id,
+                now,
+            ) {
+                warn!("Failed to record the last transaction added by {sender}: {e}");
+            }
+        }
+    }
+
+    /// Changes the last transaction the sender added in the room. An add that was split across
+    /// categories has its first split changed.
+    async fn handle_edit(
+        &self,
+        mut args: EditArgs,
+        sender: &UserId,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let Some(transaction_id) =
+            self.added
+                .last_transaction_id(room.room_id().as_str(), sender.as_str(), Local::now())
+        else {
+            return self
+                .respond_error(
+                    ErrorKind::Parse,
+                    Some("You have no recent transaction to edit".to_string()),
+                    event_id,
+                    room,
+                )
+                .await;
+        };
+
+        let path = format!("{FIREFLY_TRANSACTIONS_API}/{transaction_id}");
+        let group = match self
+            .firefly_get::<Single<TransactionGroup>>(&path, &[])
+            .await
+        {
+            Ok(group) => group,
+            Err(e) if is_status(&e, StatusCode::NOT_FOUND) => {
+                if let Err(e) = self.added.remove_transaction(&transaction_id) {
+                    warn!("Failed to forget missing transaction {transaction_id}: {e}");
+                }
+                return self
+                    .respond_error(
+                        ErrorKind::Parse,
+                        Some("Your last transaction no longer exists in Firefly".to_string()),
+                        event_id,
+                        room,
+                    )
+                    .await;
+            }
+            Err(e) => {
+                error!("Failed to get transaction {transaction_id}: {e}");
+                return self
+                    .respond_error(
+                        ErrorKind::Upstream,
+                        Some(failure_message(&e, "Failed to get transaction")),
+                        event_id,
+                        room,
+                    )
+                    .await;
+            }
+        };
+
+        match &mut args {
+            EditArgs::Category(category) => {
+                if let Some(existing) = self
+                    .mirror
+                    .data()
+                    .categories
+                    .iter()
+                    .find(|existing| existing.eq_ignore_ascii_case(category))
+                {
+                    *category = existing.clone();
+                }
+            }
+            // The person's tag would otherwise be lost along with the old tags
+            EditArgs::Tags(tags) if self.config.attribution().in_tags() => {
+                tags.push(self.resolve_person_tag(sender, room).await);
+            }
+            _ => {}
+        }
+
+        let journal_id = group
+            .data
+            .attributes
+            .transactions
+            .into_iter()
+            .next()
+            .and_then(|split| split.transaction_journal_id);
+        match self
+            .firefly_put::<_, serde_json::Value>(&path, &args.update(journal_id))
+            .await
+        {
+            Ok(_) => {
+                info!("Edited transaction {transaction_id}: {args:?}");
+                self.results
+                    .lock()
+                    .unwrap()
+                    .invalidate(Dependency::Transactions);
+                send_reaction(EDITED_REACTION.to_owned(), event_id, room).await
+            }
+            Err(e) => {
+                error!("Failed to edit transaction {transaction_id}: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to edit transaction")),
+                    event_id,
+                    room,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Deletes the transaction that was added by the command, or the bot's confirmation, that the
+    /// `!delete` replies to
+    async fn handle_delete(
+        &self,
+        reply_to: Option<OwnedEventId>,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let Some(reply_to) = reply_to else {
+            return send_message(
+                format!("Reply to an add or its confirmation with {DELETE_CMD} to delete it"),
+                room,
+            )
+            .await;
+        };
+
+        let transaction_id = self.added.transaction_id(reply_to.as_str(), Local::now());
+        let Some(transaction_id) = transaction_id else {
+            return send_message(
+                "That message didn't add a transaction that can be deleted".to_string(),
+                room,
+            )
+            .await;
+        };
+
+        match self.delete_transaction(&transaction_id).await {
+            Ok(()) => {
+                info!("Deleted transaction {transaction_id}");
+                if let Err(e) = self.added.remove_transaction(&transaction_id) {
+                    warn!("Failed to forget deleted transaction {transaction_id}: {e}");
+                }
+                send_reaction(DELETED_REACTION.to_owned(), event_id, room).await
+            }
+            Err(e) => {
+                error!("Failed to delete transaction {transaction_id}: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to delete transaction")),
+                    event_id,
+                    room,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn delete_transaction(&self, id: &str) -> anyhow::Result<()> {
+        let response = self
+            .send_firefly(
+                self.http_client.delete(
+                    self.config
+                        .api_url(&format!("{FIREFLY_TRANSACTIONS_API}/{id}")),
+                ),
+            )
+            .await?;
+        check_maintenance(response).await?;
+
+        self.results
+            .lock()
+            .unwrap()
+            .invalidate(Dependency::Transactions);
+        Ok(())
+    }
+
+    /// Creates a piggy bank for the goal, linked to the source account. Returns the name of the
+    /// created piggy bank, or `None` if one with the same name already exists.
+    async fn add_goal(&self, goal: &GoalArgs) -> anyhow::Result<Option<String>> {
+        let piggy_banks: Vec<PiggyBank> =
+            self.firefly_get_all(FIREFLY_PIGGY_BANKS_API, &[]).await?;
+        if piggy_banks
+            .iter()
+            .any(|piggy_bank| piggy_bank.attributes.name.eq_ignore_ascii_case(&goal.name))
+        {
+            return Ok(None);
+        }
+
+        let body = NewPiggyBank::new(goal, self.config.firefly_source_account_id);
+        match self
+            .firefly_post::<_, Single<PiggyBank>>(FIREFLY_PIGGY_BANKS_API, &body)
+            .await
+        {
+            Ok(created) => Ok(Some(created.data.attributes.name)),
+            // Firefly rejects duplicate names, which happens if one was created since listing
+            Err(e) if is_status(&e, StatusCode::UNPROCESSABLE_ENTITY) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates the category in Firefly and adds it to the mirror. Returns the name of the created
+    /// category.
+    async fn create_category(&self, name: &str) -> anyhow::Result<String> {
+        let body = NewCategory {
+            name: name.to_string(),
+        };
+        let created: Single<Category> = self.firefly_post(FIREFLY_CATEGORIES_API, &body).await?;
+        let name = created.data.attributes.name;
+
+        let mut data = self.mirror.data();
+        if !data.categories.contains(&name) {
+            data.categories.push(name.clone());
+            data.categories.sort();
+            self.mirror.update(data)?;
+        }
+        self.results
+            .lock()
+            .unwrap()
+            .invalidate(Dependency::Categories);
+
+        Ok(name)
+    }
+
+    /// Returns the exchange rates Firefly knows about. They are cached briefly.
+    async fn exchange_rates(&self) -> anyhow::Result<Vec<ExchangeRate>> {
+        if let Some((rates, fetched_at)) = self.rates_cache.lock().unwrap().as_ref() {
+            if fetched_at.elapsed() < RATES_CACHE_TTL {
+                return Ok(rates.clone());
+            }
+        }
+
+        let page: Page<ExchangeRate> = self.firefly_get(FIREFLY_EXCHANGE_RATES_API, &[]).await?;
+        *self.rates_cache.lock().unwrap() = Some((page.data.clone(), Instant::now()));
+
+        Ok(page.data)
+    }
+
+    /// Replies with the amount converted to the other currency, unless there is no
+    /// `rates_api_url` to get the rate from
+    async fn handle_convert(
+        &self,
+        args: ConvertArgs,
+        event_id: OwnedEventId,
+        room: &Joined,
+    ) -> anyhow::Result<()> {
+        let Some(rates_api_url) = &self.config.rates_api_url else {
+            send_message(
+                "Currency conversion is disabled. Set rates_api_url to enable it".to_string(),
+                room,
+            )
+            .await?;
+            return Ok(());
+        };
+
+        match self.convert_currency(rates_api_url, &args).await {
+            Ok(Some(converted)) => send_message(format_conversion(&args, converted), room).await?,
+            Ok(None) => {
+                send_message(
+                    format!(
+                        "There is no exchange rate from {} to {}",
+                        args.from, args.to
+                    ),
+                    room,
+                )
+                .await?
+            }
+            Err(e) => {
+                error!("Failed to convert currency: {e}");
+                self.respond_error(
+                    ErrorKind::Upstream,
+                    Some(failure_message(&e, "Failed to convert currency")),
+                    event_id,
+                    room,
+                )
+                .await?
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the amount with the rate from the rates API, which is asked for the rates of the
+    /// `from` currency as `?from=<Code>`. The rates are cached briefly. Returns `None` if the API
+    /// has no rate to the `to` currency.
+    async fn convert_currency(
+        &self,
+        rates_api_url: &str,
+        args: &ConvertArgs,
+    ) -> anyhow::Result<Option<f64>> {
+        if args.from == args.to {
+            return Ok(Some(args.amount));
+        }
+
+        let cached = self
+            .conversion_rates
+            .lock()
+            .unwrap()
+            .get(&args.from)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < RATES_CACHE_TTL)
+            .map(|(rates, _)| rates.clone());
+        let rates = match cached {
+            Some(rates) => rates,
+            None => {
+                let response: ConversionRates = self
+                    .http_client
+                    .get(rates_api_url)
+                    .query(&[("from", &args.from)])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                self.conversion_rates
+                    .lock()
+                    .unwrap()
+                    .insert(args.from.clone(), (response.clone(), Instant::now()));
+                response
+            }
+        };
+
+        Ok(rates.rates.get(&args.to).map(|rate| args.amount * rate))
+    }
+
+    /// Returns the categories from the mirror, fetching them from Firefly first if they weren't
+    /// fetched in the last `category_cache_ttl_secs`
+    async fn categories(&self) -> anyhow::Result<Vec<String>> {
+        let ttl = Duration::from_secs(self.config.category_cache_ttl_secs);
+        let fresh = self
+            .categories_fetched_at
+            .lock()
+            .unwrap()
+            .is_some_and(|fetched_at| fetched_at.elapsed() < ttl);
+
+        if fresh && self.mirror.is_synced() {
+            Ok(self.mirror.data().categories)
+        } else if self.mirror.is_synced() {
+            self.refresh_categories().await
+        } else {
+            Ok(self.refresh_mirror().await?.categories)
+        }
+    }
+
+    /// Fetches the latest categories from Firefly into the mirror
+    async fn refresh_categories(&self) -> anyhow::Result<Vec<String>> {
+        let categories = self.list_categories().await?;
+        *self.categories_fetched_at.lock().unwrap() = Some(Instant::now());
+
+        let mut data = self.mirror.data();
+        if data.categories != categories {
+            data.categories = categories.clone();
+            self.mirror.update(data)?;
+            self.results
+                .lock()
+                .unwrap()
+                .invalidate(Dependency::Categories);
+        }
+
+        Ok(categories)
+    }
+
+    /// Returns the tags that Firefly didn't have before they were added, and adds them to the
+    /// mirror so that they're only reported once. The tags are compared with the mirror, which is
+    /// refreshed every `mirror_refresh_secs`, so that adds don't request the tags from Firefly.
+    async fn new_tags(&self, tags: &[String]) -> Vec<String> {
+        let known = match self.tags().await {
+            Ok(known) => known,
+            Err(e) => {
+                warn!("Failed to list tags to check for new ones: {e}");
+                return Vec::new();
+            }
+        };
+
+        let new_tags: Vec<String> = tags
+            .iter()
+            .filter(|tag| !known.contains(tag))
+            .cloned()
+            .collect();
+        if !new_tags.is_empty() {
+            let mut data = self.mirror.data();
+            data.tags.extend(new_tags.iter().cloned());
+            if let Err(e) = self.mirror.update(data) {
+                warn!("Failed to add new tags to the mirror: {e}");
+            }
+            self.results
+                .lock()
+                .unwrap()
+                .invalidate(Dependency::Transactions);
+        }
+        new_tags
+    }
+
+    async fn tags(&self) -> anyhow::Result<Vec<String>> {
+        if self.mirror.is_synced() {
+            Ok(self.mirror.data().tags)
+        } else {
+            Ok(self.refresh_mirror().await?.tags)
+        }
+    }
+
+    /// Fetches the latest categories, accounts, and tags from Firefly into the mirror
+    async fn refresh_mirror(&self) -> anyhow::Result<MirrorData> {
+        let data = MirrorData {
+            categories: self.list_categories().await?,
+            accounts: self.list_accounts().await?,
+            tags: self.list_tags().await?,
+            synced_at: Some(Local::now()),
+        };
+        *self.categories_fetched_at.lock().unwrap() = Some(Instant::now());
+
+        self.mirror.update(data.clone())?;
+        self.results
+            .lock()
+            .unwrap()
+            .invalidate(Dependency::Categories);
+        debug!("Refreshed mirror: {data:?}");
+
+        Ok(data)
+    }
+
+    async fn get_account_balance(&self, account_id: i64) -> anyhow::Result<AccountBalance> {
+        let account: SingleAccountBalance = self
+            .firefly_get(&format!("{FIREFLY_ACCOUNTS_API}/{account_id}"), &[])
+            .await?;
+        Ok(account.data)
+    }
+
+    /// Returns all of the asset accounts in Firefly
+    async fn list_accounts(&self) -> anyhow::Result<Vec<MirroredAccount>> {
+        let accounts: Vec<Account> = self
+            .firefly_get_all(FIREFLY_ACCOUNTS_API, &[("type", "asset".to_string())])
+            .await?;
+
+        accounts
+            .into_iter()
+            .map(|account| {
+                Ok(MirroredAccount {
+                    id: i64::from_str(&account.id)?,
+                    name: account.attributes.name,
+                })
+            })
+            .collect()
+    }
+
+    async fn list_revenue_accounts(&self) -> anyhow::Result<Vec<String>> {
+        let accounts: Vec<Account> = self
+            .firefly_get_all(FIREFLY_ACCOUNTS_API, &[("type", "revenue".to_string())])
+            .await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| account.attributes.name)
+            .collect())
+    }
+
+    async fn list_tags(&self) -> anyhow::Result<Vec<String>> {
+        let tags: Vec<Tag> = self.firefly_get_all(FIREFLY_TAGS_API, &[]).await?;
+        Ok(tags.into_iter().map(|tag| tag.attributes.tag).collect())
+    }
+
+    /// Returns the withdrawals tagged with `person` between `start` and `end`, inclusive
+    async fn list_expenses(
+        &self,
+        person: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> anyhow::Result<Vec<TransactionSplit>> {
+        Ok(self
+            .list_withdrawals(start, end)
+            .await?
+            .into_iter()
+            .filter(|split| split.tags.iter().any(|tag| tag == person))
+            .collect())
+    }
+
+    /// Returns all withdrawals between `start` and `end`, inclusive
+    async fn list_withdrawals(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> anyhow::Result<Vec<TransactionSplit>> {
+        self.list_transactions("withdrawal", start, end).await
+    }
+
+    /// Returns the transactions of the Firefly type, eg: "withdrawal" or "all", between `start` and
+    /// `end`, inclusive
+    async fn list_transactions(
+        &self,
+        transaction_type: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> anyhow::Result<Vec<TransactionSplit>> {
+        let groups: Vec<TransactionGroup> = self
+            .firefly_get_all(
+                FIREFLY_TRANSACTIONS_API,
+                &[
+                    ("type", transaction_type.to_string()),
+                    ("start", start.to_string()),
+                    ("end", end.to_string()),
+                ],
+            )
+            .await?;
+
+        Ok(groups
+            .into_iter()
+            .flat_map(|group| group.attributes.transactions)
+            .collect())
+    }
+
+    /// Returns the account's last `count` transactions, newest first. Only the first page is
+    /// requested, with Firefly's page size set to `count`.
+    async fn list_recent_transactions(
+        &self,
+        account_id: i64,
+        count: usize,
+    ) -> anyhow::Result<Vec<TransactionSplit>> {
+        let page: Page<TransactionGroup> = self
+            .firefly_get(
+                &format!("{FIREFLY_ACCOUNTS_API}/{account_id}/transactions"),
+                &[("limit", count.to_string()), ("page", "1".to_string())],
+            )
+            .await?;
+
+        Ok(page
+            .data
+            .into_iter()
+            .flat_map(|group| group.attributes.transactions)
+            .take(count)
+            .collect())
+    }
+
+    async fn firefly_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<T> {
+        let response = self
+            .send_firefly(self.http_client.get(self.config.api_url(path)).query(query))
+            .await?;
+
+        Ok(check_maintenance(response).await?.json().await?)
+    }
+
+    async fn firefly_post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> anyhow::Result<T> {
+        let response = self
+            .send_firefly(self.http_client.post(self.config.api_url(path)).json(body))
+            .await?;
+
+        Ok(check_maintenance(response).await?.json().await?)
+    }
+
+    async fn firefly_put<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> anyhow::Result<T> {
+        let response = self
+            .send_firefly(self.http_client.put(self.config.api_url(path)).json(body))
+            .await?;
+
+        Ok(check_maintenance(response).await?.json().await?)
+    }
+
+    /// Sends the request to Firefly with the API key, and records whether it succeeded and how
+    /// long it took in the metrics
+    async fn send_firefly(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        let started = Instant::now();
+        let response = request
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.config.firefly_api_key),
+            )
+            .send()
+            .await;
+        let success = matches!(&response, Ok(response) if response.status().is_success());
+        self.metrics
+            .record_firefly_request(success, started.elapsed());
+        response
+    }
+
+    /// Requests every page of a paginated Firefly list endpoint and returns the combined results
+    async fn firefly_get_all<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<Vec<T>> {
+        collect_pages(|page_num| async move {
+            let mut page_query = query.to_vec();
+            page_query.push(("page", page_num.to_string()));
+            self.firefly_get(path, &page_query).await
+        })
+        .await
+    }
+
+    async fn list_categories(&self) -> anyhow::Result<Vec<String>> {
+        let categories: Vec<Category> = with_retries(
+            "list categories",
+            self.config.firefly_max_retries,
+            FIREFLY_RETRY_DELAY,
+            is_transient,
+            || self.firefly_get_all(FIREFLY_CATEGORIES_API, &[]),
+        )
+        .await?;
+
+        Ok(categories
+            .into_iter()
+            .map(|cat| cat.attributes.name)
+            .collect())
+    }
+}
+
+impl Cmd {
+    fn parse(input: &str, config: &Config) -> anyhow::Result<Self> {
+        let note_mode = config.note_mode;
+        let cmd_end = input.find(' ').unwrap_or(input.len());
+        let cmd_str = &input[..cmd_end];
+        let cmd_args = if cmd_end == input.len() {
+            ""
+        } else {
+            &input[cmd_end + 1..]
+        };
+
+        let cmd = match cmd_str {
+            HELP_CMD if cmd_args.trim() == "examples" => Ok(Cmd::HelpExamples),
+            HELP_CMD if cmd_args.trim().is_empty() => Ok(Cmd::Help(None)),
+            HELP_CMD => {
+                let name = cmd_args.trim();
+                let name = name.strip_prefix(&config.command_prefix).unwrap_or(name);
+                match find_command(name) {
+                    Some(command) => Ok(Cmd::Help(Some(command))),
+                    None => Err(anyhow!(
+                        "Unknown command: {name}. Use {HELP_CMD} to list the commands"
+                    )),
+                }
+            }
+            HISTORY_CMD => match cmd_args.trim() {
+                "" => Ok(Cmd::History),
+                "commands" => Ok(Cmd::CommandHistory),
+                _ => Err(anyhow!("{INVALID_ARGS} Usage: {HISTORY_USAGE}")),
+            },
+            PING_CMD => Ok(Cmd::Ping),
+            BALANCE_CMD => Ok(Cmd::Balance),
+            BUDGET_CMD => Ok(Cmd::Budget),
+            DELETE_CMD => Ok(Cmd::Delete),
+            EDIT_CMD => Ok(Cmd::Edit(EditArgs::parse(cmd_args, config.decimal_comma)?)),
+            BALANCE_HISTORY_CMD => Ok(Cmd::BalanceHistory(BalanceHistoryArgs::parse(cmd_args)?)),
+            CATEGORIES_CMD => match cmd_args.trim() {
+                "" => Ok(Cmd::Categories {
+                    refresh: false,
+                    search: None,
+                }),
+                "refresh" => Ok(Cmd::Categories {
+                    refresh: true,
+                    search: None,
+                }),
+                search => Ok(Cmd::Categories {
+                    refresh: false,
+                    search: Some(search.to_string()),
+                }),
+            },
+            TAGS_CMD => Ok(Cmd::Tags),
+            ACCOUNTS_CMD => Ok(Cmd::Accounts),
+            CATEGORY_TREE_CMD => Ok(Cmd::CategoryTree),
+            CATEGORY_EXPORT_CMD => Ok(Cmd::CategoryExport(CategoryExportArgs::parse(cmd_args)?)),
+            LIMIT_SET_CMD => Ok(Cmd::LimitSet(LimitSetArgs::parse(cmd_args)?)),
+            MONTH_SET_CMD => match u32::from_str(cmd_args.trim()) {
+                Ok(day) if is_valid_start_day(day) => Ok(Cmd::MonthSet(day)),
+                _ => Err(anyhow!(
+                    "{INVALID_ARGS} The day must be between 1 and 31. Usage: {MONTH_SET_USAGE}"
+                )),
+            },
+            DIAG_CMD if cmd_args.trim() == "dump" => Ok(Cmd::DiagDump),
+            DIAG_CMD => Err(anyhow!("{INVALID_ARGS} Usage: {DIAG_USAGE}")),
+            NET_CMD => Ok(Cmd::Net(NetArgs::parse(cmd_args)?)),
+            PAUSE_USER_CMD => Ok(Cmd::PauseUser(PauseUserArgs::parse(cmd_args)?)),
+            UNPAUSE_USER_CMD => Ok(Cmd::UnpauseUser(parse_unpause_user(cmd_args)?)),
+            RATES_CMD => Ok(Cmd::Rates),
+            CONVERT_CMD => Ok(Cmd::Convert(ConvertArgs::parse(cmd_args)?)),
+            RECENT_CMD => Ok(Cmd::Recent(RecentArgs::parse(cmd_args)?)),
+            REFRESH_CMD => Ok(Cmd::Refresh),
+            REPORT_LINK_CMD => Ok(Cmd::ReportLink(ReportLinkArgs::parse(cmd_args)?)),
+            REMIND_CMD => Ok(Cmd::Remind(RemindArgs::parse(cmd_args)?)),
+            REMINDERS_CMD => Ok(Cmd::Reminders),
+            RETRY_CMD => Ok(Cmd::Retry),
+            SETTLE_CMD => Ok(Cmd::Settle(SettleArgs::parse(cmd_args)?)),
+            SPEND_CMD => Ok(Cmd::Spend(SpendArgs::parse(cmd_args)?)),
+            SPLIT_CMD => Ok(Cmd::Split(SplitArgs::parse(
+                cmd_args,
+                note_mode,
+                config.decimal_comma,
+            )?)),
+            SUMMARY_CMD => Ok(Cmd::Summary(SummaryArgs::parse(cmd_args)?)),
+            SUBSCRIBE_CMD => match Frequency::parse(cmd_args) {
+                Some(frequency) => Ok(Cmd::Subscribe(frequency)),
+                None => Err(anyhow!("{INVALID_ARGS} Usage: {SUBSCRIBE_USAGE}")),
+            },
+            UNSUBSCRIBE_CMD => Ok(Cmd::Unsubscribe),
+            WHO_OWES_CMD => Ok(Cmd::WhoOwes),
+            TOP_CMD => Ok(Cmd::Top(TopArgs::parse(cmd_args)?)),
+            TRANSFER_CMD => Ok(Cmd::Transfer(TransferArgs::parse(cmd_args)?)),
+            ADD_CMD => Ok(Cmd::Add(AddArgs::parse(
+                cmd_args,
+                note_mode,
+                config.decimal_comma,
+            )?)),
+            DEPOSIT_CMD => Ok(Cmd::Deposit(DepositArgs::parse(
+                cmd_args,
+                note_mode,
+                config.decimal_comma,
+                config.default_income_category.as_deref(),
+            )?)),
+            GOAL_ADD_CMD => Ok(Cmd::AddGoal(GoalArgs::parse(cmd_args)?)),
+            _ => Err(anyhow!("Unknown command: {cmd_str}")),
+        };
+        cmd?.round_amounts(config.amount_rounding)
+    }
+
+    /// Applies the `amount_rounding` to the amounts of every command that takes an amount
+    fn round_amounts(mut self, rounding: AmountRounding) -> anyhow::Result<Self> {
+        match &mut self {
+            Cmd::Add(args)
+            | Cmd::Split(SplitArgs { add: args, .. })
+            | Cmd::Deposit(DepositArgs { add: args, .. }) => {
+                args.amount = round_amount(args.amount, rounding)?;
+                for split in &mut args.splits {
+                    split.amount = round_amount(split.amount, rounding)?;
+                }
+            }
+            Cmd::Edit(EditArgs::Amount(amount)) => *amount = round_amount(*amount, rounding)?,
+            Cmd::Transfer(args) => args.amount = round_amount(args.amount, rounding)?,
+            Cmd::Settle(args) => args.amount = round_amount(args.amount, rounding)?,
+            Cmd::AddGoal(goal) => goal.target = round_amount(goal.target, rounding)?,
+            Cmd::LimitSet(LimitSetArgs::Set { amount, .. }) => {
+                *amount = round_amount(*amount, rounding)?
+            }
+            Cmd::Convert(args) => args.amount = round_amount(args.amount, rounding)?,
+            _ => {}
+        }
+        Ok(self)
+    }
+
+    /// Checks the parsed arguments against the config
+    fn validate(&self, config: &Config) -> anyhow::Result<()> {
+        match self {
+            Cmd::Add(args) => {
+                args.validate_amount(config.allow_zero_amounts)?;
+                if !args.splits.is_empty()
+                    && infer_add_kind(&args.category, &args.tags, &config.income_keywords)
+                        == AddKind::Deposit
+                {
+                    return Err(anyhow!("Only expenses can be split across categories"));
+                }
+                if args.refund
+                    && infer_add_kind(&args.category, &args.tags, &config.income_keywords)
+                        == AddKind::Deposit
+                {
+                    return Err(anyhow!("Only expenses can be refunded"));
+                }
+                args.category_amounts().try_for_each(|(category, _)| {
+                    check_required_tags(&config.required_tags, category, &args.tags)
+                })
+            }
+            Cmd::Split(SplitArgs { add: args, .. })
+            | Cmd::Deposit(DepositArgs { add: args, .. })
+                if args.dry_run =>
+            {
+                Err(anyhow!("{DRY_RUN_FLAG} is only supported by {ADD_CMD}"))
+            }
+            Cmd::Split(SplitArgs { add: args, .. })
+            | Cmd::Deposit(DepositArgs { add: args, .. })
+                if args.refund =>
+            {
+                Err(anyhow!("Refunds can only be added with {ADD_CMD}"))
+            }
+            Cmd::Split(SplitArgs {
+                participants,
+                add: args,
+            }) => {
+                if !args.splits.is_empty() {
+                    return Err(anyhow!("{SPLIT_CMD} can't be split across categories"));
+                }
+                args.validate_amount(config.allow_zero_amounts)?;
+                // Every share must be at least a cent, or it would be added as a zero amount
+                if args.amount > 0.0
+                    && ((args.amount * 100.0).round() as usize) < participants.len()
+                {
+                    return Err(anyhow!(
+                        "{} is too little to split between {} people",
+                        format_amount(args.amount, None),
+                        participants.len()
+                    ));
+                }
+                check_required_tags(&config.required_tags, &args.category, &args.tags)
+            }
+            Cmd::Deposit(DepositArgs { add: args, .. }) => {
+                if !args.splits.is_empty() {
+                    return Err(anyhow!("{DEPOSIT_CMD} can't be split across categories"));
+                }
+                args.validate_amount(config.allow_zero_amounts)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AddArgs {
+    fn validate_amount(&self, allow_zero: bool) -> anyhow::Result<()> {
+        if self.category_amounts().any(|(_, amount)| amount == 0.0) && !allow_zero {
+            return Err(anyhow!(
+                "Amount must be greater than zero. Zero amounts are not enabled."
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse(args: &str, note_mode: NoteMode, decimal_comma: bool) -> anyhow::Result<Self> {
+        let (dry_run, args) = split_dry_run(args);
+        let Some((category, rest)) = args.split_once(':') else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {ADD_USAGE}"));
+        };
+
+        let category = category.trim();
+        if category.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {ADD_USAGE}"));
+        }
+
+        let (mut splits, currency, rest) = parse_category_amounts(category, rest, decimal_comma)?;
+        let CategoryAmount { category, amount } = splits.remove(0);
+        // A negative amount is a refund of an earlier expense, eg: `!add Groceries: -12.50 refund`
+        let refund = amount < 0.0;
+        if (refund || splits.iter().any(|split| split.amount < 0.0)) && !splits.is_empty() {
+            return Err(anyhow!("Refunds can't be split across categories"));
+        }
+        let (date, rest) = split_date(rest)?;
+        let (payee, rest) = split_payee(rest);
+        let (note, tags) = match note_mode {
+            NoteMode::Standard => split_note_and_tags(rest),
+            NoteMode::Strict => split_strict_note_and_tags(rest),
+        };
+
+        Ok(Self {
+            category,
+            amount: amount.abs(),
+            date,
+            note,
+            tags,
+            currency,
+            payee,
+            splits,
+            dry_run,
+            refund,
+        })
+    }
+
+    /// Parses the shorthand without a colon, `<Amount> [@Date] [Category] [#Tag...]`, in which
+    /// the category is everything before the tags, or `default_category` if there is nothing
+    /// there. There is no note.
+    fn parse_shorthand(
+        args: &str,
+        decimal_comma: bool,
+        default_category: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let (amount, currency, rest) = parse_amount(args, decimal_comma)?;
+        let (date, rest) = split_date(rest)?;
+        let (category, tags) = split_note_and_tags(rest);
+
+        let Some(category) = category.or_else(|| default_category.map(str::to_string)) else {
+            return Err(anyhow!("{INVALID_ARGS} No category was given"));
+        };
+
+        // Like the colon form, a negative amount is a refund, which `Cmd::validate` checks
+        Ok(Self {
+            category,
+            amount: amount.abs(),
+            date,
+            note: None,
+            tags,
+            currency,
+            payee: None,
+            splits: Vec::new(),
+            dry_run: false,
+            refund: amount < 0.0,
+        })
+    }
+
+    /// The category and amount of each split, starting with the first
+    fn category_amounts(&self) -> impl Iterator<Item = (&str, f64)> {
+        iter::once((self.category.as_str(), self.amount)).chain(
+            self.splits
+                .iter()
+                .map(|split| (split.category.as_str(), split.amount)),
+        )
+    }
+
+    /// Describes where the amounts went, eg: `$40.00 to Groceries and $15.00 to Household`
+    fn describe_amounts(&self) -> String {
+        self.category_amounts()
+            .map(|(category, amount)| format!("{} to {category}", format_amount(amount, None)))
+            .collect::<Vec<_>>()
+            .join(" and ")
+    }
+}
+
+/// Parses the amount after an add's first category, along with the further categories and amounts
+/// that follow it, separated by commas, eg: `40, Household: 15` after `Groceries:`. The amounts
+/// share one currency. Returns each category and amount, the currency, and the rest of the text.
+fn parse_category_amounts<'a>(
+    category: &str,
+    text: &'a str,
+    decimal_comma: bool,
+) -> anyhow::Result<(Vec<CategoryAmount>, Option<String>, Option<&'a str>)> {
+    let mut amounts = Vec::new();
+    let mut categories = vec![category];
+    let mut text = text;
+    loop {
+        let (word, rest) = split_word(text);
+        let next = word
+            .strip_suffix(',')
+            .zip(rest.and_then(|rest| rest.split_once(':')));
+        match next {
+            Some((amount, (category, rest)))
+                if !amount.is_empty()
+                    && !category.trim().is_empty()
+                    && !category.contains(['#', '@', '>']) =>
+            {
+                amounts.push(amount);
+                categories.push(category.trim());
+                text = rest;
+            }
+            _ => break,
+        }
+    }
+
+    let (last, mut currency, rest) = parse_amount(text, decimal_comma)?;
+    let mut values = Vec::with_capacity(amounts.len() + 1);
+    for amount in amounts {
+        let (value, amount_currency, _) = parse_amount(amount, decimal_comma)?;
+        if let Some(amount_currency) = amount_currency {
+            if currency.get_or_insert_with(|| amount_currency.clone()) != &amount_currency {
+                return Err(anyhow!("All of the amounts must be in the same currency"));
+            }
+        }
+        values.push(value);
+    }
+    values.push(last);
+
+    let category_amounts = categories
+        .into_iter()
+        .zip(values)
+        .map(|(category, amount)| CategoryAmount {
+            category: category.to_string(),
+            amount,
+        })
+        .collect();
+    Ok((category_amounts, currency, rest))
+}
+
+/// Parses the amount at the start of the text, and its currency. The currency is either a symbol
+/// before or after the amount, eg: `€50`, or a currency code after it, eg: `50 EUR`. Returns them
+/// along with the rest of the text, if there is any.
+fn parse_amount(
+    text: &str,
+    decimal_comma: bool,
+) -> anyhow::Result<(f64, Option<String>, Option<&str>)> {
+    let (amount, rest) = split_word(text);
+
+    let (amount, mut currency) = strip_currency_symbol(amount);
+    let amount = amount.strip_prefix('$').unwrap_or(amount);
+    if amount.is_empty() {
+        return Err(anyhow!("{INVALID_ARGS} Usage: {ADD_USAGE}"));
+    }
+    let normalized;
+    let amount = if decimal_comma && amount.contains(',') {
+        let Some(replaced) = normalize_decimal_comma(amount) else {
+            return Err(anyhow!("Invalid amount: {amount}"));
+        };
+        normalized = replaced;
+        normalized.as_str()
+    } else {
+        amount
+    };
+    // Sums are worked out to the cent, so that eg: `100/3` isn't taken for a typo
+    let Some(amount) = f64::from_str(amount)
+        .ok()
+        .or_else(|| arithmetic::evaluate(amount).map(|amount| (amount * 100.0).round() / 100.0))
+        .filter(|amount| amount.is_finite())
+    else {
+        return Err(anyhow!("Invalid amount: {amount}"));
+    };
+
+    let rest = match rest.map(split_word) {
+        Some((code, rest)) if currency.is_none() && is_currency_code(code) => {
+            currency = Some(code.to_string());
+            rest
+        }
+        _ => rest,
+    };
+
+    Ok((amount, currency, rest))
+}
+
+/// Applies the `amount_rounding` to an amount with more than two decimal places, by rounding it to
+/// two decimal places or rejecting it. Other amounts are kept.
+fn round_amount(amount: f64, rounding: AmountRounding) -> anyhow::Result<f64> {
+    let rounded = (amount * 100.0).round() / 100.0;
+    if rounding == AmountRounding::Round || (amount - rounded).abs() < 1e-9 {
+        Ok(rounded)
+    } else {
+        Err(anyhow!(
+            "Invalid amount: {amount}. Use at most two decimal places"
+        ))
+    }
+}
+
+/// Replaces the decimal comma in each number of the amount with a period, eg: `12,50` becomes
+/// `12.50`. Returns `None` if a number has more than one comma, or a period as well, or doesn't
+/// have one or two digits after its comma, because `1,000` may be a thousands separator.
+fn normalize_decimal_comma(amount: &str) -> Option<String> {
+    for number in amount.split(['+', '-', '*', '/']) {
+        if let Some((_, decimals)) = number.split_once(',') {
+            if number.contains('.')
+                || !(1..=2).contains(&decimals.len())
+                || !decimals.chars().all(|c| c.is_ascii_digit())
+            {
+                return None;
+            }
+        }
+    }
+    Some(amount.replace(',', "."))
+}
+
+/// Splits the first word from the text. Returns it along with the rest of the text, if there is any.
+fn split_word(text: &str) -> (&str, Option<&str>) {
+    let text = text.trim();
+    match text.split_once(' ') {
+        Some((word, rest)) if !rest.trim().is_empty() => (word, Some(rest.trim())),
+        Some((word, _)) => (word, None),
+        None => (text, None),
+    }
+}
+
+/// Removes a currency symbol from the start or end of the amount. Returns the rest of the amount
+/// along with the symbol's currency code.
+fn strip_currency_symbol(amount: &str) -> (&str, Option<String>) {
+    for (symbol, code) in CURRENCY_SYMBOLS {
+        if let Some(stripped) = amount
+            .strip_prefix(symbol)
+            .or_else(|| amount.strip_suffix(symbol))
+        {
+            return (stripped, Some(code.to_string()));
+        }
+    }
+    (amount, None)
+}
+
+/// Returns true if the word is a known ISO 4217 currency code, eg: `EUR`
+fn is_currency_code(word: &str) -> bool {
+    CURRENCY_CODES.contains(&word)
+}
+
+impl GoalArgs {
+    fn parse(args: &str) -> anyhow::Result<Self> {
+        let Some((name, target)) = args.split_once(':') else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {GOAL_ADD_USAGE}"));
+        };
+
+        let name = name.trim();
+        let target = target.trim();
+        let target = target.strip_prefix('$').unwrap_or(target);
+
+        if name.is_empty() || target.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {GOAL_ADD_USAGE}"));
+        }
+
+        match f64::from_str(target) {
+            Ok(target) if target.is_finite() && target > 0.0 => Ok(Self {
+                name: name.to_string(),
+                target,
+            }),
+            _ => Err(anyhow!("Invalid target: {target}")),
+        }
+    }
+}
+
+impl TopArgs {
+    fn parse(args: &str) -> anyhow::Result<Self> {
+        let mut parts = args.split_whitespace();
+
+        let count = match parts.next() {
+            Some(count) => match usize::from_str(count) {
+                Ok(count) if count > 0 => count.min(TOP_MAX_COUNT),
+                _ => return Err(anyhow!("Invalid count: {count}")),
+            },
+            None => TOP_DEFAULT_COUNT,
+        };
+
+        let days = parse_days(parts.next(), TOP_DEFAULT_DAYS, REPORT_MAX_DAYS)?;
+
+        if parts.next().is_some() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {TOP_USAGE}"));
+        }
+
+        Ok(Self { count, days })
+    }
+}
+
+/// Parses an optional positive number of days, which is `default` when it's missing and clamped to
+/// `max`
+fn parse_days(days: Option<&str>, default: i64, max: i64) -> anyhow::Result<i64> {
+    match days {
+        Some(days) => match i64::from_str(days) {
+            Ok(days) if days > 0 => Ok(days.min(max)),
+            _ => Err(anyhow!("Invalid days: {days}")),
+        },
+        None => Ok(default),
+    }
+}
+
+/// Returns the first day of a report over the last `days` days that ends on, and includes, `end`
+fn report_start(end: NaiveDate, days: i64) -> NaiveDate {
+    end - ChronoDuration::days(days - 1)
+}
+
+/// Splits a leading `@YYYY-MM-DD`, `@today`, or `@yesterday` from the text after an amount. The
+/// date is set to noon so that it falls on the same day in nearby timezones.
+fn split_date(rest: Option<&str>) -> anyhow::Result<(Option<DateTime<Local>>, Option<&str>)> {
+    let Some(rest) = rest else {
+        return Ok((None, None));
+    };
+    let (word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+    let Some(date) = word.strip_prefix('@') else {
+        return Ok((None, Some(rest)));
+    };
+
+    let invalid = || anyhow!("Invalid date: {word}. Use @YYYY-MM-DD, @today, or @yesterday");
+    let today = Local::now().date_naive();
+    let day = if date.eq_ignore_ascii_case("today") {
+        today
+    } else if date.eq_ignore_ascii_case("yesterday") {
+        today - ChronoDuration::days(1)
+    } else if date.starts_with(|c: char| c.is_ascii_digit()) {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?
+    } else {
+        // Not a date, eg: a note that starts with a mention
+        return Ok((None, Some(rest)));
+    };
+
+    let date = day
+        .and_hms_opt(12, 0, 0)
+        .and_then(|noon| noon.and_local_timezone(Local).earliest())
+        .ok_or_else(invalid)?;
+    let after = after.trim();
+    Ok((Some(date), (!after.is_empty()).then_some(after)))
+}
+
+/// Splits the payee, given with `>`, from the start of the text after an add's amount. The payee is
+/// either the word after the `>`, or enclosed in braces or quotes, eg: `>{Corner Store}` or
+/// `>"Corner Store"`.
+fn split_payee(rest: Option<&str>) -> (Option<String>, Option<&str>) {
+    let Some(after) = rest.and_then(|rest| rest.strip_prefix('>')) else {
+        return (None, rest);
+    };
+
+    let enclosed = match after.chars().next() {
+        Some('{') => after[1..].find('}'),
+        Some('"') => after[1..].find('"'),
+        _ => None,
+    };
+    let (payee, after) = match enclosed {
+        Some(end) => (&after[1..end + 1], &after[end + 2..]),
+        None => after.split_at(
+            after
+                .find(|c: char| c.is_whitespace() || c == '#')
+                .unwrap_or(after.len()),
+        ),
+    };
+
+    let payee = payee.trim();
+    if payee.is_empty() {
+        return (None, rest);
+    }
+    let after = after.trim();
+    (
+        Some(payee.to_string()),
+        (!after.is_empty()).then_some(after),
+    )
+}
+
+/// Splits the text after an add's amount into the note, which is the text before the first `#`,
+/// and the tags, which are separated by `#`
+fn split_note_and_tags(rest: Option<&str>) -> (Option<String>, Vec<String>) {
+    let Some(rest) = rest else {
+        return (None, Vec::new());
+    };
+
+    let (note, tags) = rest.split_at(rest.find('#').unwrap_or(rest.len()));
+    let note = note.trim();
+
+    (
+        if note.is_empty() {
+            None
+        } else {
+            Some(note.to_string())
+        },
+        parse_tags(tags),
+    )
+}
+
+/// Parses the tags in text that starts with a `#`. A tag is either the text up to the next `#`, or
+/// enclosed in braces or quotes, eg: `#{two words}` or `#"two words"`, in which case it may contain
+/// `#`s. Text between an enclosed tag and the next `#` is ignored.
+fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('#') {
+        let after = &rest[start + 1..];
+
+        let enclosed = match after.chars().next() {
+            Some('{') => after[1..].find('}'),
+            Some('"') => after[1..].find('"'),
+            _ => None,
+        };
+
+        let tag = match enclosed {
+            Some(end) => {
+                rest = &after[end + 2..];
+                &after[1..end + 1]
+            }
+            None => {
+                let end = after.find('#').unwrap_or(after.len());
+                rest = &after[end..];
+                &after[..end]
+            }
+        };
+
+        let tag = tag.trim();
+        if !tag.is_empty() {
+            tags.push(tag.to_string());
+        }
+    }
+
+    tags
+}
+
+/// Splits the text after an add's amount into the note, which is everything up to the first `#`,
+/// and the tags that follow. The note is kept verbatim, so its spacing and any `:`s are preserved.
+fn split_strict_note_and_tags(rest: Option<&str>) -> (Option<String>, Vec<String>) {
+    let Some(rest) = rest else {
+        return (None, Vec::new());
+    };
+
+    let tags_start = rest.find('#').unwrap_or(rest.len());
+    let note = rest[..tags_start].trim_end();
+    let tags = parse_tags(&rest[tags_start..]);
+
+    (
+        if note.is_empty() {
+            None
+        } else {
+            Some(note.to_string())
+        },
+        tags,
+    )
+}
+
+/// Groups categories into a tree by splitting their names on `separator`. Categories without the
+/// separator are top-level.
+fn build_category_tree(categories: &[String], separator: &str) -> Vec<CategoryNode> {
+    let mut roots: Vec<CategoryNode> = Vec::new();
+
+    for category in categories {
+        let mut level = &mut roots;
+
+        for part in category
+            .split(separator)
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let index = match level.iter().position(|node| node.name == part) {
+                Some(index) => index,
+                None => {
+                    level.push(CategoryNode {
+                        name: part.to_string(),
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            level = &mut level[index].children;
+        }
+    }
+
+    roots
+}
+
+fn render_category_tree(nodes: &[CategoryNode], depth: usize, output: &mut String) {
+    for node in nodes {
+        output.push('\n');
+        output.push_str(&"  ".repeat(depth - 1));
+        output.push_str(" - ");
+        output.push_str(&node.name);
+        render_category_tree(&node.children, depth + 1, output);
+    }
+}
+
+fn choose_person_tag(
+    sources: &[PersonSource],
+    display_name: Option<&str>,
+    user_tag: Option<&str>,
+    localpart: &str,
+) -> String {
+    sources
+        .iter()
+        .find_map(|source| {
+            match source {
+                PersonSource::DisplayName => display_name,
+                PersonSource::UserTag => user_tag,
+                PersonSource::Localpart => Some(localpart),
+            }
+            .filter(|name| !name.trim().is_empty())
+        })
+        .unwrap_or(localpart)
+        .to_string()
+}
+
+/// Returns the `count` largest expenses. Ties are ordered by most recent first.
+fn top_expenses(mut expenses: Vec<TransactionSplit>, count: usize) -> Vec<TransactionSplit> {
+    expenses.sort_by(|a, b| {
+        b.amount
+            .total_cmp(&a.amount)
+            .then_with(|| b.date.cmp(&a.date))
+    });
+    expenses.truncate(count);
+    expenses
+}
+
+/// Checks that the tags satisfy the category's entry in `required_tags`, if any. An empty entry
+/// requires at least one tag. Otherwise, at least one of the listed tags is required.
+fn check_required_tags(
+    required_tags: &HashMap<String, Vec<String>>,
+    category: &str,
+    tags: &[String],
+) -> anyhow::Result<()> {
+    let Some((category, required)) = required_tags
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(category))
+    else {
+        return Ok(());
+    };
+
+    if required.is_empty() {
+        if tags.is_empty() {
+            return Err(anyhow!("{category} expenses require at least one tag"));
+        }
+    } else if !tags
+        .iter()
+        .any(|tag| required.iter().any(|r| r.eq_ignore_ascii_case(tag)))
+    {
+        return Err(anyhow!(
+            "{category} expenses require one of these tags: #{}",
+            required.join(", #")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the most recent rate for each currency pair, ordered by pair
+fn latest_rates(rates: Vec<ExchangeRate>) -> Vec<ExchangeRateAttributes> {
+    let mut latest: Vec<ExchangeRateAttributes> = Vec::new();
+
+    for rate in rates.into_iter().map(|rate| rate.attributes) {
+        match latest.iter_mut().find(|existing| {
+            existing.from_currency_code == rate.from_currency_code
+                && existing.to_currency_code == rate.to_currency_code
+        }) {
+            Some(existing) if existing.date < rate.date => *existing = rate,
+            Some(_) => {}
+            None => latest.push(rate),
+        }
+    }
+
+    latest.sort_by(|a, b| {
+        (&a.from_currency_code, &a.to_currency_code)
+            .cmp(&(&b.from_currency_code, &b.to_currency_code))
+    });
+    latest
+}
+
+fn format_rates(rates: &[ExchangeRateAttributes]) -> String {
+    let mut output = String::from("Exchange rates:");
+    for rate in rates {
+        output.push_str(&format!(
+            "\n - {} → {}: {:.4} ({})",
+            rate.from_currency_code,
+            rate.to_currency_code,
+            rate.rate,
+            rate.date.date_naive()
+        ));
+    }
+    output
+}
+
+/// Returns the categories that are similar to `name`, ignoring case: those that contain it, are
+/// contained by it, or are within a small edit distance of it. At most one option per number
+/// reaction is returned, closest first.
+fn fuzzy_category_matches(name: &str, categories: &[String]) -> Vec<String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<(usize, &String)> = categories
+        .iter()
+        .filter_map(|category| {
+            let lower = category.to_lowercase();
+            let distance = edit_distance(&name, &lower);
+            if distance <= MAX_CATEGORY_DISTANCE || lower.contains(&name) || name.contains(&lower) {
+                Some((distance, category))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    matches.sort_by(|(a_distance, a), (b_distance, b)| a_distance.cmp(b_distance).then(a.cmp(b)));
+    matches
+        .into_iter()
+        .take(NUMBER_REACTIONS.len())
+        .map(|(_, category)| category.clone())
+        .collect()
+}
+
+/// The Levenshtein distance between two strings, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+
+    prev[b.len()]
+}
+
+fn format_category_options(name: &str, options: &[String]) -> String {
+    let mut output = format!("Category '{name}' doesn't exist. React to pick one:");
+    for (reaction, option) in NUMBER_REACTIONS.iter().zip(options) {
+        output.push_str(&format!("\n {reaction} {option}"));
+    }
+    output
+}
+
+/// Returns the id of the account from `source_accounts` that the note selects with
+/// "from <Account>", ignoring case, and the rest of the note. Returns `None` if the note doesn't
+/// select an account.
+fn select_source_account(
+    note: Option<&str>,
+    source_accounts: &BTreeMap<String, i64>,
+) -> Option<(i64, Option<String>)> {
+    let rest = note?.strip_prefix("from ")?.trim_start();
+
+    source_accounts
+        .iter()
+        .filter(|(name, _)| {
+            rest.is_char_boundary(name.len())
+                && rest[..name.len()].eq_ignore_ascii_case(name)
+                && (rest.len() == name.len() || rest[name.len()..].starts_with(char::is_whitespace))
+        })
+        .max_by_key(|(name, _)| name.len())
+        .map(|(name, id)| {
+            let note = rest[name.len()..].trim();
+            (*id, (!note.is_empty()).then(|| note.to_string()))
+        })
+}
+
+/// Returns the option that the number reaction picks, if it picks one
+fn chosen_option<'a>(options: &'a [String], reaction: &str) -> Option<&'a str> {
+    NUMBER_REACTIONS
+        .iter()
+        .position(|number| *number == reaction)
+        .and_then(|i| options.get(i))
+        .map(String::as_str)
+}
+
+/// Returns true if the bot should join the room it was invited to. Only invites to the rooms it
+/// listens to are accepted, and, with `auto_join_allowed_users_only`, only from `allowed_users`.
+fn should_accept_invite(
+    config: &Config,
+    room_ids: &[OwnedRoomId],
+    room_id: &RoomId,
+    inviter: &UserId,
+) -> bool {
+    config.auto_join
+        && room_ids.iter().any(|listened| listened == room_id)
+        && (!config.auto_join_allowed_users_only
+            || config.allowed_users.is_empty()
+            || config
+                .allowed_users
+                .iter()
+                .any(|allowed| allowed == inviter.as_str()))
+}
+
+/// Joins the room, retrying because homeservers sometimes reject a join that comes right after the
+/// invite
+async fn accept_invite(room: &Invited) -> anyhow::Result<()> {
+    let room_id = room.room_id();
+    with_retries(
+        &format!("join {room_id}"),
+        JOIN_MAX_RETRIES,
+        JOIN_RETRY_DELAY,
+        |_| true,
+        || async { Ok(room.accept_invitation().await?) },
+    )
+    .await?;
+    info!("Joined {room_id}");
+    Ok(())
+}
+
+/// Explains why the bot won't hear commands in a configured room, given whether it has joined,
+/// been invited to, or left the room, or if it doesn't know the room at all. Returns nothing once
+/// the bot has joined.
+fn membership_problem(room_id: &RoomId, room_type: Option<RoomType>) -> Option<String> {
+    match room_type {
+        Some(RoomType::Joined) => None,
+        Some(RoomType::Invited) => Some(format!(
+            "The bot was invited to {room_id} but hasn't joined it. Accept the invite, or set auto_join = true"
+        )),
+        Some(RoomType::Left) => Some(format!(
+            "The bot has left {room_id}. Invite it again to use it there"
+        )),
+        None => Some(format!(
+            "The bot is not in {room_id}. Check the room id, and invite the bot to the room"
+        )),
+    }
+}
+
+/// Returns the rooms to listen to. `matrix_room_ids` takes precedence over `matrix_room_id`, which
+/// is only used when `matrix_room_ids` is empty. The rooms in `rooms` are listened to as well.
+fn room_ids(config: &Config) -> anyhow::Result<Vec<OwnedRoomId>> {
+    let mut room_ids: Vec<&str> = if !config.matrix_room_ids.is_empty() {
+        if config.matrix_room_id.is_some() {
+            warn!("Ignoring matrix_room_id because matrix_room_ids is set");
+        }
+        config.matrix_room_ids.iter().map(String::as_str).collect()
+    } else {
+        non_empty(&config.matrix_room_id).into_iter().collect()
+    };
+    for room in &config.rooms {
+        if !room_ids.contains(&room.room_id.as_str()) {
+            room_ids.push(&room.room_id);
+        }
+    }
+
+    if room_ids.is_empty() {
+        return Err(anyhow!(
+            "At least one of matrix_room_ids, matrix_room_id, or rooms must be set"
+        ));
+    }
+
+    room_ids
+        .into_iter()
+        .map(|room_id| {
+            OwnedRoomId::try_from(room_id).map_err(|e| anyhow!("Invalid room id {room_id}: {e}"))
+        })
+        .collect()
+}
+
+/// Returns how to log in to Matrix. An access token is preferred over the password. Empty values
+/// are treated as unset.
+fn matrix_login(config: &Config) -> anyhow::Result<MatrixLogin<'_>> {
+    match (
+        non_empty(&config.matrix_access_token),
+        non_empty(&config.matrix_device_id),
+        non_empty(&config.matrix_password),
+    ) {
+        (Some(access_token), Some(device_id), _) => Ok(MatrixLogin::AccessToken {
+            access_token,
+            device_id,
+        }),
+        (Some(_), None, _) => Err(anyhow!(
+            "matrix_device_id must be set when logging in with matrix_access_token"
+        )),
+        (None, _, Some(password)) => Ok(MatrixLogin::Password(password)),
+        (None, _, None) => Err(anyhow!(
+            "Either matrix_password or matrix_access_token must be set"
+        )),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.is_empty())
+}
+
+/// Serializes the config as toml with the secrets masked
+fn redacted_config(config: &Config) -> anyhow::Result<String> {
+    let mut value = toml::Value::try_from(config)?;
+
+    if let Some(table) = value.as_table_mut() {
+        for field in SECRET_CONFIG_FIELDS {
+            if let Some(secret) = table.get_mut(*field) {
+                *secret = toml::Value::String(REDACTED.to_string());
+            }
+        }
+    }
+
+    Ok(toml::to_string(&value)?)
+}
+
+/// Returns true if the command should be redacted after it's processed. Only commands that
+/// succeeded are redacted, so that failures stay visible and can be retried.
+fn should_redact(config: &Config, succeeded: bool) -> bool {
+    config.auto_redact_commands && succeeded
+}
+
+/// Returns the person's entry in `settle_accounts`, ignoring case
+fn settle_account(settle_accounts: &HashMap<String, i64>, person: &str) -> Option<i64> {
+    settle_accounts
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(person))
+        .map(|(_, id)| *id)
+}
+
+/// Fills the `{category}`, `{person}`, and `{note}` placeholders of the template. Falls back to the
+/// category if that leaves the description empty, eg: `{note}` for an expense without a note,
+/// because Firefly requires a description.
+fn render_description(template: &str, category: &str, person: &str, note: Option<&str>) -> String {
+    let description = template
+        .replace("{category}", category)
+        .replace("{person}", person)
+        .replace("{note}", note.unwrap_or_default());
+    let description = description.trim();
+    if description.is_empty() {
+        category.to_string()
+    } else {
+        description.to_string()
+    }
+}
+
+/// Returns how to respond to a command from an unauthorized user, if at all
+fn unauthorized_response(config: &Config) -> Option<Response> {
+    match config.unauthorized_action {
+        UnauthorizedAction::Ignore => None,
+        UnauthorizedAction::React => Some(Response::Reaction(UNAUTHORIZED_REACTION.to_string())),
+        UnauthorizedAction::Message => Some(Response::Message(config.unauthorized_message.clone())),
+    }
+}
+
+/// Returns how to respond to a command that failed: a message, if there is one to send, and a
+/// reaction for the kind of error. The message of a parse error, which includes the command's usage,
+/// is only sent with `parse_error_messages`.
+fn error_responses(kind: ErrorKind, message: Option<String>, config: &Config) -> Vec<Response> {
+    let (reaction, send_message) = match kind {
+        ErrorKind::Parse => (INVALID_REACTION, config.parse_error_messages),
+        ErrorKind::Upstream => (FAILED_REACTION, true),
+    };
+    message
+        .filter(|_| send_message)
+        .map(Response::Message)
+        .into_iter()
+        .chain([Response::Reaction(reaction.to_string())])
+        .collect()
+}
+
+/// Returned instead of an HTTP error when Firefly is in maintenance mode
+#[derive(Debug)]
+struct MaintenanceError;
+
+impl fmt::Display for MaintenanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MAINTENANCE_MESSAGE)
+    }
+}
+
+impl std::error::Error for MaintenanceError {}
+
+/// Returned when Firefly rejects an added transaction
+#[derive(Debug)]
+struct TransactionError {
+    status: StatusCode,
+    body: String,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to add transaction: [{:?}] {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// Returned when Firefly responds with a 503 that isn't its maintenance mode response, such as
+/// while it's restarting
+#[derive(Debug)]
+struct UnavailableError {
+    status: StatusCode,
+    body: String,
+}
+
+impl fmt::Display for UnavailableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Firefly is unavailable: [{:?}] {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for UnavailableError {}
+
+/// Returns true if the response is Firefly's maintenance mode response, which is a 503 that mentions
+/// maintenance. Other 503s, such as Laravel's generic "Service Unavailable" error, aren't.
+fn is_maintenance(status: u16, body: &str) -> bool {
+    status == 503 && body.to_lowercase().contains("maintenance")
+}
+
+/// Turns a maintenance mode response into a `MaintenanceError`, any other 503 into an
+/// `UnavailableError`, and any other error status into an HTTP error
+async fn check_maintenance(response: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    let status = response.status();
+    if status.as_u16() != 503 {
+        return Ok(response.error_for_status()?);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    if is_maintenance(status.as_u16(), &body) {
+        Err(MaintenanceError.into())
+    } else {
+        Err(UnavailableError { status, body }.into())
+    }
+}
+
+/// Checks Firefly's response to adding a transaction, which is any 2xx status on success, and
+/// returns the id of the created transaction group if it could be read
+async fn read_transaction_response(response: reqwest::Response) -> anyhow::Result<Option<String>> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "failed to read response body".to_string());
+        if is_maintenance(status.as_u16(), &body) {
+            return Err(MaintenanceError.into());
+        }
+        return Err(TransactionError { status, body }.into());
+    }
+
+    match response.json::<StoredTransactionGroup>().await {
+        Ok(stored) => Ok(Some(stored.data.id)),
+        Err(e) => {
+            warn!("Failed to read the id of the added transaction: {e}");
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the message to reply with when a command fails, which explains when Firefly is in
+/// maintenance mode
+fn failure_message(error: &anyhow::Error, message: &str) -> String {
+    if error.is::<MaintenanceError>() {
+        MAINTENANCE_MESSAGE.to_string()
+    } else {
+        message.to_string()
+    }
+}
+
+/// Returns true if the error is an HTTP error response with the given status
+fn is_status(error: &anyhow::Error, status: StatusCode) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        .map(|s| s.as_u16() == status.as_u16())
+        .unwrap_or(false)
+}
+
+/// Returns true if a Firefly request that failed with the error may succeed if retried, which is
+/// when the connection failed or timed out, or Firefly responded with a 5xx status. 4xx responses
+/// and maintenance mode are never retried.
+fn is_transient(error: &anyhow::Error) -> bool {
+    is_transient_post(error)
+        || error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout())
+}
+
+/// Returns true if a transaction that failed to post with the error can be posted again without
+/// risking a duplicate, which is when the connection failed or Firefly responded with a 5xx status.
+/// Timeouts aren't retried because Firefly may have stored the transaction before timing out.
+fn is_transient_post(error: &anyhow::Error) -> bool {
+    if error.is::<MaintenanceError>() {
+        return false;
+    }
+    if let Some(e) = error.downcast_ref::<TransactionError>() {
+        return e.status.is_server_error();
+    }
+    if let Some(e) = error.downcast_ref::<UnavailableError>() {
+        return e.status.is_server_error();
+    }
+    error
+        .downcast_ref::<reqwest::Error>()
+        .map(|e| e.is_connect() || e.status().is_some_and(|status| status.is_server_error()))
+        .unwrap_or(false)
+}
+
+/// Returns the message that greets a new member, which lists the commands with the given prefix
+fn greeting_text(user_id: &UserId, prefix: &str) -> String {
+    with_prefix(&format!("Welcome, {user_id}! {}", help_text()), prefix)
+}
+
+/// Replaces the default prefix of the commands mentioned in the text with the given prefix. A
+/// command is a `!` at the start of a word that's followed by a lowercase letter.
+fn with_prefix(text: &str, prefix: &str) -> String {
+    if prefix == DEFAULT_COMMAND_PREFIX {
+        return text.to_string();
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let starts_word = previous.is_none_or(|p: char| p.is_whitespace() || p == '`' || p == '(');
+        if c == '!' && starts_word && chars.peek().is_some_and(char::is_ascii_lowercase) {
+            output.push_str(prefix);
+        } else {
+            output.push(c);
+        }
+        previous = Some(c);
+    }
+    output
+}
+
+fn format_examples() -> String {
+    let mut examples = String::from("**Examples**\n");
+    for (example, description) in EXAMPLES {
+        examples.push_str(&format!("\n- `{example}`: {description}"));
+    }
+    examples
+}
+
+/// Returns the first `max_lines` lines of the output if it is longer than that, or `None` if the
+/// output should be sent in full. A `max_lines` of 0 means there is no limit.
+fn output_preview(output: &str, max_lines: usize) -> Option<String> {
+    let line_count = output.lines().count();
+
+    if max_lines == 0 || line_count <= max_lines {
+        return None;
+    }
+
+    let mut preview = output
+        .lines()
+        .take(max_lines)
+        .collect::<Vec<_>>()
+        .join("\n");
+    preview.push_str(&format!(
+        "\n… {} more lines in the attached file",
+        line_count - max_lines
+    ));
+    Some(preview)
+}
+
+/// Describes the tags that an add created, eg: `new tag: vacation`, or `None` if there are none
+fn new_tags_note(new_tags: &[String]) -> Option<String> {
+    match new_tags {
+        [] => None,
+        [tag] => Some(format!("new tag: {tag}")),
+        tags => Some(format!("new tags: {}", tags.join(", "))),
+    }
+}
+
+/// Lists the tags alphabetically, ignoring case, so that tags that only differ by case are together
+fn format_tags(mut tags: Vec<String>) -> String {
+    if tags.is_empty() {
+        return "No tags".to_string();
+    }
+
+    tags.sort_by_key(|tag| tag.to_lowercase());
+    format!("Tags:\n - {}", tags.join("\n - "))
+}
+
+/// Lists the categories, or only those whose names contain `search`, ignoring case
+fn format_categories(categories: &[String], search: Option<&str>) -> String {
+    let Some(search) = search else {
+        let mut response = String::from("Categories:");
+        for category in categories {
+            response.push_str(&format!("\n - {category}"));
+        }
+        return response;
+    };
+
+    let search_lower = search.to_lowercase();
+    let matches: Vec<&String> = categories
+        .iter()
+        .filter(|category| category.to_lowercase().contains(&search_lower))
+        .collect();
+    if matches.is_empty() {
+        return format!("No categories match '{search}'");
+    }
+
+    let mut response = format!("Categories matching '{search}':");
+    for category in matches {
+        response.push_str(&format!("\n - {category}"));
+    }
+    response
+}
+
+/// Lists the asset accounts by name, with the ids that `firefly_source_account_id`,
+/// `source_account_id`, and `user_accounts` take
+fn format_accounts(mut accounts: Vec<MirroredAccount>) -> String {
+    if accounts.is_empty() {
+        return "No asset accounts".to_string();
+    }
+
+    accounts.sort_by_key(|account| account.name.to_lowercase());
+    let mut response = String::from("Asset accounts:");
+    for account in accounts {
+        response.push_str(&format!("\n - {}: {}", account.name, account.id));
+    }
+    response
+}
+
+fn format_budgets(budgets: &[BudgetStatus]) -> String {
+    if budgets.is_empty() {
+        return "No budgets are defined".to_string();
+    }
+
+    let mut response = String::from("Budgets this month:");
+    for budget in budgets {
+        let remaining = match budget.limit {
+            Some(limit) if budget.spent > limit => {
+                format!("{} over", format_amount(budget.spent - limit, None))
+            }
+            Some(limit) => format!("{} remaining", format_amount(limit - budget.spent, None)),
+            None => "no limit".to_string(),
+        };
+        response.push_str(&format!(
+            "\n - {}: {} spent, {remaining}",
+            budget.name,
+            format_amount(budget.spent, None)
+        ));
+    }
+    response
+}
+
+/// Formats the expenses for `!top`, which are already sorted largest first
+fn format_top(top: Vec<TransactionSplit>, days: i64) -> String {
+    let mut response = String::new();
+
+    if top.is_empty() {
+        response.push_str(&format!("No expenses in the last {days} days"));
+    } else {
+        response.push_str(&format!(
+            "Top {} expenses in the last {days} days:",
+            top.len()
+        ));
+        for (i, expense) in top.iter().enumerate() {
+            response.push_str(&format!(
+                "\n {}. {} {} ({})",
+                i + 1,
+                format_amount(expense.amount, expense.currency_symbol.as_deref()),
+                expense
+                    .category_name
+                    .as_deref()
+                    .unwrap_or(&expense.description),
+                expense.date.date_naive()
+            ));
+        }
+    }
+
+    response
+}
+
+/// Sums expenses by category, largest total first
+fn category_totals(expenses: &[TransactionSplit]) -> Vec<(String, f64)> {
+    let mut totals: Vec<(String, f64)> = Vec::new();
+
+    for expense in expenses {
+        let category = expense.category_name.as_deref().unwrap_or("Uncategorized");
+        match totals.iter_mut().find(|(name, _)| name == category) {
+            Some((_, total)) => *total += expense.amount,
+            None => totals.push((category.to_string(), expense.amount)),
+        }
+    }
+
+    totals.sort_by(|(a_name, a), (b_name, b)| b.total_cmp(a).then_with(|| a_name.cmp(b_name)));
+    totals
+}
+
+/// Formats a transaction for `!history`, eg: "2024-01-02 withdrawal 42.10 Groceries"
+fn format_history_line(transaction: &TransactionSplit) -> String {
+    format!(
+        "{} {} {} {}",
+        transaction.date.date_naive(),
+        transaction.transaction_type,
+        format_amount(transaction.amount, transaction.currency_symbol.as_deref()),
+        transaction
+            .category_name
+            .as_deref()
+            .unwrap_or(&transaction.description)
+    )
+}
+
+/// Requests each page of a paginated list with `fetch_page`, starting from page 1, until the last
+/// page, and returns the combined results
+async fn collect_pages<T, F, Fut>(fetch_page: F) -> anyhow::Result<Vec<T>>
+where
+    F: Fn(i64) -> Fut,
+    Fut: Future<Output = anyhow::Result<Page<T>>>,
+{
+    let mut results = Vec::new();
+    let mut page_num = 1;
+
+    loop {
+        let page = fetch_page(page_num).await?;
+        results.extend(page.data);
+
+        if page.meta.pagination.current_page >= page.meta.pagination.total_pages {
+            break;
+        }
+        page_num += 1;
+    }
+
+    Ok(results)
+}
+
+fn format_amount(amount: f64, currency_symbol: Option<&str>) -> String {
+    format!("{}{amount:.2}", currency_symbol.unwrap_or_default())
+}
+
+/// Firefly serializes amounts as strings
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let amount = String::deserialize(deserializer)?;
+    f64::from_str(&amount).map_err(serde::de::Error::custom)
+}
+
+impl Prompt {
+    /// Returns true if the reaction answers the prompt
+    fn accepts(&self, reaction: &str) -> bool {
+        match self {
+            Prompt::CreateCategory { .. }
+            | Prompt::ConfirmDuplicate { .. }
+            | Prompt::ConfirmSplit { .. } => reaction == CONFIRM_REACTION,
+            Prompt::ChooseCategory { options, .. } => chosen_option(options, reaction).is_some(),
+            Prompt::ChooseSource { options, .. } => {
+                reaction == CANCEL_REACTION || chosen_option(options, reaction).is_some()
+            }
+        }
+    }
+}
+
+impl PromptStore {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn insert(&mut self, prompt_id: OwnedEventId, user: OwnedUserId, prompt: Prompt) {
+        let timeout = self.timeout;
+        self.entries
+            .retain(|_, (_, _, created_at)| created_at.elapsed() < timeout);
+        self.entries
+            .insert(prompt_id, (user, prompt, Instant::now()));
+    }
+
+    /// Removes and returns the prompt if it was answered by the user that it was created for
+    fn take(&mut self, prompt_id: &OwnedEventId, user: &UserId, reaction: &str) -> Option<Prompt> {
+        let (owner, prompt, created_at) = self.entries.get(prompt_id)?;
+
+        if owner != user || !prompt.accepts(reaction) {
+            return None;
+        }
+
+        if created_at.elapsed() >= self.timeout {
+            self.entries.remove(prompt_id);
+            return None;
+        }
+
+        self.entries.remove(prompt_id).map(|(_, prompt, _)| prompt)
+    }
+}
+
+impl RetryStore {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn store(&mut self, user: OwnedUserId, request: AddRequest) {
+        self.entries.insert(user, (request, Instant::now()));
+    }
+
+    /// Removes and returns the user's last failed add, unless it has expired
+    fn take(&mut self, user: &UserId) -> Option<AddRequest> {
+        self.entries
+            .remove(user)
+            .filter(|(_, failed_at)| failed_at.elapsed() < self.timeout)
+            .map(|(request, _)| request)
+    }
+
+    fn clear(&mut self, user: &UserId) {
+        self.entries.remove(user);
+    }
+}
+
+/// The text of a message, without the fallback that replies start with. Some clients send an empty
+/// plain body with formatted messages, so the HTML body is read as text when the plain one is empty.
+fn message_text(message: &TextMessageEventContent) -> String {
+    // Replies start with a quote of the message they reply to
+    let plain = remove_plain_reply_fallback(&message.body);
+    if !plain.trim().is_empty() {
+        return plain.to_string();
+    }
+    match &message.formatted {
+        Some(formatted) if formatted.format == MessageFormat::Html => html_to_text(&formatted.body),
+        _ => plain.to_string(),
+    }
+}
+
+fn is_dm(room: &Joined) -> bool {
+    is_dm_room(room.is_direct(), room.clone_info().active_members_count())
+}
+
+/// A room is a DM if it is marked as direct. Its member count isn't considered, because a shared
+/// room may have only two members.
+fn is_dm_room(is_direct: bool, _active_members: u64) -> bool {
+    is_direct
+}
+
+fn cache_dir() -> PathBuf {
+    dirs::data_dir().unwrap().join(CACHE_DIR)
+}
+
+/// Returns the cache dir if it's writable. Otherwise, falls back to a dir under the system temp dir
+/// when `cache_dir_fallback` is set, or fails with an error explaining the problem.
+fn resolve_cache_dir(config: &Config) -> anyhow::Result<PathBuf> {
+    let dir = cache_dir();
+
+    match check_writable(&dir) {
+        Ok(_) => Ok(dir),
+        Err(e) if config.cache_dir_fallback => {
+            let fallback = env::temp_dir().join(CACHE_DIR);
+            warn!(
+                "{e}. Falling back to {}, which may not survive restarts",
+                fallback.display()
+            );
+            check_writable(&fallback)?;
+            Ok(fallback)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks that files can be created in the dir, creating it if it doesn't exist
+fn check_writable(dir: &Path) -> anyhow::Result<()> {
+    let not_writable = |e: std::io::Error| {
+        anyhow!(
+            "cache dir {} is not writable — check permissions: {e}",
+            dir.display()
+        )
+    };
+
+    fs::create_dir_all(dir).map_err(not_writable)?;
+
+    let probe = dir.join(".write-check");
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .map_err(not_writable)?;
+    fs::remove_file(&probe).map_err(not_writable)?;
+
+    Ok(())
+}
+
+/// Returns the bot's DM room with the user, creating it if it doesn't exist
+async fn dm_room(client: &MatrixClient, user_id: &UserId) -> anyhow::Result<Joined> {
+    if let Some(room) = client
+        .joined_rooms()
+        .into_iter()
+        .find(|room| room.is_direct() && room.direct_targets().contains(user_id))
+    {
+        return Ok(room);
+    }
+
+    let invite = [user_id.to_owned()];
+    let mut request = CreateRoomRequest::new();
+    request.invite = &invite;
+    request.is_direct = true;
+    request.preset = Some(RoomPreset::TrustedPrivateChat);
+    let room_id = client.create_room(request).await?.room_id;
+
+    // The room is only available once the sync loop has seen it
+    for _ in 0..10 {
+        if let Some(room) = client.get_joined_room(&room_id) {
+            room.set_is_direct(true).await?;
+            return Ok(room);
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    Err(anyhow!(
+        "Created DM room {room_id} but it never became available"
+    ))
+}
+
+async fn send_message(content: String, room: &Joined) -> anyhow::Result<()> {
+    send_text(content, room).await?;
+    Ok(())
+}
+
+/// Sends a plain text message, and returns its event id
+async fn send_text(content: String, room: &Joined) -> anyhow::Result<OwnedEventId> {
+    let response = room
+        .send(RoomMessageEventContent::text_plain(content), None)
+        .await?;
+    Ok(response.event_id)
+}
+
+/// Replaces the content of one of the bot's messages
+async fn edit_message(
+    content: String,
+    event_id: OwnedEventId,
+    room: &Joined,
+) -> anyhow::Result<()> {
+    let mut edit = RoomMessageEventContent::text_plain(format!("* {content}"));
+    edit.relates_to = Some(MessageRelation::Replacement(Replacement::new(
+        event_id,
+        Box::new(RoomMessageEventContent::text_plain(content)),
+    )));
+    room.send(edit, None).await?;
+    Ok(())
+}
+
+/// Waits for SIGINT or, on Unix, SIGTERM, which are sent to stop the bot, eg: by systemd or
+/// Docker. Returns the signal's name.
+async fn shutdown_signal() -> anyhow::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT").map_err(Into::into),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("SIGINT")
+    }
+}
+
+/// Builds a text message that relates to the command as the `reply_mode` requires. The relation is
+/// written as JSON because the SDK's thread relation is behind an unstable feature. In a thread,
+/// the reply to the command is a fallback for clients that don't support threads.
+fn confirmation_content(
+    text: &str,
+    event_id: &EventId,
+    reply_mode: ReplyMode,
+) -> serde_json::Value {
+    let mut content = serde_json::json!({ "msgtype": "m.text", "body": text });
+    content["m.relates_to"] = match reply_mode {
+        ReplyMode::Reaction => return content,
+        ReplyMode::Reply => serde_json::json!({ "m.in_reply_to": { "event_id": event_id } }),
+        ReplyMode::Thread => serde_json::json!({
+            "rel_type": "m.thread",
+            "event_id": event_id,
+            "is_falling_back": true,
+            "m.in_reply_to": { "event_id": event_id },
+        }),
+    };
+    content
+}
+
+/// Starts or stops the bot's typing notice. Failures are only logged, because the notice is
+/// cosmetic and shouldn't stop a command.
+async fn set_typing(room: &Joined, typing: bool) {
+    if let Err(e) = room.typing_notice(typing).await {
+        warn!("Failed to set typing notice to {typing}: {e}");
+    }
+}
+
+async fn send_reaction(
+    reaction: String,
+    event_id: OwnedEventId,
+    room: &Joined,
+) -> anyhow::Result<()> {
+    room.send(
+        ReactionEventContent::new(Relation::new(event_id, reaction)),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .format_target(false)
+        .init();
+
+    let program = env::args()
+        .next()
+        .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+    let config_path = match parse_args(env::args().skip(1)) {
+        Ok(CliCommand::Run(config_path)) => config_path,
+        Ok(CliCommand::Version) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Ok(CliCommand::Help) => {
+            println!("{}", usage(&program));
+            return Ok(());
+        }
+        Err(e) => {
+            error!("{e}\n{}", usage(&program));
+            exit(1)
+        }
+    };
+
+    let mut config_file = File::open(config_path)?;
+    let mut bytes = Vec::new();
+    config_file.read_to_end(&mut bytes)?;
+
+    let config: Config = toml::from_slice(&bytes)?;
+
+    let problems = config.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            error!("Invalid config: {problem}");
+        }
+        exit(1)
+    }
+
+    MatrixFireflyBot::new(config)?.start().await?;
+
+    info!("Exiting");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::CommandHistory;
+    use crate::edit::EditArgs;
+    use crate::help::command_examples;
+    use crate::retry::with_retries;
+    use crate::{
+        build_category_tree, category_totals, check_maintenance, check_required_tags,
+        check_writable, choose_person_tag, chosen_option, collect_pages, confirmation_content,
+        edit_distance, error_responses, failure_message, format_accounts, format_budgets,
+        format_categories, format_rates, format_tags, fuzzy_category_matches, greeting_text,
+        is_dm_room, is_maintenance, is_transient, is_transient_post, latest_rates, matrix_login,
+        membership_problem, message_text, new_tags_note, output_preview, read_transaction_response,
+        redacted_config, render_category_tree, render_description, report_start, room_ids,
+        round_amount, select_source_account, should_accept_invite, should_redact, top_expenses,
+        unauthorized_response, with_prefix, About, AddArgs, AddRequest, AmountRounding, Budget,
+        BudgetLimit, BudgetStatus, Category, CategoryAmount, CategoryCreation, CategoryNode, Cmd,
+        Config, ErrorKind, ExchangeRate, GoalArgs, MaintenanceError, MatrixLogin, MirroredAccount,
+        NewPiggyBank, NoteMode, Page, PersonAttribution, PersonSource, Prompt, PromptStore,
+        ReplyMode, Response, RetryStore, RoomType, Single, TextMessageEventContent, TopArgs,
+        Transaction, TransactionError, TransactionSplit, Transactions, UnavailableError,
+        CANCEL_REACTION, CONFIRM_REACTION, DEFAULT_DESCRIPTION_TEMPLATE, EXAMPLES, FAILED_REACTION,
+        FIREFLY_CATEGORIES_API, FIREFLY_TRANSACTIONS_API, INVALID_REACTION, MAINTENANCE_MESSAGE,
+        NUMBER_REACTIONS, REDACTED, REPORT_MAX_DAYS, TOP_MAX_COUNT, UNAUTHORIZED_REACTION,
+    };
+    use chrono::{Duration as ChronoDuration, Local, NaiveDate};
+    use matrix_sdk::ruma::exports::http::StatusCode;
+    use matrix_sdk::ruma::{EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse_add() {
+        assert_add_arg(parse_add("Test: 1.23"), "Test", 1.23, None, vec![]);
+        assert_add_arg(
+            parse_add("multi word cat: $100"),
+            "multi word cat",
+            100.00,
+            None,
+            vec![],
+        );
+        assert_add_arg(
+            parse_add("cat : 0.25 this is a note"),
+            "cat",
+            0.25,
+            Some("this is a note"),
+            vec![],
+        );
+        assert_add_arg(
+            parse_add("test: 1.25 #tag"),
+            "test",
+            1.25,
+            None,
+            vec!["tag"],
+        );
+        assert_add_arg(
+            parse_add("test: 1 this is a note #one #two #three"),
+            "test",
+            1.00,
+            Some("this is a note"),
+            vec!["one", "two", "three"],
+        );
+        assert_add_arg(
+            parse_add(
+                "   weird spacing   :   $1.01    this one has  extra   spacing    #one    #  two  ",
+            ),
+            "weird spacing",
+            1.01,
+            Some("this one has  extra   spacing"),
+            vec!["one", "two"],
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_word_tags() {
+        assert_add_arg(
+            parse_add("test: 1 note #one #{two words} #\"three  more words\" #four"),
+            "test",
+            1.00,
+            Some("note"),
+            vec!["one", "two words", "three  more words", "four"],
+        );
+        assert_add_arg(
+            parse_add("test: 1 #{C# class}#next"),
+            "test",
+            1.00,
+            None,
+            vec!["C# class", "next"],
+        );
+        assert_add_arg(
+            parse_add("test: 1 #{unclosed tag #next"),
+            "test",
+            1.00,
+            None,
+            vec!["{unclosed tag", "next"],
+        );
+        assert_add_arg(
+            AddArgs::parse("test: 1 a note #{two words} #one", NoteMode::Strict, false).unwrap(),
+            "test",
+            1.00,
+            Some("a note"),
+            vec!["two words", "one"],
+        );
+    }
+
+    #[test]
+    fn test_parse_strict_note() {
+        let strict = |args: &str| AddArgs::parse(args, NoteMode::Strict, false).unwrap();
+
+        assert_add_arg(
+            strict("Books: 30 learn Rust in  24 hours: part 2 #school #kids"),
+            "Books",
+            30.0,
+            Some("learn Rust in  24 hours: part 2"),
+            vec!["school", "kids"],
+        );
+        assert_add_arg(
+            strict("Test: 1 note a#b #tag"),
+            "Test",
+            1.0,
+            Some("note a"),
+            vec!["b", "tag"],
+        );
+        assert_add_arg(
+            strict("Books: 30 learn C# #school"),
+            "Books",
+            30.0,
+            Some("learn C"),
+            vec!["school"],
+        );
+
+        assert_add_arg(strict("Test: 1.25 #tag"), "Test", 1.25, None, vec!["tag"]);
+        assert_add_arg(strict("Test: 1.25"), "Test", 1.25, None, vec![]);
+        assert_add_arg(
+            strict("Test: 1.25   spaced   note   "),
+            "Test",
+            1.25,
+            Some("spaced   note"),
+            vec![],
+        );
+    }
+
+    #[test]
+    fn test_retry_store() {
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let bob = OwnedUserId::try_from("@bob:example.org").unwrap();
+        let request = AddRequest {
+            args: parse_add("Test: 1.23 note #tag"),
+            timestamp: SystemTime::now(),
+            source_account_id: None,
+        };
+
+        let mut store = RetryStore::new(Duration::from_secs(60));
+        assert_eq!(None, store.take(&alice));
+
+        store.store(alice.clone(), request.clone());
+        assert_eq!(None, store.take(&bob));
+        assert_eq!(Some(request.clone()), store.take(&alice));
+        assert_eq!(None, store.take(&alice));
+
+        store.store(alice.clone(), request.clone());
+        store.clear(&alice);
+        assert_eq!(None, store.take(&alice));
+
+        let mut expired = RetryStore::new(Duration::ZERO);
+        expired.store(alice.clone(), request);
+        assert_eq!(None, expired.take(&alice));
+    }
+
+    #[test]
+    fn test_confirm_category_prompt() {
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let bob = OwnedUserId::try_from("@bob:example.org").unwrap();
+        let prompt_id = OwnedEventId::try_from("$prompt:example.org").unwrap();
+        let prompt = Prompt::CreateCategory {
+            request: AddRequest {
+                args: parse_add("New: 5"),
+                timestamp: SystemTime::now(),
+                source_account_id: None,
+            },
+            event_id: OwnedEventId::try_from("$add:example.org").unwrap(),
+        };
+
+        let mut store = PromptStore::new(Duration::from_secs(60));
+        store.insert(prompt_id.clone(), alice.clone(), prompt.clone());
+
+        assert_eq!(None, store.take(&prompt_id, &bob, CONFIRM_REACTION));
+        assert_eq!(None, store.take(&prompt_id, &alice, "👎"));
+        assert_eq!(
+            Some(prompt),
+            store.take(&prompt_id, &alice, CONFIRM_REACTION)
+        );
+        assert_eq!(None, store.take(&prompt_id, &alice, CONFIRM_REACTION));
+    }
+
+    #[test]
+    fn test_choose_category() {
+        let categories: Vec<String> = ["Groceries", "Gas", "Gifts", "Dining", "Dining Out"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+
+        assert_eq!(1, edit_distance("grocries", "groceries"));
+        assert_eq!(3, edit_distance("grocery", "groceries"));
+        assert_eq!(
+            vec!["Groceries"],
+            fuzzy_category_matches("Grocries", &categories)
+        );
+        assert_eq!(
+            vec!["Dining", "Dining Out"],
+            fuzzy_category_matches("dinin", &categories)
+        );
+        assert_eq!(
+            vec!["Gas", "Gifts"],
+            fuzzy_category_matches("Gis", &categories)
+        );
+        assert!(fuzzy_category_matches("Travel", &categories).is_empty());
+
+        let options = vec!["Dining".to_string(), "Dining Out".to_string()];
+        assert_eq!(Some("Dining"), chosen_option(&options, NUMBER_REACTIONS[0]));
+        assert_eq!(Some("Dining Out"), chosen_option(&options, "2️⃣"));
+        assert_eq!(None, chosen_option(&options, NUMBER_REACTIONS[2]));
+        assert_eq!(None, chosen_option(&options, CONFIRM_REACTION));
+
+        let prompt_id = OwnedEventId::try_from("$prompt:example.org").unwrap();
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let prompt = Prompt::ChooseCategory {
+            request: AddRequest {
+                args: parse_add("dinin: 5"),
+                timestamp: SystemTime::now(),
+                source_account_id: None,
+            },
+            event_id: OwnedEventId::try_from("$add:example.org").unwrap(),
+            options,
+        };
+        let mut store = PromptStore::new(Duration::from_secs(60));
+        store.insert(prompt_id.clone(), alice.clone(), prompt.clone());
+        assert_eq!(None, store.take(&prompt_id, &alice, "3️⃣"));
+        assert_eq!(Some(prompt), store.take(&prompt_id, &alice, "2️⃣"));
+    }
+
+    #[test]
+    fn test_select_source_account() {
+        let accounts = BTreeMap::from([("Joint".to_string(), 1), ("Joint Savings".to_string(), 2)]);
+
+        assert_eq!(
+            Some((1, Some("milk".to_string()))),
+            select_source_account(Some("from joint milk"), &accounts)
+        );
+        assert_eq!(
+            Some((2, None)),
+            select_source_account(Some("from Joint Savings"), &accounts)
+        );
+        assert_eq!(None, select_source_account(Some("from Jointly"), &accounts));
+        assert_eq!(None, select_source_account(Some("milk"), &accounts));
+        assert_eq!(None, select_source_account(None, &accounts));
+    }
+
+    #[test]
+    fn test_choose_source_prompt() {
+        let prompt_id = OwnedEventId::try_from("$prompt:example.org").unwrap();
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let options = vec!["Checking".to_string(), "Joint".to_string()];
+        let prompt = Prompt::ChooseSource {
+            request: AddRequest {
+                args: parse_add("Groceries: 5"),
+                timestamp: SystemTime::now(),
+                source_account_id: None,
+            },
+            event_id: OwnedEventId::try_from("$add:example.org").unwrap(),
+            options: options.clone(),
+        };
+
+        let mut store = PromptStore::new(Duration::from_secs(60));
+        store.insert(prompt_id.clone(), alice.clone(), prompt.clone());
+        assert_eq!(None, store.take(&prompt_id, &alice, "3️⃣"));
+        assert_eq!(None, store.take(&prompt_id, &alice, CONFIRM_REACTION));
+        assert_eq!(Some(prompt.clone()), store.take(&prompt_id, &alice, "2️⃣"));
+        assert_eq!(Some("Joint"), chosen_option(&options, "2️⃣"));
+
+        store.insert(prompt_id.clone(), alice.clone(), prompt.clone());
+        assert_eq!(
+            Some(prompt.clone()),
+            store.take(&prompt_id, &alice, CANCEL_REACTION)
+        );
+        assert_eq!(None, store.take(&prompt_id, &alice, "1️⃣"));
+
+        let mut expired = PromptStore::new(Duration::ZERO);
+        expired.insert(prompt_id.clone(), alice.clone(), prompt);
+        assert_eq!(None, expired.take(&prompt_id, &alice, "1️⃣"));
+    }
+
+    #[test]
+    fn test_report_start() {
+        let end = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        assert_eq!(end, report_start(end, 1));
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+            report_start(end, 7)
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 2, 10).unwrap(),
+            report_start(end, 30)
+        );
+    }
+
+    #[test]
+    fn test_top_expenses() {
+        assert_eq!(TopArgs { count: 5, days: 30 }, TopArgs::parse("").unwrap());
+        assert_eq!(
+            TopArgs { count: 3, days: 7 },
+            TopArgs::parse("3 7").unwrap()
+        );
+        assert_eq!(
+            TopArgs {
+                count: TOP_MAX_COUNT,
+                days: 30
+            },
+            TopArgs::parse("1000").unwrap()
+        );
+        assert_eq!(
+            TopArgs {
+                count: 5,
+                days: REPORT_MAX_DAYS
+            },
+            TopArgs::parse("5 100000000").unwrap()
+        );
+        assert!(TopArgs::parse("0").is_err());
+        assert!(TopArgs::parse("a").is_err());
+        assert!(TopArgs::parse("5 99999999999999999999").is_err());
+
+        let expenses: Vec<TransactionSplit> = serde_json::from_str(
+            r#"[
+                {"type": "withdrawal", "date": "2024-01-01T00:00:00+00:00", "amount": "5.00",
+                 "description": "a", "category_name": "A", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-02T00:00:00+00:00", "amount": "20.50",
+                 "description": "b", "category_name": "B", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-03T00:00:00+00:00", "amount": "5.00",
+                 "description": "c", "category_name": "C", "notes": null, "currency_symbol": "$"}
+            ]"#,
+        )
+        .unwrap();
+
+        let top = top_expenses(expenses.clone(), 2);
+        assert_eq!(vec!["b", "c"], descriptions(&top));
+        let top = top_expenses(expenses, 10);
+        assert_eq!(vec!["b", "c", "a"], descriptions(&top));
+        assert!(top_expenses(vec![], 5).is_empty());
+    }
+
+    #[test]
+    fn test_choose_person_tag() {
+        let all = [
+            PersonSource::DisplayName,
+            PersonSource::UserTag,
+            PersonSource::Localpart,
+        ];
+
+        assert_eq!(
+            "Alice A.",
+            choose_person_tag(&all, Some("Alice A."), Some("Alice"), "alice")
+        );
+        assert_eq!(
+            "Alice",
+            choose_person_tag(&all, None, Some("Alice"), "alice")
+        );
+        assert_eq!("alice", choose_person_tag(&all, None, None, "alice"));
+        assert_eq!(
+            "Alice",
+            choose_person_tag(&all, Some(" "), Some("Alice"), "alice")
+        );
+        assert_eq!(
+            "Alice",
+            choose_person_tag(
+                &[PersonSource::UserTag, PersonSource::Localpart],
+                Some("Alice A."),
+                Some("Alice"),
+                "alice"
+            )
+        );
+        assert_eq!(
+            "alice",
+            choose_person_tag(&[PersonSource::DisplayName], None, Some("Alice"), "alice")
+        );
+        assert_eq!(
+            "alice",
+            choose_person_tag(&[], Some("Alice A."), None, "alice")
+        );
+    }
+
+    #[test]
+    fn test_category_tree() {
+        let categories = vec![
+            "Food:Groceries".to_string(),
+            "Bills".to_string(),
+            "Food:Dining:Takeout".to_string(),
+            "Food".to_string(),
+            "Food: Dining".to_string(),
+        ];
+
+        let tree = build_category_tree(&categories, ":");
+        assert_eq!(
+            vec![
+                node(
+                    "Food",
+                    vec![
+                        node("Groceries", vec![]),
+                        node("Dining", vec![node("Takeout", vec![])])
+                    ]
+                ),
+                node("Bills", vec![]),
+            ],
+            tree
+        );
+
+        let mut output = String::new();
+        render_category_tree(&tree, 1, &mut output);
+        assert_eq!(
+            "\n - Food\n   - Groceries\n   - Dining\n     - Takeout\n - Bills",
+            output
+        );
+
+        assert_eq!(
+            vec![node("Food:Groceries", vec![])],
+            build_category_tree(&["Food:Groceries".to_string()], "/")
+        );
+    }
+
+    fn node(name: &str, children: Vec<CategoryNode>) -> CategoryNode {
+        CategoryNode {
+            name: name.to_string(),
+            children,
+        }
+    }
+
+    #[test]
+    fn test_category_totals() {
+        let expenses: Vec<TransactionSplit> = serde_json::from_str(
+            r#"[
+                {"type": "withdrawal", "date": "2024-01-01T00:00:00+00:00", "amount": "5.00",
+                 "description": "a", "category_name": "A", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-02T00:00:00+00:00", "amount": "2.50",
+                 "description": "b", "category_name": null, "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-03T00:00:00+00:00", "amount": "6.00",
+                 "description": "c", "category_name": "A", "notes": null, "currency_symbol": "$"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![("A".to_string(), 11.0), ("Uncategorized".to_string(), 2.5)],
+            category_totals(&expenses)
+        );
+        assert!(category_totals(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_output_preview() {
+        let output = "Header:\n - one\n - two\n - three";
+
+        assert_eq!(None, output_preview(output, 4));
+        assert_eq!(None, output_preview(output, 10));
+        assert_eq!(None, output_preview(output, 0));
+        assert_eq!(
+            Some("Header:\n - one\n… 2 more lines in the attached file".to_string()),
+            output_preview(output, 2)
+        );
+    }
+
+    #[test]
+    fn test_examples_parse() {
+        let config = config("");
+
+        let examples = EXAMPLES.iter().map(|(example, _)| *example);
+        for example in examples.chain(command_examples()) {
+            let cmd = Cmd::parse(example, &config)
+                .unwrap_or_else(|e| panic!("Failed to parse example '{example}': {e}"));
+            cmd.validate(&config)
+                .unwrap_or_else(|e| panic!("Invalid example '{example}': {e}"));
+        }
+    }
+
+    /// Returns a config with the required fields set, plus the given toml
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            matrix_homeserver_url = "https://matrix.example.org"
+            matrix_username = "@bot:example.org"
+            matrix_password = "password"
+            matrix_room_id = "!room:example.org"
+            firefly_url = "https://firefly.example.org"
+            firefly_api_key = "key"
+            firefly_source_account_id = 1
+            {extra}
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn descriptions(expenses: &[TransactionSplit]) -> Vec<&str> {
+        expenses.iter().map(|e| e.description.as_str()).collect()
+    }
+
+    #[test]
+    fn test_zero_amount() {
+        let free = parse_add("Sample: 0 #freebie");
+        assert_add_arg(free.clone(), "Sample", 0.0, None, vec!["freebie"]);
+        assert!(free.validate_amount(true).is_ok());
+        assert!(free.validate_amount(false).is_err());
+
+        let paid = parse_add("Sample: 0.01");
+        assert!(paid.validate_amount(true).is_ok());
+        assert!(paid.validate_amount(false).is_ok());
+    }
+
+    #[test]
+    fn test_category_creation_config() {
+        let default = config("");
+        assert_eq!(CategoryCreation::Implicit, default.category_creation);
+        assert!(!default.auto_create_categories);
+
+        let configured = config(
+            r#"
+            category_creation = "reject"
+            auto_create_categories = true
+            "#,
+        );
+        assert_eq!(CategoryCreation::Reject, configured.category_creation);
+        assert!(configured.auto_create_categories);
+    }
+
+    #[test]
+    fn test_required_tags() {
+        let required = config(
+            r#"
+            [required_tags]
+            Groceries = []
+            Travel = ["business", "personal"]
+            "#,
+        )
+        .required_tags;
+
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        assert!(check_required_tags(&required, "Groceries", &tags(&["food"])).is_ok());
+        assert!(check_required_tags(&required, "groceries", &tags(&[])).is_err());
+        assert!(check_required_tags(&required, "Travel", &tags(&["food", "Business"])).is_ok());
+        assert!(check_required_tags(&required, "Travel", &tags(&["food"])).is_err());
+        assert!(check_required_tags(&required, "Travel", &tags(&[])).is_err());
+        assert!(check_required_tags(&required, "Dining", &tags(&[])).is_ok());
+    }
+
+    fn category_page(names: &[&str], current_page: i64, total_pages: i64) -> Page<Category> {
+        serde_json::from_value(serde_json::json!({
+            "data": names
+                .iter()
+                .map(|name| serde_json::json!({"id": "1", "attributes": {"name": name}}))
+                .collect::<Vec<_>>(),
+            "meta": {"pagination": {"total": 5, "count": names.len(), "per_page": 2,
+                "current_page": current_page, "total_pages": total_pages}}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let rejected = |status: StatusCode| -> anyhow::Error {
+            TransactionError {
+                status,
+                body: String::new(),
+            }
+            .into()
+        };
+
+        assert!(!is_transient(&MaintenanceError.into()));
+        assert!(is_transient(&rejected(StatusCode::BAD_GATEWAY)));
+        assert!(!is_transient(&rejected(StatusCode::UNPROCESSABLE_ENTITY)));
+        assert!(!is_transient(&anyhow::anyhow!("Invalid amount")));
+
+        assert!(!is_transient_post(&MaintenanceError.into()));
+        assert!(is_transient_post(&rejected(StatusCode::BAD_GATEWAY)));
+        assert!(!is_transient_post(&rejected(
+            StatusCode::UNPROCESSABLE_ENTITY
+        )));
+    }
+
+    #[test]
+    fn test_greeting_text() {
+        let user_id = UserId::parse("@bob:example.org").unwrap();
+        let greeting = greeting_text(&user_id, "$");
+        assert!(greeting.starts_with("Welcome, @bob:example.org! Available commands:"));
+        assert!(greeting.contains("\n - $add"));
+        assert!(!greeting.contains("!add"));
+
+        assert!(greeting_text(&user_id, "!").contains("\n - !add"));
+    }
+
+    #[test]
+    fn test_with_prefix() {
+        assert_eq!(
+            "Invalid args. Usage: $limitset <Category> <Amount> | $limitset list",
+            with_prefix(
+                "Invalid args. Usage: !limitset <Category> <Amount> | !limitset list",
+                "$"
+            )
+        );
+        assert_eq!(
+            "- `$add Groceries: 12.50`: Wow! Nice (!)",
+            with_prefix("- `!add Groceries: 12.50`: Wow! Nice (!)", "$")
+        );
+        assert_eq!("Use !retry", with_prefix("Use !retry", "!"));
+    }
+
+    #[tokio::test]
+    async fn test_read_transaction_response() {
+        let response = |status: u16, body: &str| {
+            reqwest::Response::from(
+                matrix_sdk::ruma::exports::http::Response::builder()
+                    .status(status)
+                    .body(body.to_string())
+                    .unwrap(),
+            )
+        };
+
+        let created = response(201, r#"{"data": {"id": "42"}}"#);
+        assert_eq!(
+            Some("42".to_string()),
+            read_transaction_response(created).await.unwrap()
+        );
+        let unreadable = response(200, "{}");
+        assert_eq!(None, read_transaction_response(unreadable).await.unwrap());
+
+        let invalid = response(422, r#"{"message": "The given data was invalid."}"#);
+        assert!(read_transaction_response(invalid).await.is_err());
+        let maintenance = response(503, "Down for maintenance");
+        assert!(read_transaction_response(maintenance)
+            .await
+            .unwrap_err()
+            .is::<MaintenanceError>());
+    }
+
+    #[tokio::test]
+    async fn test_check_maintenance() {
+        let response = |status: u16, body: &str| {
+            reqwest::Response::from(
+                matrix_sdk::ruma::exports::http::Response::builder()
+                    .status(status)
+                    .body(body.to_string())
+                    .unwrap(),
+            )
+        };
+
+        let maintenance = response(503, "Down for maintenance");
+        assert!(check_maintenance(maintenance)
+            .await
+            .unwrap_err()
+            .is::<MaintenanceError>());
+        let restarting = response(
+            503,
+            r#"{"message":"Service Unavailable","exception":"HttpException"}"#,
+        );
+        assert!(check_maintenance(restarting)
+            .await
+            .unwrap_err()
+            .is::<UnavailableError>());
+
+        let attempts = Mutex::new(0);
+        let result = with_retries("get", 3, Duration::ZERO, is_transient, || async {
+            let attempt = {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                *attempts
+            };
+            let status = if attempt < 3 { 503 } else { 200 };
+            check_maintenance(response(status, "Service Unavailable"))
+                .await?
+                .text()
+                .await
+                .map_err(anyhow::Error::from)
+        })
+        .await;
+        assert_eq!("Service Unavailable", result.unwrap());
+        assert_eq!(3, *attempts.lock().unwrap());
+
+        let attempts = Mutex::new(0);
+        let result = with_retries("get", 3, Duration::ZERO, is_transient, || async {
+            *attempts.lock().unwrap() += 1;
+            check_maintenance(response(503, "Down for maintenance")).await
+        })
+        .await;
+        assert!(result.unwrap_err().is::<MaintenanceError>());
+        assert_eq!(1, *attempts.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_collect_pages() {
+        let requested = Mutex::new(Vec::new());
+        let categories = collect_pages(|page_num| {
+            requested.lock().unwrap().push(page_num);
+            async move {
+                Ok(match page_num {
+                    1 => category_page(&["Bills", "Dining"], 1, 3),
+                    2 => category_page(&["Fun", "Gas"], 2, 3),
+                    _ => category_page(&["Groceries"], 3, 3),
+                })
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(vec![1, 2, 3], *requested.lock().unwrap());
+        assert_eq!(
+            vec!["Bills", "Dining", "Fun", "Gas", "Groceries"],
+            categories
+                .into_iter()
+                .map(|category| category.attributes.name)
+                .collect::<Vec<_>>()
+        );
+
+        let single = collect_pages(|_| async { Ok(category_page(&["Bills"], 1, 1)) })
+            .await
+            .unwrap();
+        assert_eq!(1, single.len());
+
+        let failed: anyhow::Result<Vec<Category>> = collect_pages(|page_num| async move {
+            match page_num {
+                1 => Ok(category_page(&["Bills", "Dining"], 1, 2)),
+                _ => Err(anyhow::anyhow!("Firefly is down")),
+            }
+        })
+        .await;
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    fn test_exchange_rates() {
+        let page: Page<ExchangeRate> = serde_json::from_str(
+            r#"{
+                "data": [
+                    {"type": "currency_exchange_rates", "id": "1", "attributes": {
+                        "from_currency_code": "EUR", "to_currency_code": "USD",
+                        "rate": "1.080000000000", "date": "2024-01-09T00:00:00+00:00"}},
+                    {"type": "currency_exchange_rates", "id": "2", "attributes": {
+                        "from_currency_code": "EUR", "to_currency_code": "USD",
+                        "rate": "1.090000000000", "date": "2024-01-10T00:00:00+00:00"}},
+                    {"type": "currency_exchange_rates", "id": "3", "attributes": {
+                        "from_currency_code": "CAD", "to_currency_code": "USD",
+                        "rate": "0.745000000000", "date": "2024-01-10T00:00:00+00:00"}}
+                ],
+                "meta": {"pagination": {"total": 3, "count": 3, "per_page": 50,
+                    "current_page": 1, "total_pages": 1}}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(3, page.data.len());
+        assert_eq!(1.08, page.data[0].attributes.rate);
+
+        let latest = latest_rates(page.data);
+        assert_eq!(2, latest.len());
+        assert_eq!("CAD", latest[0].from_currency_code);
+        assert_eq!(1.09, latest[1].rate);
+
+        let output = format_rates(&latest);
+        assert!(output.contains("EUR → USD: 1.0900"));
+        assert!(output.contains("CAD → USD: 0.7450"));
+    }
+
+    #[test]
+    fn test_new_piggy_bank() {
+        let goal = GoalArgs::parse("Vacation: $2000").unwrap();
+        assert_eq!(
+            GoalArgs {
+                name: "Vacation".to_string(),
+                target: 2000.0
+            },
+            goal
+        );
+        assert!(GoalArgs::parse("Vacation").is_err());
+        assert!(GoalArgs::parse(": 2000").is_err());
+        assert!(GoalArgs::parse("Vacation: 0").is_err());
+        assert!(GoalArgs::parse("Vacation: lots").is_err());
+
+        assert_eq!(
+            serde_json::json!({
+                "name": "Vacation",
+                "account_id": "3",
+                "target_amount": "2000.00"
+            }),
+            serde_json::to_value(NewPiggyBank::new(&goal, 3)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unauthorized_response() {
+        assert_eq!(
+            Some(Response::Reaction(UNAUTHORIZED_REACTION.to_string())),
+            unauthorized_response(&config(r#"allowed_users = ["@alice:example.org"]"#))
+        );
+        assert_eq!(
+            None,
+            unauthorized_response(&config(r#"unauthorized_action = "ignore""#))
+        );
+        assert_eq!(
+            Some(Response::Reaction(UNAUTHORIZED_REACTION.to_string())),
+            unauthorized_response(&config(r#"unauthorized_action = "react""#))
+        );
+        assert_eq!(
+            Some(Response::Message(
+                "You are not allowed to use this bot".to_string()
+            )),
+            unauthorized_response(&config(r#"unauthorized_action = "message""#))
+        );
+        assert_eq!(
+            Some(Response::Message("Ask Alice for access".to_string())),
+            unauthorized_response(&config(
+                r#"
+                unauthorized_action = "message"
+                unauthorized_message = "Ask Alice for access"
+                "#
+            ))
+        );
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let message = || Some("Invalid args. Usage: !net [Days]".to_string());
+        assert_eq!(
+            vec![
+                Response::Message("Invalid args. Usage: !net [Days]".to_string()),
+                Response::Reaction(INVALID_REACTION.to_string()),
+            ],
+            error_responses(ErrorKind::Parse, message(), &config(""))
+        );
+        assert_eq!(
+            vec![Response::Reaction(INVALID_REACTION.to_string())],
+            error_responses(
+                ErrorKind::Parse,
+                message(),
+                &config("parse_error_messages = false")
+            )
+        );
+        assert_eq!(
+            vec![
+                Response::Message("Failed to delete transaction".to_string()),
+                Response::Reaction(FAILED_REACTION.to_string()),
+            ],
+            error_responses(
+                ErrorKind::Upstream,
+                Some("Failed to delete transaction".to_string()),
+                &config("parse_error_messages = false")
+            )
+        );
+        assert_eq!(
+            vec![Response::Reaction(FAILED_REACTION.to_string())],
+            error_responses(ErrorKind::Upstream, None, &config(""))
+        );
+    }
+
+    #[test]
+    fn test_parse_month_set() {
+        let config = config("");
+        assert!(matches!(
+            Cmd::parse("!monthset 25", &config),
+            Ok(Cmd::MonthSet(25))
+        ));
+        assert!(matches!(
+            Cmd::parse("!monthset 31", &config),
+            Ok(Cmd::MonthSet(31))
+        ));
+        assert!(Cmd::parse("!monthset 0", &config).is_err());
+        assert!(Cmd::parse("!monthset 32", &config).is_err());
+        assert!(Cmd::parse("!monthset", &config).is_err());
+    }
+
+    #[test]
+    fn test_user_accounts() {
+        let config = config(
+            r#"
+            [[rooms]]
+            room_id = "!personal:example.org"
+            source_account_id = 7
+
+            [user_accounts]
+            "@alice:example.org" = 3
+            "#,
+        );
+        let family = <&RoomId>::try_from("!room:example.org").unwrap();
+        let personal = <&RoomId>::try_from("!personal:example.org").unwrap();
+        let alice = <&UserId>::try_from("@alice:example.org").unwrap();
+        let bob = <&UserId>::try_from("@bob:example.org").unwrap();
+
+        assert_eq!(3, config.source_account_id(family, alice));
+        assert_eq!(3, config.source_account_id(personal, alice));
+        assert_eq!(1, config.source_account_id(family, bob));
+        assert_eq!(7, config.source_account_id(personal, bob));
+        assert!(config.validate().is_empty());
+
+        let mut invalid = config;
+        invalid.user_accounts.insert("alice".to_string(), 3);
+        assert_eq!(1, invalid.validate().len());
+    }
+
+    #[test]
+    fn test_should_accept_invite() {
+        let room_ids = vec![OwnedRoomId::try_from("!room:example.org").unwrap()];
+        let room = <&RoomId>::try_from("!room:example.org").unwrap();
+        let other_room = <&RoomId>::try_from("!other:example.org").unwrap();
+        let alice = <&UserId>::try_from("@alice:example.org").unwrap();
+        let mallory = <&UserId>::try_from("@mallory:example.org").unwrap();
+
+        assert!(!should_accept_invite(&config(""), &room_ids, room, alice));
+
+        let auto_join = config("auto_join = true");
+        assert!(should_accept_invite(&auto_join, &room_ids, room, mallory));
+        assert!(!should_accept_invite(
+            &auto_join, &room_ids, other_room, alice
+        ));
+
+        let allowed_only = config(
+            r#"
+            auto_join = true
+            auto_join_allowed_users_only = true
+            allowed_users = ["@alice:example.org"]
+            "#,
+        );
+        assert!(should_accept_invite(&allowed_only, &room_ids, room, alice));
+        assert!(!should_accept_invite(
+            &allowed_only,
+            &room_ids,
+            room,
+            mallory
+        ));
+    }
+
+    #[test]
+    fn test_membership_problem() {
+        let room_id = <&RoomId>::try_from("!room:example.org").unwrap();
+        assert_eq!(None, membership_problem(room_id, Some(RoomType::Joined)));
+        assert_eq!(
+            Some("The bot was invited to !room:example.org but hasn't joined it. Accept the invite, or set auto_join = true".to_string()),
+            membership_problem(room_id, Some(RoomType::Invited))
+        );
+        assert!(membership_problem(room_id, Some(RoomType::Left)).is_some());
+        assert_eq!(
+            Some("The bot is not in !room:example.org. Check the room id, and invite the bot to the room".to_string()),
+            membership_problem(room_id, None)
+        );
+    }
+
+    #[test]
+    fn test_room_ids() {
+        let single = config("");
+        assert_eq!(vec!["!room:example.org"], room_ids(&single).unwrap());
+
+        let multiple = config(
+            r#"
+            matrix_room_ids = ["!family:example.org", "!personal:example.org"]
+
+            [[rooms]]
+            room_id = "!personal:example.org"
+            source_account_id = 7
+
+            [[rooms]]
+            room_id = "!joint:example.org"
+            "#,
+        );
+        assert_eq!(
+            vec![
+                "!family:example.org",
+                "!personal:example.org",
+                "!joint:example.org"
+            ],
+            room_ids(&multiple).unwrap()
+        );
+        let alice = <&UserId>::try_from("@alice:example.org").unwrap();
+        assert_eq!(
+            1,
+            multiple.source_account_id(<&RoomId>::try_from("!family:example.org").unwrap(), alice)
+        );
+        assert_eq!(
+            7,
+            multiple
+                .source_account_id(<&RoomId>::try_from("!personal:example.org").unwrap(), alice)
+        );
+        assert_eq!(
+            1,
+            multiple.source_account_id(<&RoomId>::try_from("!joint:example.org").unwrap(), alice)
+        );
+
+        assert!(room_ids(&config(r#"matrix_room_ids = ["room"]"#)).is_err());
+    }
+
+    #[test]
+    fn test_matrix_login() {
+        assert_eq!(
+            MatrixLogin::Password("password"),
+            matrix_login(&config("")).unwrap()
+        );
+
+        let token = |extra: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                matrix_homeserver_url = "https://matrix.example.org"
+                matrix_username = "@bot:example.org"
+                matrix_room_id = "!room:example.org"
+                firefly_url = "https://firefly.example.org"
+                firefly_api_key = "key"
+                firefly_source_account_id = 1
+                {extra}
+                "#
+            ))
+            .unwrap()
+        };
+        let config = token(
+            r#"
+            matrix_access_token = "token"
+            matrix_device_id = "DEVICE"
+            "#,
+        );
+        assert_eq!(
+            MatrixLogin::AccessToken {
+                access_token: "token",
+                device_id: "DEVICE"
+            },
+            matrix_login(&config).unwrap()
+        );
+        assert!(matrix_login(&token(r#"matrix_access_token = "token""#)).is_err());
+        assert!(matrix_login(&token(r#"matrix_password = """#)).is_err());
+        assert!(matrix_login(&token("")).is_err());
+    }
+
+    #[test]
+    fn test_redacted_config() {
+        let config = config(
+            r#"
+            admin_users = ["@alice:example.org"]
+
+            [user_tags]
+            "@alice:example.org" = "Alice"
+            "#,
+        );
+        let dump = redacted_config(&config).unwrap();
+
+        assert!(!dump.contains(&format!(
+            "\"{}\"",
+            config.matrix_password.as_deref().unwrap()
+        )));
+        assert!(!dump.contains(&format!("\"{}\"", config.firefly_api_key)));
+        assert!(dump.contains(&format!("matrix_password = \"{REDACTED}\"")));
+        assert!(dump.contains(&format!("firefly_api_key = \"{REDACTED}\"")));
+        assert!(dump.contains("matrix_username = \"@bot:example.org\""));
+        assert!(dump.contains("\"@alice:example.org\" = \"Alice\""));
+    }
+
+    #[test]
+    fn test_parse_foreign_currency() {
+        let euros = parse_add("Travel: €50 hotel #trip");
+        assert_eq!(Some("EUR".to_string()), euros.currency);
+        assert_eq!(50.0, euros.amount);
+        assert_eq!(Some("hotel".to_string()), euros.note);
+
+        let pounds = parse_add("Travel: 12.5£");
+        assert_eq!(Some("GBP".to_string()), pounds.currency);
+        assert_eq!(12.5, pounds.amount);
+
+        let code = parse_add("Travel: 50 EUR @2024-05-01 hotel");
+        assert_eq!(Some("EUR".to_string()), code.currency);
+        assert_eq!(Some("hotel".to_string()), code.note);
+        assert!(code.date.is_some());
+
+        let bbq = parse_add("Eating Out: 30 BBQ with friends");
+        assert_eq!(None, bbq.currency);
+        assert_eq!(Some("BBQ with friends".to_string()), bbq.note);
+
+        let note = parse_add("Travel: 50 eur hotel");
+        assert_eq!(None, note.currency);
+        assert_eq!(Some("eur hotel".to_string()), note.note);
+        assert_eq!(None, parse_add("Travel: $50").currency);
+        assert!(AddArgs::parse("Travel: € hotel", NoteMode::Standard, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_amount_expression() {
+        let dinner = parse_add("Dinner: 60/3 my share");
+        assert_eq!(20.0, dinner.amount);
+        assert_eq!(Some("my share".to_string()), dinner.note);
+
+        let groceries = parse_add("Groceries: 2*3+1.5 EUR two items");
+        assert_eq!(7.5, groceries.amount);
+        assert_eq!(Some("EUR".to_string()), groceries.currency);
+        assert_eq!(Some("two items".to_string()), groceries.note);
+
+        assert_eq!(
+            "Invalid amount: 60/",
+            AddArgs::parse("Dinner: 60/ my share", NoteMode::Standard, false)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_categories() {
+        let config = config("");
+        assert_eq!(300, config.category_cache_ttl_secs);
+        assert!(matches!(
+            Cmd::parse("!categories", &config).unwrap(),
+            Cmd::Categories {
+                refresh: false,
+                search: None
+            }
+        ));
+        assert!(matches!(
+            Cmd::parse("!categories refresh", &config).unwrap(),
+            Cmd::Categories {
+                refresh: true,
+                search: None
+            }
+        ));
+        assert!(matches!(
+            Cmd::parse("!categories  eating out ", &config).unwrap(),
+            Cmd::Categories { refresh: false, search: Some(search) } if search == "eating out"
+        ));
+    }
+
+    #[test]
+    fn test_format_categories() {
+        let categories = ["Eating Out", "Groceries", "Household"].map(String::from);
+        assert_eq!(
+            "Categories:\n - Eating Out\n - Groceries\n - Household",
+            format_categories(&categories, None)
+        );
+        assert_eq!(
+            "Categories matching 'GROC':\n - Groceries",
+            format_categories(&categories, Some("GROC"))
+        );
+        assert_eq!(
+            "Categories matching 'o':\n - Eating Out\n - Groceries\n - Household",
+            format_categories(&categories, Some("o"))
+        );
+        assert_eq!(
+            "No categories match 'travel'",
+            format_categories(&categories, Some("travel"))
+        );
+        assert_eq!("Categories:", format_categories(&[], None));
+    }
+
+    #[test]
+    fn test_new_tags_note() {
+        assert_eq!(None, new_tags_note(&[]));
+        assert_eq!(
+            Some("new tag: vacation".to_string()),
+            new_tags_note(&["vacation".to_string()])
+        );
+        assert_eq!(
+            Some("new tags: vacation, beach".to_string()),
+            new_tags_note(&["vacation".to_string(), "beach".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_about_deserialization() {
+        let about: Single<About> = serde_json::from_str(
+            r#"{"data": {"version": "6.1.0", "api_version": "2.0.12", "php_version": "8.3.2", "os": "Linux", "driver": "pgsql"}}"#,
+        )
+        .unwrap();
+        assert_eq!("6.1.0", about.data.version);
+        assert_eq!("2.0.12", about.data.api_version);
+    }
+
+    #[test]
+    fn test_format_accounts() {
+        assert_eq!("No asset accounts", format_accounts(Vec::new()));
+        assert_eq!(
+            "Asset accounts:\n - Checking: 1\n - savings: 4",
+            format_accounts(vec![
+                MirroredAccount {
+                    id: 4,
+                    name: "savings".to_string(),
+                },
+                MirroredAccount {
+                    id: 1,
+                    name: "Checking".to_string(),
+                },
+            ])
+        );
+        assert!(matches!(
+            Cmd::parse("!accounts", &config("")),
+            Ok(Cmd::Accounts)
+        ));
+    }
+
+    #[test]
+    fn test_format_tags() {
+        assert_eq!("No tags", format_tags(Vec::new()));
+        assert_eq!(
+            "Tags:\n - food\n - vacation\n - Vacation\n - weekend",
+            format_tags(
+                ["vacation", "weekend", "food", "Vacation"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_budgets() {
+        assert_eq!("No budgets are defined", format_budgets(&[]));
+
+        let budget = |name: &str, spent: f64, limit: Option<f64>| BudgetStatus {
+            name: name.to_string(),
+            spent,
+            limit,
+        };
+        assert_eq!(
+            "Budgets this month:\n - Groceries: 120.00 spent, 180.00 remaining\n - Eating Out: 130.00 spent, 30.00 over\n - Fun: 20.50 spent, no limit",
+            format_budgets(&[
+                budget("Groceries", 120.0, Some(300.0)),
+                budget("Eating Out", 130.0, Some(100.0)),
+                budget("Fun", 20.5, None),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_budget_deserialization() {
+        let budget: Budget = serde_json::from_value(serde_json::json!({
+            "id": "3",
+            "attributes": {
+                "name": "Groceries",
+                "spent": [{"sum": "-120.50", "currency_code": "USD"}],
+            },
+        }))
+        .unwrap();
+        assert_eq!(-120.5, budget.attributes.spent[0].sum);
+
+        let limit: BudgetLimit = serde_json::from_value(serde_json::json!({
+            "id": "7",
+            "attributes": {"amount": "300.00", "start": "2024-05-01"},
+        }))
+        .unwrap();
+        assert_eq!(300.0, limit.attributes.amount);
+    }
+
+    #[test]
+    fn test_confirmation_content() {
+        assert_eq!(ReplyMode::Reaction, config("").reply_mode);
+        assert_eq!(
+            ReplyMode::Thread,
+            config("reply_mode = \"thread\"").reply_mode
+        );
+
+        let event_id = EventId::parse("$command:example.org").unwrap();
+        assert_eq!(
+            serde_json::json!({ "msgtype": "m.text", "body": "Added" }),
+            confirmation_content("Added", &event_id, ReplyMode::Reaction)
+        );
+        assert_eq!(
+            serde_json::json!({
+                "msgtype": "m.text",
+                "body": "Added",
+                "m.relates_to": { "m.in_reply_to": { "event_id": "$command:example.org" } },
+            }),
+            confirmation_content("Added", &event_id, ReplyMode::Reply)
+        );
+
+        let thread = confirmation_content("Added", &event_id, ReplyMode::Thread);
+        assert_eq!("m.thread", thread["m.relates_to"]["rel_type"]);
+        assert_eq!("$command:example.org", thread["m.relates_to"]["event_id"]);
+        assert_eq!(
+            "$command:example.org",
+            thread["m.relates_to"]["m.in_reply_to"]["event_id"]
+        );
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(config("").validate().is_empty());
+
+        let invalid: Config = toml::from_str(
+            r#"
+            matrix_homeserver_url = "matrix.example.org"
+            matrix_username = "@bot:example.org"
+            matrix_password = "password"
+            matrix_room_ids = ["!room:example.org", "room"]
+            firefly_url = "ftp://firefly.example.org"
+            firefly_api_key = " "
+            firefly_source_account_id = 1
+            command_prefix = ""
+            "#,
+        )
+        .unwrap();
+        let problems = invalid.validate();
+        assert_eq!(5, problems.len());
+        assert!(problems[0].starts_with("matrix_homeserver_url is not a valid URL"));
+        assert_eq!(
+            "firefly_url must be an http or https URL, but was 'ftp://firefly.example.org'",
+            problems[1]
+        );
+        assert_eq!("firefly_api_key must not be empty", problems[2]);
+        assert!(problems[3].starts_with("matrix_room_ids is not a valid room id, 'room'"));
+        assert!(problems[4].starts_with("command_prefix must not be empty"));
+
+        let mut no_rooms = config("");
+        no_rooms.matrix_room_id = Some(String::new());
+        assert_eq!(
+            vec!["At least one of matrix_room_ids, matrix_room_id, or rooms must be set"],
+            no_rooms.validate()
+        );
+
+        assert_eq!(
+            vec![
+                "mirror_refresh_secs must be greater than 0",
+                "category_separator must not be empty"
+            ],
+            config(
+                r#"
+                mirror_refresh_secs = 0
+                category_separator = ""
+                "#
+            )
+            .validate()
+        );
+    }
+
+    #[test]
+    fn test_amount_rounding() {
+        assert_eq!(4.25, round_amount(4.25, AmountRounding::Reject).unwrap());
+        assert_eq!(12.0, round_amount(12.0, AmountRounding::Reject).unwrap());
+        assert_eq!(
+            "Invalid amount: 4.25917. Use at most two decimal places",
+            round_amount(4.25917, AmountRounding::Reject)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(4.26, round_amount(4.25917, AmountRounding::Round).unwrap());
+        assert_eq!(2.68, round_amount(2.675001, AmountRounding::Round).unwrap());
+
+        let reject = config("");
+        assert_eq!(AmountRounding::Reject, reject.amount_rounding);
+        assert!(Cmd::parse("!add Coffee: 4.25917", &reject).is_err());
+        assert!(Cmd::parse("!add Coffee: 4.25, Snacks: 1.005", &reject).is_err());
+        assert!(Cmd::parse("!deposit Salary: 3000.001", &reject).is_err());
+        assert!(Cmd::parse("!edit amount 4.999", &reject).is_err());
+        assert!(Cmd::parse("!settle alice bob 10.005", &reject).is_err());
+        assert!(Cmd::parse("!goaladd Vacation: 1000.001", &reject).is_err());
+        assert!(Cmd::parse("!limitset Groceries 300.001", &reject).is_err());
+        assert!(Cmd::parse("!convert 10.001 EUR USD", &reject).is_err());
+        assert!(matches!(
+            Cmd::parse("!add Dinner: 100/3", &reject),
+            Ok(Cmd::Add(args)) if args.amount == 33.33
+        ));
+
+        let round = config(r#"amount_rounding = "round""#);
+        assert!(matches!(
+            Cmd::parse("!add Coffee: 4.25917", &round),
+            Ok(Cmd::Add(args)) if args.amount == 4.26
+        ));
+        assert!(matches!(
+            Cmd::parse("!edit amount 4.999", &round),
+            Ok(Cmd::Edit(EditArgs::Amount(amount))) if amount == 5.0
+        ));
+        assert!(matches!(
+            Cmd::parse("!settle alice bob 10.005", &round),
+            Ok(Cmd::Settle(args)) if args.amount == 10.01
+        ));
+
+        for amount in ["NaN", "inf", "-inf", "1/0"] {
+            assert!(Cmd::parse(&format!("!add Coffee: {amount}"), &round).is_err());
+        }
+        assert!(Cmd::parse("!settle alice bob inf", &round).is_err());
+        assert!(Cmd::parse("!goaladd Vacation: inf", &round).is_err());
+    }
+
+    #[test]
+    fn test_parse_decimal_comma() {
+        let comma = |args: &str| AddArgs::parse(args, NoteMode::Standard, true);
+
+        let groceries = comma("Groceries: 12,50 milk").unwrap();
+        assert_eq!(12.5, groceries.amount);
+        assert_eq!(Some("milk".to_string()), groceries.note);
+        assert_eq!(3.0, comma("Groceries: 1,5*2").unwrap().amount);
+        assert_eq!(42.1, comma("Groceries: 42.10").unwrap().amount);
+        assert_eq!(
+            Some("EUR".to_string()),
+            comma("Travel: 9,99€").unwrap().currency
+        );
+
+        for ambiguous in ["1,000", "1,000,000", "1.000,50", "12,", "12,5a"] {
+            assert_eq!(
+                format!("Invalid amount: {ambiguous}"),
+                comma(&format!("Groceries: {ambiguous}"))
+                    .unwrap_err()
+                    .to_string()
+            );
+        }
+
+        assert!(AddArgs::parse("Groceries: 12,50 milk", NoteMode::Standard, false).is_err());
+        assert!(!config("").decimal_comma);
+        assert!(config("decimal_comma = true").decimal_comma);
+    }
+
+    #[test]
+    fn test_parse_refund() {
+        let refund = parse_add("Groceries: -12.50 refund #food");
+        assert!(refund.refund);
+        assert_eq!(12.5, refund.amount);
+        assert_eq!(Some("refund".to_string()), refund.note);
+        assert!(!parse_add("Groceries: 12.50").refund);
+
+        assert!(
+            AddArgs::parse("Groceries: -12.50, Household: 5", NoteMode::Standard, false).is_err()
+        );
+        assert!(
+            AddArgs::parse("Groceries: 12.50, Household: -5", NoteMode::Standard, false).is_err()
+        );
+
+        let config = config(r#"income_keywords = ["Salary"]"#);
+        assert!(Cmd::parse("!add Groceries: -12.50", &config)
+            .unwrap()
+            .validate(&config)
+            .is_ok());
+        assert!(Cmd::parse("!add Salary: -100", &config)
+            .unwrap()
+            .validate(&config)
+            .is_err());
+        assert!(Cmd::parse("!deposit Salary: -100", &config)
+            .unwrap()
+            .validate(&config)
+            .is_err());
+        assert!(Cmd::parse("!deposit -50 Salary", &config)
+            .unwrap()
+            .validate(&config)
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_split_shares() {
+        let config = config(r#"split_remainder = "first""#);
+        assert!(
+            Cmd::parse("!split alice,bob,carol Groceries: 0.03", &config)
+                .unwrap()
+                .validate(&config)
+                .is_ok()
+        );
+        assert!(
+            Cmd::parse("!split alice,bob,carol Groceries: 0.02", &config)
+                .unwrap()
+                .validate(&config)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_dry_run() {
+        let dry_run = parse_add("--dry-run Groceries: 40 #food");
+        assert!(dry_run.dry_run);
+        assert_eq!("Groceries", dry_run.category);
+        assert_eq!(vec!["food"], dry_run.tags);
+        assert!(!parse_add("Groceries: 40 #food").dry_run);
+
+        let config = config("");
+        assert!(Cmd::parse("!add --dry-run Groceries: 40", &config)
+            .unwrap()
+            .validate(&config)
+            .is_ok());
+        assert!(Cmd::parse("!deposit --dry-run Salary: 3000", &config)
+            .unwrap()
+            .validate(&config)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_category_splits() {
+        let target = parse_add("Groceries: 40, Household: 15 target run #errands");
+        assert_eq!("Groceries", target.category);
+        assert_eq!(40.0, target.amount);
+        assert_eq!(
+            vec![CategoryAmount {
+                category: "Household".to_string(),
+                amount: 15.0,
+            }],
+            target.splits
+        );
+        assert_eq!(Some("target run".to_string()), target.note);
+        assert_eq!(vec!["errands"], target.tags);
+        assert_eq!(
+            "40.00 to Groceries and 15.00 to Household",
+            target.describe_amounts()
+        );
 
-            match cmd {
-                Cmd::Ping => send_message("pong".to_string(), &room).await?,
-                Cmd::Help => {
-                    send_message(
-                        format!(
-                            "Available commands:\n - {ADD_USAGE}\n - {CATEGORIES_CMD}\n - {HELP_CMD}\n - {PING_CMD}"
-                        ),
-                        &room,
-                    )
-                    .await?;
-                }
-                Cmd::Categories => match self.list_categories().await {
-                    Ok(categories) => {
-                        let mut response = String::new();
-                        response.push_str("Categories:");
+        let euros = parse_add("Groceries: €40, Eating Out: 12.50, Gifts: 5 @today");
+        assert_eq!(Some("EUR".to_string()), euros.currency);
+        assert_eq!(
+            vec![("Groceries", 40.0), ("Eating Out", 12.5), ("Gifts", 5.0)],
+            euros.category_amounts().collect::<Vec<_>>()
+        );
+        assert!(euros.date.is_some());
 
-                        if !categories.is_empty() {
-                            response.push_str("\n - ");
-                            response.push_str(&categories.join("\n - "));
-                        }
+        // A comma in the note doesn't start a split
+        assert!(parse_add("Groceries: 40 milk, eggs: fresh")
+            .splits
+            .is_empty());
 
-                        send_message(response, &room).await?;
-                    }
-                    Err(e) => {
-                        error!("Failed to list categories: {}", e);
-                        send_message("Failed to list categories".to_string(), &room).await?;
-                    }
-                },
-                Cmd::Add(AddArgs {
-                    category,
-                    amount,
-                    note,
-                    tags,
-                }) => {
-                    match self
-                        .add_expense(&category, amount, username, timestamp, note, tags)
-                        .await
-                    {
-                        Ok(_) => {
-                            send_reaction("✅".to_owned(), event.event_id.clone(), &room).await?;
-                        }
-                        Err(e) => {
-                            error!("{e}");
-                            send_reaction("❌".to_owned(), event.event_id.clone(), &room).await?;
-                        }
-                    }
-                }
-            }
-        }
+        assert!(AddArgs::parse("Groceries: 40, Household: x", NoteMode::Standard, false).is_err());
+        assert!(AddArgs::parse("Travel: €40, Hotel: £15", NoteMode::Standard, false).is_err());
 
-        Ok(())
+        let config = config("");
+        assert!(Cmd::Add(parse_add("Groceries: 40, Household: 0"))
+            .validate(&config)
+            .is_err());
+        assert!(Cmd::Add(parse_add("Groceries: 40, Household: 15"))
+            .validate(&config)
+            .is_ok());
     }
 
-    async fn add_expense(
-        &self,
-        category: &str,
-        amount: f64,
-        username: &str,
-        timestamp: SystemTime,
-        note: Option<String>,
-        tags: Vec<String>,
-    ) -> anyhow::Result<()> {
-        let transaction = Transactions::new(Transaction::withdrawal(
-            category.to_string(),
-            amount,
-            timestamp.into(),
-            self.config.firefly_source_account_id,
-            FIREFLY_GENERAL_EXPENSE.to_string(),
-            username.to_string(),
-            note,
-            tags,
-        ));
+    #[test]
+    fn test_parse_payee() {
+        let costco = parse_add("Groceries: 30 >Costco milk #food");
+        assert_eq!(Some("Costco".to_string()), costco.payee);
+        assert_eq!(Some("milk".to_string()), costco.note);
+        assert_eq!(vec!["food"], costco.tags);
 
-        let response = self
-            .http_client
-            .post(format!(
-                "{}/{FIREFLY_TRANSACTIONS_API}",
-                self.config.firefly_url
-            ))
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.config.firefly_api_key),
-            )
-            .json(&transaction)
-            .send()
-            .await;
+        let enclosed = parse_add("Groceries: 30 @2024-05-01 >{Corner Store}");
+        assert_eq!(Some("Corner Store".to_string()), enclosed.payee);
+        assert_eq!(None, enclosed.note);
+        assert!(enclosed.date.is_some());
 
-        match response {
-            Ok(response) if response.status() != StatusCode::OK => {
-                return Err(anyhow!(
-                    "Failed to add transaction: [{:?}] {}",
-                    response.status(),
-                    response
-                        .text()
-                        .await
-                        .unwrap_or_else(|_| { "failed to read response body".to_string() })
-                ));
-            }
-            Err(e) => {
-                return Err(anyhow!("Failed to execute HTTP request: {e}"));
-            }
-            _ => {}
-        }
+        let quoted = parse_add(r##"Groceries: 30 >"Trader Joe's"#snacks"##);
+        assert_eq!(Some("Trader Joe's".to_string()), quoted.payee);
+        assert_eq!(vec!["snacks"], quoted.tags);
 
-        Ok(())
+        let tagged = parse_add("Groceries: 30 >Costco#food");
+        assert_eq!(Some("Costco".to_string()), tagged.payee);
+        assert_eq!(vec!["food"], tagged.tags);
+
+        assert_eq!(None, parse_add("Groceries: 30 milk > eggs").payee);
+        assert_eq!(None, parse_add("Groceries: 30 > milk").payee);
     }
 
-    async fn list_categories(&self) -> anyhow::Result<Vec<String>> {
-        let response: ListCategories = self
-            .http_client
-            .get(format!(
-                "{}/{FIREFLY_CATEGORIES_API}",
-                self.config.firefly_url
-            ))
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.config.firefly_api_key),
+    #[test]
+    fn test_foreign_currency_serialization() {
+        let withdrawal = |currency| {
+            Transaction::withdrawal(
+                "Travel".to_string(),
+                50.0,
+                Local::now(),
+                1,
+                "General expense".to_string(),
+                "alice".to_string(),
+                PersonAttribution::Both,
+                DEFAULT_DESCRIPTION_TEMPLATE,
+                None,
+                Vec::new(),
             )
-            .send()
-            .await?
-            .json()
-            .await?;
+            .in_currency(currency)
+        };
 
-        Ok(response
-            .data
-            .into_iter()
-            .map(|cat| cat.attributes.name)
-            .collect())
+        let foreign = serde_json::to_value(withdrawal(Some("EUR"))).unwrap();
+        assert_eq!(50.0, foreign["foreign_amount"]);
+        assert_eq!("EUR", foreign["foreign_currency_code"]);
+
+        let local = serde_json::to_value(withdrawal(None)).unwrap();
+        assert!(local.get("foreign_amount").is_none());
+        assert!(local.get("foreign_currency_code").is_none());
     }
-}
 
-impl Cmd {
-    fn parse(input: &str) -> anyhow::Result<Self> {
-        let cmd_end = input.find(' ').unwrap_or(input.len());
-        let cmd_str = &input[..cmd_end];
-        let cmd_args = if cmd_end == input.len() {
-            ""
-        } else {
-            &input[cmd_end + 1..]
+    #[test]
+    fn test_batch_transactions() {
+        let date = Local::now();
+        let withdrawal = |person: &str, amount: f64| {
+            Transaction::withdrawal(
+                "Dinner".to_string(),
+                amount,
+                date,
+                1,
+                "General expense".to_string(),
+                person.to_string(),
+                PersonAttribution::Both,
+                DEFAULT_DESCRIPTION_TEMPLATE,
+                Some("pizza".to_string()),
+                vec!["food".to_string()],
+            )
         };
 
-        match cmd_str {
-            HELP_CMD => Ok(Cmd::Help),
-            PING_CMD => Ok(Cmd::Ping),
-            CATEGORIES_CMD => Ok(Cmd::Categories),
-            ADD_CMD => Ok(Cmd::Add(AddArgs::parse(cmd_args)?)),
-            _ => Err(anyhow!("Unknown command: {cmd_str}")),
-        }
+        let batch = serde_json::to_value(Transactions::batch(
+            "Dinner split between alice, bob".to_string(),
+            vec![withdrawal("alice", 30.01), withdrawal("bob", 30.0)],
+        ))
+        .unwrap();
+
+        assert_eq!("Dinner split between alice, bob", batch["group_title"]);
+        let transactions = batch["transactions"].as_array().unwrap();
+        assert_eq!(2, transactions.len());
+        assert_eq!("withdrawal", transactions[0]["type"]);
+        assert_eq!(30.01, transactions[0]["amount"]);
+        assert_eq!("Dinner by alice", transactions[0]["description"]);
+        assert_eq!(serde_json::json!(["food", "bob"]), transactions[1]["tags"]);
+        assert_eq!(1, transactions[1]["source_id"]);
+        assert!(transactions[1].get("destination_id").is_none());
+
+        let single = serde_json::to_value(Transactions::batch(
+            "Dinner split between alice".to_string(),
+            vec![withdrawal("alice", 60.0)],
+        ))
+        .unwrap();
+        assert!(single.get("group_title").is_none());
     }
-}
 
-impl AddArgs {
-    fn parse(args: &str) -> anyhow::Result<Self> {
-        if let Some((category, rest)) = args.split_once(':') {
-            let (amount, rest) = rest
-                .trim()
-                .split_once(' ')
-                .map(|(amount, rest)| {
-                    let trimmed = rest.trim();
-                    (
-                        amount,
-                        if trimmed.is_empty() {
-                            None
-                        } else {
-                            Some(trimmed)
-                        },
-                    )
-                })
-                .unwrap_or((rest, None));
+    #[test]
+    fn test_should_redact() {
+        let enabled = config("auto_redact_commands = true");
+        assert!(should_redact(&enabled, true));
+        assert!(!should_redact(&enabled, false));
 
-            let has_note = rest.map(|rest| !rest.starts_with('#')).unwrap_or(false);
+        let disabled = config("");
+        assert!(!should_redact(&disabled, true));
+        assert!(!should_redact(&disabled, false));
+    }
 
-            let text_parts = rest.map(|rest| {
-                rest.split('#')
-                    .map(|part| part.trim().to_string())
-                    .filter(|part| !part.is_empty())
-                    .collect::<Vec<_>>()
-            });
+    #[test]
+    fn test_check_writable() {
+        let root = std::env::temp_dir().join(format!("firefly-bot-test-{}", std::process::id()));
+        let dir = root.join("cache");
 
-            let note = text_parts.as_ref().and_then(|parts| {
-                if has_note {
-                    parts.get(0).cloned()
-                } else {
-                    None
-                }
-            });
+        check_writable(&dir).unwrap();
+        assert!(dir.is_dir());
+        assert!(!dir.join(".write-check").exists());
 
-            let tags = if has_note {
-                Vec::from(&text_parts.unwrap()[1..])
-            } else {
-                text_parts.unwrap_or_default()
-            };
+        let file = root.join("file");
+        std::fs::write(&file, "").unwrap();
+        let error = check_writable(&file.join("cache")).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("is not writable — check permissions"));
 
-            let mut amount_str = amount.trim();
-            if amount_str.starts_with('$') {
-                amount_str = &amount_str[1..];
-            }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_render_description() {
+        assert_eq!(
+            "Groceries by alice",
+            render_description(DEFAULT_DESCRIPTION_TEMPLATE, "Groceries", "alice", None)
+        );
+        assert_eq!(
+            "milk and eggs",
+            render_description("{note}", "Groceries", "alice", Some("milk and eggs"))
+        );
+        assert_eq!(
+            "Groceries",
+            render_description("{note}", "Groceries", "alice", None)
+        );
+        assert_eq!(
+            "alice: Groceries",
+            render_description("{person}: {category} {note}", "Groceries", "alice", None)
+        );
+        assert_eq!(
+            DEFAULT_DESCRIPTION_TEMPLATE,
+            config("").description_template
+        );
+    }
 
-            let category = category.trim();
+    #[test]
+    fn test_person_attribution() {
+        let withdrawal = |attribution| {
+            Transaction::withdrawal(
+                "Groceries".to_string(),
+                10.0,
+                Local::now(),
+                1,
+                "General expense".to_string(),
+                "alice".to_string(),
+                attribution,
+                DEFAULT_DESCRIPTION_TEMPLATE,
+                None,
+                vec!["food".to_string()],
+            )
+        };
 
-            if category.is_empty() || amount_str.is_empty() {
-                return Err(anyhow!("{INVALID_ARGS} Usage: {ADD_USAGE}"));
-            }
+        let both = withdrawal(PersonAttribution::Both);
+        assert_eq!("Groceries by alice", both.description);
+        assert_eq!(vec!["food", "alice"], both.tags);
 
-            let Ok(amount) = f64::from_str(amount_str) else {
-                return Err(anyhow!("Invalid amount: {amount_str}"))
-            };
+        let description = withdrawal(PersonAttribution::Description);
+        assert_eq!("Groceries by alice", description.description);
+        assert_eq!(vec!["food"], description.tags);
 
-            Ok(Self {
-                category: category.to_string(),
-                amount,
-                note,
-                tags,
-            })
-        } else {
-            Err(anyhow!("{INVALID_ARGS} Usage: {ADD_USAGE}"))
-        }
+        let tags = withdrawal(PersonAttribution::Tags);
+        assert_eq!("Groceries", tags.description);
+        assert_eq!(vec!["food", "alice"], tags.tags);
+
+        let none = withdrawal(PersonAttribution::None);
+        assert_eq!("Groceries", none.description);
+        assert_eq!(vec!["food"], none.tags);
+
+        assert_eq!(PersonAttribution::Both, config("").person_attribution);
+        assert_eq!(
+            PersonAttribution::None,
+            config(r#"person_attribution = "none""#).person_attribution
+        );
     }
-}
 
-async fn send_message(content: String, room: &Joined) -> anyhow::Result<()> {
-    room.send(RoomMessageEventContent::text_plain(content), None)
-        .await?;
-    Ok(())
-}
+    #[test]
+    fn test_tag_with_person() {
+        let withdrawal = |config: Config| {
+            Transaction::withdrawal(
+                "Groceries".to_string(),
+                10.0,
+                Local::now(),
+                1,
+                "General expense".to_string(),
+                "alice".to_string(),
+                config.attribution(),
+                &config.description_template,
+                None,
+                vec!["food".to_string()],
+            )
+        };
 
-async fn send_reaction(
-    reaction: String,
-    event_id: OwnedEventId,
-    room: &Joined,
-) -> anyhow::Result<()> {
-    room.send(
-        ReactionEventContent::new(Relation::new(event_id, reaction)),
-        None,
-    )
-    .await?;
-    Ok(())
-}
+        let tagged = withdrawal(config("tag_with_person = true"));
+        assert_eq!("Groceries by alice", tagged.description);
+        assert_eq!(vec!["food", "alice"], tagged.tags);
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    env_logger::builder()
-        .filter_level(LevelFilter::Info)
-        .format_target(false)
-        .init();
+        let untagged = withdrawal(config("tag_with_person = false"));
+        assert_eq!("Groceries by alice", untagged.description);
+        assert_eq!(vec!["food"], untagged.tags);
 
-    if env::args().len() != 2 {
-        error!("Usage: {} <PATH_TO_CONFIG>", env::args().next().unwrap());
-        exit(1)
+        assert_eq!(PersonAttribution::Both, config("").attribution());
+        assert_eq!(
+            PersonAttribution::None,
+            config(r#"person_attribution = "none""#).attribution()
+        );
+
+        assert_eq!(
+            PersonAttribution::Tags,
+            config("person_attribution = \"none\"\ntag_with_person = true").attribution()
+        );
+        assert_eq!(
+            PersonAttribution::None,
+            config("person_attribution = \"tags\"\ntag_with_person = false").attribution()
+        );
+        assert_eq!(
+            PersonAttribution::Both,
+            config("person_attribution = \"both\"").attribution()
+        );
     }
 
-    let mut config_file = File::open(env::args().nth(1).unwrap())?;
-    let mut bytes = Vec::new();
-    config_file.read_to_end(&mut bytes)?;
+    #[test]
+    fn test_message_text() {
+        assert_eq!(
+            "!add Groceries 45",
+            message_text(&TextMessageEventContent::html(
+                "!add Groceries 45",
+                "!add <b>Groceries</b> 12"
+            ))
+        );
+        assert_eq!(
+            "!add Groceries 45 #food",
+            message_text(&TextMessageEventContent::html(
+                "",
+                "!add Groceries 45 <a href=\"https://matrix.to/#/%23food:example.org\">#food:example.org</a>"
+            ))
+        );
+        assert_eq!(
+            "!undo",
+            message_text(&TextMessageEventContent::plain(
+                "> <@alice:example.org> hi\n\n!undo"
+            ))
+            .trim_start()
+        );
+        assert_eq!("", message_text(&TextMessageEventContent::plain("")));
+    }
 
-    let config = toml::from_slice(&bytes)?;
+    #[test]
+    fn test_is_dm_room() {
+        assert!(is_dm_room(true, 2));
+        assert!(is_dm_room(true, 3));
+        assert!(!is_dm_room(false, 2));
+        assert!(!is_dm_room(false, 1));
+        assert!(!is_dm_room(false, 3));
+    }
 
-    MatrixFireflyBot::new(config).start().await?;
+    #[test]
+    fn test_is_maintenance() {
+        let body = r#"{"message":"Service Unavailable","exception":"Symfony\\Component\\HttpKernel\\Exception\\HttpException"}"#;
+        assert!(!is_maintenance(503, body));
+        assert!(is_maintenance(
+            503,
+            "<html><body>Firefly III is down for maintenance.</body></html>"
+        ));
+        assert!(!is_maintenance(
+            503,
+            "<html><body>503 Service Temporarily Unavailable</body></html>"
+        ));
+        assert!(!is_maintenance(500, body));
 
-    info!("Exiting");
+        let error = anyhow::Error::from(MaintenanceError);
+        assert_eq!(
+            MAINTENANCE_MESSAGE,
+            failure_message(&error, "Failed to add")
+        );
+        assert_eq!(
+            "Failed to add",
+            failure_message(&anyhow::anyhow!("boom"), "Failed to add")
+        );
+    }
 
-    Ok(())
-}
+    #[test]
+    fn test_again_repeats_last_command() {
+        let config = config("");
+        let alice = OwnedUserId::try_from("@alice:example.org").unwrap();
+        let mut history = CommandHistory::default();
+        history.record(&alice, "!top 3 7");
 
-#[cfg(test)]
-mod tests {
-    use crate::AddArgs;
+        let command = history.resolve(&alice, "!again").unwrap();
+        assert!(matches!(
+            Cmd::parse(&command, &config).unwrap(),
+            Cmd::Top(TopArgs { count: 3, days: 7 })
+        ));
+        assert!(matches!(
+            Cmd::parse("!history commands", &config).unwrap(),
+            Cmd::CommandHistory
+        ));
+        assert!(Cmd::parse("!history everything", &config).is_err());
+    }
 
     #[test]
-    fn test_parse_add() {
-        assert_add_arg(parse_add("Test: 1.23"), "Test", 1.23, None, vec![]);
-        assert_add_arg(
-            parse_add("multi word cat: $100"),
-            "multi word cat",
-            100.00,
-            None,
-            vec![],
+    fn test_api_url() {
+        assert_eq!(
+            "https://firefly.example.org/api/v1/transactions",
+            config("").api_url(FIREFLY_TRANSACTIONS_API)
         );
-        assert_add_arg(
-            parse_add("cat : 0.25 this is a note"),
-            "cat",
-            0.25,
-            Some("this is a note"),
-            vec![],
+        assert_eq!(
+            "https://firefly.example.org/api/v2/categories",
+            config(r#"firefly_api_version = "v2""#).api_url(FIREFLY_CATEGORIES_API)
         );
-        assert_add_arg(
-            parse_add("test: 1.25 #tag"),
-            "test",
-            1.25,
-            None,
-            vec!["tag"],
+    }
+
+    #[test]
+    fn test_parse_add_date() {
+        let args = parse_add("Groceries: 42.10 @2024-01-05 milk #food");
+        assert_eq!(
+            Some(NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()),
+            args.date.map(|date| date.date_naive())
         );
-        assert_add_arg(
-            parse_add("test: 1 this is a note #one #two #three"),
-            "test",
-            1.00,
-            Some("this is a note"),
-            vec!["one", "two", "three"],
+        assert_eq!(Some("milk".to_string()), args.note);
+        assert_eq!(vec!["food"], args.tags);
+
+        let today = Local::now().date_naive();
+        let args = parse_add("Groceries: 5 @Yesterday");
+        assert_eq!(
+            Some(today - ChronoDuration::days(1)),
+            args.date.map(|date| date.date_naive())
         );
-        assert_add_arg(
-            parse_add(
-                "   weird spacing   :   $1.01    this one has  extra   spacing    #one    #  two  ",
-            ),
-            "weird spacing",
-            1.01,
-            Some("this one has  extra   spacing"),
-            vec!["one", "two"],
+        assert_eq!(None, args.note);
+        assert_eq!(
+            Some(today),
+            parse_add("Groceries: 5 @today #food")
+                .date
+                .map(|date| date.date_naive())
         );
+
+        let args = parse_add("Gifts: 20 @alice's birthday");
+        assert_eq!(None, args.date);
+        assert_eq!(Some("@alice's birthday".to_string()), args.note);
+        assert_eq!(None, parse_add("Groceries: 5 milk @2024-01-05").date);
+
+        assert!(AddArgs::parse("Groceries: 5 @2024-02-30", NoteMode::Standard, false).is_err());
+        assert!(AddArgs::parse("Groceries: 5 @05/01/2024", NoteMode::Standard, false).is_err());
     }
 
     fn parse_add(args: &str) -> AddArgs {
-        AddArgs::parse(args).unwrap()
+        AddArgs::parse(args, NoteMode::Standard, false).unwrap()
     }
 
     fn assert_add_arg(
@@ -560,8 +8508,14 @@ mod tests {
             AddArgs {
                 category: category.to_string(),
                 amount,
+                date: None,
                 note: note.map(|note| note.to_string()),
                 tags: tags.into_iter().map(|tag| tag.to_string()).collect(),
+                currency: None,
+                payee: None,
+                splits: Vec::new(),
+                dry_run: false,
+                refund: false,
             },
             actual
         );
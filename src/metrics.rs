@@ -0,0 +1,199 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const METRICS_PATH: &str = "/metrics";
+/// The upper bounds, in seconds, of the Firefly request latency buckets
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Counts the commands the bot receives and the requests it makes to Firefly, and serves them in
+/// the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    /// The number of commands received, by the command's name without its prefix, eg: `add`
+    commands: Mutex<BTreeMap<String, u64>>,
+    firefly_successes: Mutex<u64>,
+    firefly_failures: Mutex<u64>,
+    firefly_latency: Mutex<Histogram>,
+}
+
+/// A Prometheus histogram of durations, in seconds
+#[derive(Default)]
+struct Histogram {
+    /// The number of observations in each of the `LATENCY_BUCKETS`, not including the smaller
+    /// buckets
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn record_command(&self, command: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(command.to_string())
+            .or_default() += 1;
+    }
+
+    /// Records a request to Firefly, which succeeded if Firefly responded with a 2xx status
+    pub fn record_firefly_request(&self, success: bool, elapsed: Duration) {
+        if success {
+            *self.firefly_successes.lock().unwrap() += 1;
+        } else {
+            *self.firefly_failures.lock().unwrap() += 1;
+        }
+        self.firefly_latency
+            .lock()
+            .unwrap()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP matrix_firefly_bot_commands_total Commands received, by command\n");
+        output.push_str("# TYPE matrix_firefly_bot_commands_total counter\n");
+        for (command, count) in self.commands.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "matrix_firefly_bot_commands_total{{command=\"{command}\"}} {count}"
+            );
+        }
+
+        output.push_str(
+            "# HELP matrix_firefly_bot_firefly_requests_total Requests to Firefly, by outcome\n",
+        );
+        output.push_str("# TYPE matrix_firefly_bot_firefly_requests_total counter\n");
+        for (outcome, count) in [
+            ("success", *self.firefly_successes.lock().unwrap()),
+            ("failure", *self.firefly_failures.lock().unwrap()),
+        ] {
+            let _ = writeln!(
+                output,
+                "matrix_firefly_bot_firefly_requests_total{{outcome=\"{outcome}\"}} {count}"
+            );
+        }
+
+        output.push_str(
+            "# HELP matrix_firefly_bot_firefly_request_duration_seconds Latency of requests to Firefly\n",
+        );
+        output.push_str("# TYPE matrix_firefly_bot_firefly_request_duration_seconds histogram\n");
+        let latency = self.firefly_latency.lock().unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(latency.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                output,
+                "matrix_firefly_bot_firefly_request_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            output,
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            latency.count
+        );
+        let _ = writeln!(
+            output,
+            "matrix_firefly_bot_firefly_request_duration_seconds_sum {}",
+            latency.sum
+        );
+        let _ = writeln!(
+            output,
+            "matrix_firefly_bot_firefly_request_duration_seconds_count {}",
+            latency.count
+        );
+
+        output
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Serves the metrics at `/metrics` on the port, on every interface, until the server fails
+pub async fn serve(metrics: Arc<Metrics>, port: u16) -> anyhow::Result<()> {
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_service = make_service_fn(move |_| {
+        let metrics = Arc::clone(&metrics);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = respond(&metrics, &request);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)?.serve(make_service);
+    info!("Serving metrics on {address}{METRICS_PATH}");
+    server.await?;
+    Ok(())
+}
+
+fn respond(metrics: &Metrics, request: &Request<Body>) -> Response<Body> {
+    let mut response = Response::default();
+    if request.method() != Method::GET {
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+    } else if request.uri().path() != METRICS_PATH {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+    } else {
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+        );
+        *response.body_mut() = Body::from(metrics.render());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::Metrics;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.record_command("add");
+        metrics.record_command("add");
+        metrics.record_command("balance");
+        metrics.record_firefly_request(true, Duration::from_millis(250));
+        metrics.record_firefly_request(true, Duration::from_millis(500));
+        metrics.record_firefly_request(false, Duration::from_secs(60));
+
+        let rendered = metrics.render();
+        for line in [
+            "matrix_firefly_bot_commands_total{command=\"add\"} 2",
+            "matrix_firefly_bot_commands_total{command=\"balance\"} 1",
+            "matrix_firefly_bot_firefly_requests_total{outcome=\"success\"} 2",
+            "matrix_firefly_bot_firefly_requests_total{outcome=\"failure\"} 1",
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{le=\"0.1\"} 0",
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{le=\"0.25\"} 1",
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{le=\"0.5\"} 2",
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{le=\"30\"} 2",
+            "matrix_firefly_bot_firefly_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "matrix_firefly_bot_firefly_request_duration_seconds_sum 60.75",
+            "matrix_firefly_bot_firefly_request_duration_seconds_count 3",
+        ] {
+            assert!(
+                rendered.lines().any(|rendered| rendered == line),
+                "missing {line} in {rendered}"
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+const MIRROR_TREE: &str = "mirror";
+const MIRROR_KEY: &str = "data";
+
+/// An asset account as known to the mirror
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MirroredAccount {
+    pub id: i64,
+    pub name: String,
+}
+
+/// The Firefly data that is mirrored locally
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MirrorData {
+    pub categories: Vec<String>,
+    pub accounts: Vec<MirroredAccount>,
+    pub tags: Vec<String>,
+    /// When the data was last fetched from Firefly, or `None` if it never has been
+    pub synced_at: Option<DateTime<Local>>,
+}
+
+/// A local copy of Firefly's categories, accounts, and tags that can be persisted across restarts
+/// so that commands can be answered without waiting on Firefly
+pub struct Mirror {
+    tree: sled::Tree,
+    persist: bool,
+    data: RwLock<MirrorData>,
+}
+
+impl Mirror {
+    /// Opens the mirror in `db`. When `persist` is set, the previously persisted data, if any, is
+    /// loaded, and updates are persisted. Otherwise, the mirror starts empty and is only kept in
+    /// memory.
+    pub fn open(db: &sled::Db, persist: bool) -> anyhow::Result<Self> {
+        let tree = db.open_tree(MIRROR_TREE)?;
+        let data = if persist {
+            load(&tree)
+        } else {
+            MirrorData::default()
+        };
+        Ok(Self {
+            tree,
+            persist,
+            data: RwLock::new(data),
+        })
+    }
+
+    pub fn data(&self) -> MirrorData {
+        self.data.read().unwrap().clone()
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.data.read().unwrap().synced_at.is_some()
+    }
+
+    /// Replaces the mirrored data, persisting it if the mirror is persisted
+    pub fn update(&self, data: MirrorData) -> anyhow::Result<()> {
+        if self.persist {
+            self.tree.insert(MIRROR_KEY, serde_json::to_vec(&data)?)?;
+            self.tree.flush()?;
+        }
+        *self.data.write().unwrap() = data;
+        Ok(())
+    }
+}
+
+/// Loads the persisted data. Missing or unreadable data is treated as empty.
+fn load(tree: &sled::Tree) -> MirrorData {
+    match tree.get(MIRROR_KEY) {
+        Ok(Some(bytes)) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("Ignoring corrupt mirror data: {e}");
+            MirrorData::default()
+        }),
+        Ok(None) => MirrorData::default(),
+        Err(e) => {
+            warn!("Failed to load mirror data: {e}");
+            MirrorData::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirror::{Mirror, MirrorData, MirroredAccount, MIRROR_KEY, MIRROR_TREE};
+    use chrono::Local;
+
+    #[test]
+    fn test_mirror_round_trip() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let mirror = Mirror::open(&db, true).unwrap();
+        assert_eq!(MirrorData::default(), mirror.data());
+        assert!(!mirror.is_synced());
+
+        let data = MirrorData {
+            categories: vec!["Groceries".to_string(), "Bills".to_string()],
+            accounts: vec![MirroredAccount {
+                id: 1,
+                name: "Checking".to_string(),
+            }],
+            tags: vec!["food".to_string()],
+            synced_at: Some(Local::now()),
+        };
+        mirror.update(data.clone()).unwrap();
+        assert_eq!(data, mirror.data());
+
+        let reopened = Mirror::open(&db, true).unwrap();
+        assert_eq!(data, reopened.data());
+        assert!(reopened.is_synced());
+    }
+
+    #[test]
+    fn test_corrupt_mirror_is_empty() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.open_tree(MIRROR_TREE)
+            .unwrap()
+            .insert(MIRROR_KEY, "not json")
+            .unwrap();
+
+        let mirror = Mirror::open(&db, true).unwrap();
+        assert_eq!(MirrorData::default(), mirror.data());
+    }
+
+    #[test]
+    fn test_unpersisted_mirror() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let data = MirrorData {
+            categories: vec!["Groceries".to_string()],
+            synced_at: Some(Local::now()),
+            ..MirrorData::default()
+        };
+
+        let mirror = Mirror::open(&db, false).unwrap();
+        mirror.update(data.clone()).unwrap();
+        assert_eq!(data, mirror.data());
+
+        let reopened = Mirror::open(&db, true).unwrap();
+        assert_eq!(MirrorData::default(), reopened.data());
+        assert!(!reopened.is_synced());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{format_amount, parse_days, TransactionSplit, INVALID_ARGS, REPORT_MAX_DAYS};
+use anyhow::anyhow;
+
+pub const NET_USAGE: &str = "!net [Days]";
+
+const NET_DEFAULT_DAYS: i64 = 30;
+
+/// A request for a person's income minus expenses over the last `days` days
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetArgs {
+    pub days: i64,
+}
+
+/// A person's income and expenses over a window
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetFlow {
+    pub income: f64,
+    pub expenses: f64,
+}
+
+impl NetArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let mut parts = args.split_whitespace();
+
+        let days = parse_days(parts.next(), NET_DEFAULT_DAYS, REPORT_MAX_DAYS)?;
+
+        if parts.next().is_some() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {NET_USAGE}"));
+        }
+
+        Ok(Self { days })
+    }
+}
+
+impl NetFlow {
+    pub fn net(&self) -> f64 {
+        self.income - self.expenses
+    }
+}
+
+/// Totals the deposits and withdrawals that are tagged with the person. Other transaction types,
+/// such as transfers, don't change the person's net.
+pub fn net_flow(transactions: &[TransactionSplit], person: &str) -> NetFlow {
+    let mut flow = NetFlow {
+        income: 0.0,
+        expenses: 0.0,
+    };
+
+    for transaction in transactions
+        .iter()
+        .filter(|transaction| transaction.tags.iter().any(|tag| tag == person))
+    {
+        match transaction.transaction_type.as_str() {
+            "deposit" => flow.income += transaction.amount,
+            "withdrawal" => flow.expenses += transaction.amount,
+            _ => {}
+        }
+    }
+
+    flow
+}
+
+pub fn format_net(flow: &NetFlow, days: i64) -> String {
+    let net = flow.net();
+    // Rounded to the cent so that a net of a fraction of a cent reads as even
+    let cents = (net * 100.0).round() as i64;
+
+    let verdict = match cents {
+        0 => "You earned as much as you spent".to_string(),
+        cents if cents > 0 => format!(
+            "You earned {} more than you spent",
+            format_amount(net, None)
+        ),
+        _ => format!(
+            "You spent {} more than you earned",
+            format_amount(-net, None)
+        ),
+    };
+
+    format!(
+        "Net over the last {days} days: {}{}. Income {}, expenses {}. {verdict}",
+        if cents > 0 { "+" } else { "" },
+        format_amount(if cents == 0 { 0.0 } else { net }, None),
+        format_amount(flow.income, None),
+        format_amount(flow.expenses, None)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{format_net, net_flow, NetArgs, NetFlow};
+    use crate::{TransactionSplit, REPORT_MAX_DAYS};
+
+    fn transaction(transaction_type: &str, amount: f64, tags: &[&str]) -> TransactionSplit {
+        TransactionSplit {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..TransactionSplit::fixture(transaction_type, amount)
+        }
+    }
+
+    #[test]
+    fn test_parse_net() {
+        assert_eq!(NetArgs { days: 30 }, NetArgs::parse("").unwrap());
+        assert_eq!(NetArgs { days: 7 }, NetArgs::parse(" 7 ").unwrap());
+        assert_eq!(
+            NetArgs {
+                days: REPORT_MAX_DAYS
+            },
+            NetArgs::parse("100000000").unwrap()
+        );
+        assert!(NetArgs::parse("0").is_err());
+        assert!(NetArgs::parse("7 days").is_err());
+    }
+
+    #[test]
+    fn test_net_flow() {
+        let transactions = vec![
+            transaction("deposit", 3000.0, &["alice"]),
+            transaction("withdrawal", 120.5, &["alice", "food"]),
+            transaction("withdrawal", 80.0, &["bob"]),
+            transaction("transfer", 500.0, &["alice"]),
+            transaction("withdrawal", 79.5, &["alice"]),
+        ];
+
+        let flow = net_flow(&transactions, "alice");
+        assert_eq!(3000.0, flow.income);
+        assert_eq!(200.0, flow.expenses);
+        assert_eq!(2800.0, flow.net());
+
+        let flow = net_flow(&transactions, "carol");
+        assert_eq!(0.0, flow.income);
+        assert_eq!(0.0, flow.expenses);
+    }
+
+    #[test]
+    fn test_format_net() {
+        assert_eq!(
+            "Net over the last 30 days: +2800.00. Income 3000.00, expenses 200.00. You earned 2800.00 more than you spent",
+            format_net(&NetFlow { income: 3000.0, expenses: 200.0 }, 30)
+        );
+        assert_eq!(
+            "Net over the last 7 days: -45.10. Income 0.00, expenses 45.10. You spent 45.10 more than you earned",
+            format_net(&NetFlow { income: 0.0, expenses: 45.1 }, 7)
+        );
+        assert_eq!(
+            "Net over the last 7 days: 0.00. Income 0.00, expenses 0.00. You earned as much as you spent",
+            format_net(&NetFlow { income: 0.0, expenses: 0.0 }, 7)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How a default note is chosen from `default_notes`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSelection {
+    /// Always the first note
+    #[default]
+    Fixed,
+    /// A note chosen at random
+    Random,
+    /// Each note in turn
+    RoundRobin,
+}
+
+/// Picks the note to add to expenses that are added without one
+pub struct DefaultNotes {
+    notes: Vec<String>,
+    selection: NoteSelection,
+    next: AtomicUsize,
+}
+
+impl DefaultNotes {
+    pub fn new(notes: Vec<String>, selection: NoteSelection) -> Self {
+        Self {
+            notes,
+            selection,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the next default note, or `None` if there are no default notes
+    pub fn pick(&self) -> Option<String> {
+        if self.notes.is_empty() {
+            return None;
+        }
+
+        let index = match self.selection {
+            NoteSelection::Fixed => 0,
+            NoteSelection::Random => {
+                // Good enough to vary the note without pulling in a random number generator
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.subsec_nanos() as usize)
+                    .unwrap_or_default()
+                    % self.notes.len()
+            }
+            NoteSelection::RoundRobin => {
+                self.next.fetch_add(1, Ordering::Relaxed) % self.notes.len()
+            }
+        };
+
+        Some(self.notes[index].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notes::{DefaultNotes, NoteSelection};
+
+    fn notes() -> Vec<String> {
+        vec!["one".to_string(), "two".to_string(), "three".to_string()]
+    }
+
+    #[test]
+    fn test_fixed_note() {
+        let notes = DefaultNotes::new(notes(), NoteSelection::Fixed);
+        assert_eq!(Some("one".to_string()), notes.pick());
+        assert_eq!(Some("one".to_string()), notes.pick());
+    }
+
+    #[test]
+    fn test_round_robin_notes() {
+        let notes = DefaultNotes::new(notes(), NoteSelection::RoundRobin);
+        let picked: Vec<_> = (0..4).filter_map(|_| notes.pick()).collect();
+        assert_eq!(vec!["one", "two", "three", "one"], picked);
+    }
+
+    #[test]
+    fn test_random_note() {
+        let default_notes = DefaultNotes::new(notes(), NoteSelection::Random);
+        for _ in 0..10 {
+            assert!(notes().contains(&default_notes.pick().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_no_default_notes() {
+        for selection in [
+            NoteSelection::Fixed,
+            NoteSelection::Random,
+            NoteSelection::RoundRobin,
+        ] {
+            assert_eq!(None, DefaultNotes::new(Vec::new(), selection).pick());
+        }
+    }
+}
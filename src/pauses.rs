@@ -0,0 +1,192 @@
+use crate::reminders::parse_delay;
+use crate::INVALID_ARGS;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local};
+use log::warn;
+
+const PAUSES_TREE: &str = "pauses";
+
+pub const PAUSE_USER_USAGE: &str = "!pauseuser <User> <Duration> | !pauseuser list";
+pub const UNPAUSE_USER_USAGE: &str = "!unpauseuser <User>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PauseUserArgs {
+    /// Ignore the user's commands for the duration
+    Pause { user_id: String, duration: Duration },
+    /// List the users that are currently paused
+    List,
+}
+
+impl PauseUserArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let args = args.trim();
+        if args == "list" {
+            return Ok(PauseUserArgs::List);
+        }
+
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [user_id, duration] if user_id.starts_with('@') => Ok(PauseUserArgs::Pause {
+                user_id: user_id.to_string(),
+                duration: parse_delay(duration)?,
+            }),
+            _ => Err(anyhow!("{INVALID_ARGS} Usage: {PAUSE_USER_USAGE}")),
+        }
+    }
+}
+
+/// Parses the args to `!unpauseuser`, which are the id of the user to unpause
+pub fn parse_unpause_user(args: &str) -> anyhow::Result<String> {
+    match args.split_whitespace().collect::<Vec<_>>()[..] {
+        [user_id] if user_id.starts_with('@') => Ok(user_id.to_string()),
+        _ => Err(anyhow!("{INVALID_ARGS} Usage: {UNPAUSE_USER_USAGE}")),
+    }
+}
+
+/// Persists the users whose commands are ignored until a given time
+pub struct PauseStore {
+    tree: sled::Tree,
+}
+
+impl PauseStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(PAUSES_TREE)?,
+        })
+    }
+
+    /// Ignores the user's commands until the given time, replacing any existing pause
+    pub fn pause(&self, user_id: &str, until: DateTime<Local>) -> anyhow::Result<()> {
+        self.tree
+            .insert(user_id.as_bytes(), serde_json::to_vec(&until)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Lifts the user's pause. Returns false if they were not paused.
+    pub fn unpause(&self, user_id: &str) -> anyhow::Result<bool> {
+        let removed = self.tree.remove(user_id.as_bytes())?.is_some();
+        self.tree.flush()?;
+        Ok(removed)
+    }
+
+    /// Returns true if the user is paused at the given time. Expired pauses are removed.
+    pub fn is_paused(&self, user_id: &str, now: DateTime<Local>) -> bool {
+        match self.tree.get(user_id.as_bytes()) {
+            Ok(Some(value)) => match serde_json::from_slice::<DateTime<Local>>(&value) {
+                Ok(until) if until > now => true,
+                Ok(_) => {
+                    if let Err(e) = self.tree.remove(user_id.as_bytes()) {
+                        warn!("Failed to remove expired pause for {user_id}: {e}");
+                    }
+                    false
+                }
+                Err(e) => {
+                    warn!("Failed to parse pause for {user_id}: {e}");
+                    false
+                }
+            },
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Failed to read pause for {user_id}: {e}");
+                false
+            }
+        }
+    }
+
+    /// Returns the users that are paused at the given time, and when their pauses end, ordered by
+    /// the end of the pause
+    pub fn list(&self, now: DateTime<Local>) -> Vec<(String, DateTime<Local>)> {
+        let mut pauses: Vec<_> = self
+            .tree
+            .iter()
+            .filter_map(|entry| match entry {
+                Ok((key, value)) => Some((
+                    String::from_utf8_lossy(&key).to_string(),
+                    serde_json::from_slice::<DateTime<Local>>(&value).ok()?,
+                )),
+                Err(e) => {
+                    warn!("Failed to read pause: {e}");
+                    None
+                }
+            })
+            .filter(|(_, until)| *until > now)
+            .collect();
+        pauses.sort_by_key(|(_, until)| *until);
+        pauses
+    }
+}
+
+pub fn format_pauses(pauses: &[(String, DateTime<Local>)]) -> String {
+    if pauses.is_empty() {
+        return "No users are paused".to_string();
+    }
+
+    let mut output = String::from("Paused users:");
+    for (user_id, until) in pauses {
+        output.push_str(&format!(
+            "\n - {user_id} until {}",
+            until.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pauses::{parse_unpause_user, PauseStore, PauseUserArgs};
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_parse_pause_user_args() {
+        assert_eq!(
+            PauseUserArgs::Pause {
+                user_id: "@alice:example.org".to_string(),
+                duration: Duration::hours(1)
+            },
+            PauseUserArgs::parse("@alice:example.org 1h").unwrap()
+        );
+        assert_eq!(PauseUserArgs::List, PauseUserArgs::parse(" list ").unwrap());
+        assert!(PauseUserArgs::parse("@alice:example.org").is_err());
+        assert!(PauseUserArgs::parse("alice 1h").is_err());
+        assert!(PauseUserArgs::parse("@alice:example.org 1y").is_err());
+
+        assert_eq!(
+            "@alice:example.org",
+            parse_unpause_user("@alice:example.org").unwrap()
+        );
+        assert!(parse_unpause_user("").is_err());
+    }
+
+    #[test]
+    fn test_pause_expiry() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = PauseStore::open(&db).unwrap();
+        let now = Local::now();
+
+        store
+            .pause("@alice:example.org", now + Duration::hours(1))
+            .unwrap();
+        assert!(store.is_paused("@alice:example.org", now));
+        assert!(!store.is_paused("@bob:example.org", now));
+        assert_eq!(1, store.list(now).len());
+
+        let later = now + Duration::hours(2);
+        assert!(store.list(later).is_empty());
+        assert!(!store.is_paused("@alice:example.org", later));
+        assert!(!store.is_paused("@alice:example.org", now));
+    }
+
+    #[test]
+    fn test_unpause() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = PauseStore::open(&db).unwrap();
+        let now = Local::now();
+
+        store
+            .pause("@alice:example.org", now + Duration::hours(1))
+            .unwrap();
+        assert!(store.unpause("@alice:example.org").unwrap());
+        assert!(!store.is_paused("@alice:example.org", now));
+        assert!(!store.unpause("@alice:example.org").unwrap());
+    }
+}
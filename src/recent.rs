@@ -0,0 +1,106 @@
+use crate::{format_amount, TransactionSplit, INVALID_ARGS};
+use anyhow::anyhow;
+use std::str::FromStr;
+
+pub const RECENT_USAGE: &str = "!recent [Count]";
+
+const RECENT_DEFAULT_COUNT: usize = 5;
+const RECENT_MAX_COUNT: usize = 20;
+
+/// A request for the last `count` transactions of the source account
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentArgs {
+    pub count: usize,
+}
+
+impl RecentArgs {
+    /// Parses the optional count, which is capped at 20
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let mut parts = args.split_whitespace();
+
+        let count = match parts.next() {
+            Some(count) => match usize::from_str(count) {
+                Ok(count) if count > 0 => count.min(RECENT_MAX_COUNT),
+                _ => return Err(anyhow!("Invalid count: {count}")),
+            },
+            None => RECENT_DEFAULT_COUNT,
+        };
+
+        if parts.next().is_some() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {RECENT_USAGE}"));
+        }
+
+        Ok(Self { count })
+    }
+}
+
+/// Lists the transactions, newest first, with their date, category, amount, and note
+pub fn format_recent(transactions: &[TransactionSplit]) -> String {
+    if transactions.is_empty() {
+        return "No recent transactions".to_string();
+    }
+
+    let mut response = format!("Last {} transactions:", transactions.len());
+    for transaction in transactions {
+        response.push_str(&format!(
+            "\n - {} {}: {}",
+            transaction.date.date_naive(),
+            transaction
+                .category_name
+                .as_deref()
+                .unwrap_or(&transaction.description),
+            format_amount(transaction.amount, transaction.currency_symbol.as_deref())
+        ));
+        if let Some(note) = transaction.notes.as_deref().filter(|note| !note.is_empty()) {
+            response.push_str(&format!(" {note}"));
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recent::{format_recent, RecentArgs};
+    use crate::TransactionSplit;
+    use chrono::{DateTime, Local};
+
+    fn transaction(
+        date: &str,
+        category: Option<&str>,
+        amount: f64,
+        notes: Option<&str>,
+    ) -> TransactionSplit {
+        TransactionSplit {
+            date: DateTime::parse_from_rfc3339(date)
+                .unwrap()
+                .with_timezone(&Local),
+            description: "Groceries by alice".to_string(),
+            category_name: category.map(str::to_string),
+            notes: notes.map(str::to_string),
+            currency_symbol: Some("$".to_string()),
+            ..TransactionSplit::fixture("withdrawal", amount)
+        }
+    }
+
+    #[test]
+    fn test_parse_recent() {
+        assert_eq!(RecentArgs { count: 5 }, RecentArgs::parse("").unwrap());
+        assert_eq!(RecentArgs { count: 12 }, RecentArgs::parse(" 12 ").unwrap());
+        assert_eq!(RecentArgs { count: 20 }, RecentArgs::parse("50").unwrap());
+        assert!(RecentArgs::parse("0").is_err());
+        assert!(RecentArgs::parse("five").is_err());
+        assert!(RecentArgs::parse("5 days").is_err());
+    }
+
+    #[test]
+    fn test_format_recent() {
+        assert_eq!("No recent transactions", format_recent(&[]));
+        assert_eq!(
+            "Last 2 transactions:\n - 2024-05-02 Groceries: $42.10 milk\n - 2024-05-01 Groceries by alice: $5.00",
+            format_recent(&[
+                transaction("2024-05-02T12:00:00+00:00", Some("Groceries"), 42.1, Some("milk")),
+                transaction("2024-05-01T12:00:00+00:00", None, 5.0, None),
+            ])
+        );
+    }
+}
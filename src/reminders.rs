@@ -0,0 +1,277 @@
+use crate::INVALID_ARGS;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const REMINDERS_TREE: &str = "reminders";
+
+/// The longest delay that a reminder or a pause can have
+pub const MAX_DELAY_DAYS: i64 = 365;
+
+pub const REMIND_USAGE: &str = "!remind <Delay> <Text> | !remind cancel <Id>";
+
+/// A message to send to a user once it's due
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub id: u64,
+    pub user_id: String,
+    pub room_id: String,
+    pub due: DateTime<Local>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemindArgs {
+    /// Remind the user of the text after the delay
+    Set { delay: Duration, text: String },
+    /// Cancel the user's reminder with the id
+    Cancel(u64),
+}
+
+/// Persists pending reminders in the bot's store so that they survive restarts
+pub struct ReminderStore {
+    db: sled::Db,
+    tree: sled::Tree,
+}
+
+impl RemindArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let Some((first, rest)) = args.trim().split_once(' ') else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {REMIND_USAGE}"));
+        };
+        let rest = rest.trim();
+
+        if first == "cancel" {
+            return match u64::from_str(rest) {
+                Ok(id) => Ok(RemindArgs::Cancel(id)),
+                Err(_) => Err(anyhow!("Invalid reminder id: {rest}")),
+            };
+        }
+
+        let delay = parse_delay(first)?;
+        if rest.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {REMIND_USAGE}"));
+        }
+
+        Ok(RemindArgs::Set {
+            delay,
+            text: rest.to_string(),
+        })
+    }
+}
+
+/// Parses a delay made of a positive number and a unit, eg: 30m, 2h, or 1d. Delays longer than
+/// `MAX_DELAY_DAYS` are rejected.
+pub fn parse_delay(delay: &str) -> anyhow::Result<Duration> {
+    let invalid = || {
+        anyhow!(
+            "Invalid delay: {delay}. Use minutes, hours, or days up to {MAX_DELAY_DAYS}d, eg: 30m, 2h, 1d"
+        )
+    };
+
+    let unit_start = delay
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount = i64::from_str(&delay[..unit_start]).map_err(|_| invalid())?;
+
+    if amount <= 0 {
+        return Err(invalid());
+    }
+
+    let minutes_per_unit = match &delay[unit_start..] {
+        "m" => 1,
+        "h" => 60,
+        "d" => 24 * 60,
+        _ => return Err(invalid()),
+    };
+
+    match amount.checked_mul(minutes_per_unit) {
+        Some(minutes) if minutes <= MAX_DELAY_DAYS * 24 * 60 => Ok(Duration::minutes(minutes)),
+        _ => Err(invalid()),
+    }
+}
+
+impl ReminderStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            db: db.clone(),
+            tree: db.open_tree(REMINDERS_TREE)?,
+        })
+    }
+
+    pub fn add(
+        &self,
+        user_id: &str,
+        room_id: &str,
+        due: DateTime<Local>,
+        text: String,
+    ) -> anyhow::Result<Reminder> {
+        let reminder = Reminder {
+            id: self.db.generate_id()?,
+            user_id: user_id.to_string(),
+            room_id: room_id.to_string(),
+            due,
+            text,
+        };
+        self.tree
+            .insert(reminder.id.to_be_bytes(), serde_json::to_vec(&reminder)?)?;
+        self.tree.flush()?;
+        Ok(reminder)
+    }
+
+    /// Removes the reminder if it belongs to the user, returning true if it was removed
+    pub fn cancel(&self, user_id: &str, id: u64) -> anyhow::Result<bool> {
+        match self.list().into_iter().find(|reminder| reminder.id == id) {
+            Some(reminder) if reminder.user_id == user_id => {
+                self.remove(id)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub fn remove(&self, id: u64) -> anyhow::Result<()> {
+        self.tree.remove(id.to_be_bytes())?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Returns the user's pending reminders, soonest first
+    pub fn list_for(&self, user_id: &str) -> Vec<Reminder> {
+        let mut reminders: Vec<Reminder> = self
+            .list()
+            .into_iter()
+            .filter(|reminder| reminder.user_id == user_id)
+            .collect();
+        reminders.sort_by_key(|reminder| reminder.due);
+        reminders
+    }
+
+    /// Returns the reminders that are due at `now`
+    pub fn due(&self, now: DateTime<Local>) -> Vec<Reminder> {
+        self.list()
+            .into_iter()
+            .filter(|reminder| reminder.due <= now)
+            .collect()
+    }
+
+    /// Returns every reminder, skipping any that cannot be read
+    fn list(&self) -> Vec<Reminder> {
+        self.tree
+            .iter()
+            .values()
+            .filter_map(|value| {
+                value
+                    .map_err(anyhow::Error::from)
+                    .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+                    .map_err(|e| warn!("Skipping unreadable reminder: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+pub fn format_reminders(reminders: &[Reminder]) -> String {
+    if reminders.is_empty() {
+        return "You have no reminders".to_string();
+    }
+
+    let mut output = String::from("Reminders:");
+    for reminder in reminders {
+        output.push_str(&format!(
+            "\n - [{}] {}: {}",
+            reminder.id,
+            reminder.due.format("%Y-%m-%d %H:%M"),
+            reminder.text
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reminders::{parse_delay, RemindArgs, ReminderStore};
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(Duration::minutes(30), parse_delay("30m").unwrap());
+        assert_eq!(Duration::hours(2), parse_delay("2h").unwrap());
+        assert_eq!(Duration::days(1), parse_delay("1d").unwrap());
+        assert!(parse_delay("0m").is_err());
+        assert!(parse_delay("2").is_err());
+        assert!(parse_delay("h").is_err());
+        assert!(parse_delay("2w").is_err());
+        assert!(parse_delay("-2h").is_err());
+        assert_eq!(Duration::days(365), parse_delay("365d").unwrap());
+        assert!(parse_delay("366d").is_err());
+        assert!(parse_delay("200000000d").is_err());
+        assert!(parse_delay("9999999999999999m").is_err());
+        assert!(parse_delay("99999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn test_parse_remind() {
+        assert_eq!(
+            RemindArgs::Set {
+                delay: Duration::hours(2),
+                text: "buy milk".to_string()
+            },
+            RemindArgs::parse("2h buy milk").unwrap()
+        );
+        assert_eq!(
+            RemindArgs::Cancel(3),
+            RemindArgs::parse("cancel 3").unwrap()
+        );
+        assert!(RemindArgs::parse("2h").is_err());
+        assert!(RemindArgs::parse("cancel x").is_err());
+    }
+
+    #[test]
+    fn test_reminder_store_round_trip() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = Local::now();
+
+        let store = ReminderStore::open(&db).unwrap();
+        let milk = store
+            .add(
+                "@alice:example.org",
+                "!room:example.org",
+                now + Duration::hours(2),
+                "buy milk".to_string(),
+            )
+            .unwrap();
+        let bread = store
+            .add(
+                "@alice:example.org",
+                "!room:example.org",
+                now + Duration::minutes(30),
+                "buy bread".to_string(),
+            )
+            .unwrap();
+        store
+            .add(
+                "@bob:example.org",
+                "!room:example.org",
+                now + Duration::days(1),
+                "pay rent".to_string(),
+            )
+            .unwrap();
+
+        let reopened = ReminderStore::open(&db).unwrap();
+        assert_eq!(
+            vec![bread.clone(), milk.clone()],
+            reopened.list_for("@alice:example.org")
+        );
+        assert!(reopened.due(now).is_empty());
+        assert_eq!(vec![bread.clone()], reopened.due(now + Duration::hours(1)));
+
+        assert!(!reopened.cancel("@bob:example.org", milk.id).unwrap());
+        assert!(reopened.cancel("@alice:example.org", milk.id).unwrap());
+        reopened.remove(bread.id).unwrap();
+        assert!(reopened.list_for("@alice:example.org").is_empty());
+        assert_eq!(1, reopened.list_for("@bob:example.org").len());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::INVALID_ARGS;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+
+pub const REPORT_LINK_USAGE: &str = "!report-link <category|tag|account> <Name>";
+
+/// The kind of Firefly page to link to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Category,
+    Tag,
+    Account,
+}
+
+/// A request for a link to the Firefly page for a category, tag, or account
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportLinkArgs {
+    pub kind: LinkKind,
+    pub name: String,
+}
+
+impl LinkKind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind.to_lowercase().as_str() {
+            "category" => Some(LinkKind::Category),
+            "tag" => Some(LinkKind::Tag),
+            "account" => Some(LinkKind::Account),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkKind::Category => "category",
+            LinkKind::Tag => "tag",
+            LinkKind::Account => "account",
+        }
+    }
+
+    /// The first segment of the kind's pages in Firefly's web interface
+    fn path(&self) -> &'static str {
+        match self {
+            LinkKind::Category => "categories",
+            LinkKind::Tag => "tags",
+            LinkKind::Account => "accounts",
+        }
+    }
+}
+
+impl ReportLinkArgs {
+    /// Parses the kind of link followed by the name, which may contain spaces
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let Some((kind, name)) = args.trim().split_once(' ') else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {REPORT_LINK_USAGE}"));
+        };
+
+        let Some(kind) = LinkKind::parse(kind) else {
+            return Err(anyhow!(
+                "Invalid link type: {kind}. Usage: {REPORT_LINK_USAGE}"
+            ));
+        };
+
+        Ok(Self {
+            kind,
+            name: name.trim().to_string(),
+        })
+    }
+}
+
+/// Builds the URL to the Firefly page for the category, tag, or account with `id`, filtered to
+/// the transactions between `start` and `end`, inclusive
+pub fn report_url(
+    firefly_url: &str,
+    kind: LinkKind,
+    id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> String {
+    format!(
+        "{}/{}/show/{id}/{start}/{end}",
+        firefly_url.trim_end_matches('/'),
+        kind.path()
+    )
+}
+
+/// Builds the URL to the Firefly page of the transaction group with `id`
+pub fn transaction_url(firefly_url: &str, id: &str) -> String {
+    format!(
+        "{}/transactions/show/{id}",
+        firefly_url.trim_end_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reportlink::{report_url, transaction_url, LinkKind, ReportLinkArgs};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_report_link() {
+        assert_eq!(
+            ReportLinkArgs {
+                kind: LinkKind::Category,
+                name: "Eating out".to_string(),
+            },
+            ReportLinkArgs::parse(" Category  Eating out ").unwrap()
+        );
+        assert_eq!(
+            LinkKind::Tag,
+            ReportLinkArgs::parse("tag food").unwrap().kind
+        );
+        assert!(ReportLinkArgs::parse("budget Food").is_err());
+        assert!(ReportLinkArgs::parse("category").is_err());
+    }
+
+    #[test]
+    fn test_report_url() {
+        let start = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+
+        assert_eq!(
+            "https://firefly.example.org/categories/show/4/2024-03-01/2024-03-31",
+            report_url(
+                "https://firefly.example.org/",
+                LinkKind::Category,
+                "4",
+                start,
+                end
+            )
+        );
+        assert_eq!(
+            "https://firefly.example.org/tags/show/7/2024-03-01/2024-03-31",
+            report_url(
+                "https://firefly.example.org",
+                LinkKind::Tag,
+                "7",
+                start,
+                end
+            )
+        );
+        assert_eq!(
+            "https://firefly.example.org/accounts/show/1/2024-03-01/2024-03-31",
+            report_url(
+                "https://firefly.example.org",
+                LinkKind::Account,
+                "1",
+                start,
+                end
+            )
+        );
+    }
+
+    #[test]
+    fn test_transaction_url() {
+        assert_eq!(
+            "https://firefly.example.org/transactions/show/42",
+            transaction_url("https://firefly.example.org/", "42")
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use log::warn;
+use std::future::Future;
+use std::time::Duration;
+
+/// Runs the request, retrying it up to `max_retries` times while it fails with an error that
+/// `is_transient` accepts. The delay before each retry starts at `base_delay` and doubles after
+/// every retry.
+pub async fn with_retries<T, F, Fut>(
+    description: &str,
+    max_retries: u32,
+    base_delay: Duration,
+    is_transient: fn(&anyhow::Error) -> bool,
+    mut request: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Err(e) if retries < max_retries && is_transient(&e) => {
+                let delay = base_delay * 2u32.saturating_pow(retries);
+                retries += 1;
+                warn!(
+                    "Failed to {description}, retry {retries} of {max_retries} in {delay:?}: {e}"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retry::with_retries;
+    use anyhow::anyhow;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn is_transient(error: &anyhow::Error) -> bool {
+        error.to_string() == "transient"
+    }
+
+    #[tokio::test]
+    async fn test_with_retries() {
+        let attempts = Cell::new(0);
+        let result = with_retries("test", 3, Duration::ZERO, is_transient, || async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(anyhow!("transient"))
+            } else {
+                Ok(attempts.get())
+            }
+        })
+        .await;
+        assert_eq!(3, result.unwrap());
+
+        let attempts = Cell::new(0);
+        let result: anyhow::Result<()> =
+            with_retries("test", 3, Duration::ZERO, is_transient, || async {
+                attempts.set(attempts.get() + 1);
+                Err(anyhow!("transient"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(4, attempts.get());
+
+        let attempts = Cell::new(0);
+        let result: anyhow::Result<()> =
+            with_retries("test", 3, Duration::ZERO, is_transient, || async {
+                attempts.set(attempts.get() + 1);
+                Err(anyhow!("permanent"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(1, attempts.get());
+    }
+}
@@ -0,0 +1,48 @@
+const SETTINGS_TREE: &str = "settings";
+const CYCLE_START_DAY_KEY: &str = "budget_cycle_start_day";
+
+/// Persists settings that are changed with commands, which take precedence over the config
+pub struct SettingsStore {
+    tree: sled::Tree,
+}
+
+impl SettingsStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(SETTINGS_TREE)?,
+        })
+    }
+
+    pub fn cycle_start_day(&self) -> anyhow::Result<Option<u32>> {
+        Ok(match self.tree.get(CYCLE_START_DAY_KEY)? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    pub fn set_cycle_start_day(&self, day: u32) -> anyhow::Result<()> {
+        self.tree
+            .insert(CYCLE_START_DAY_KEY, serde_json::to_vec(&day)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::SettingsStore;
+
+    #[test]
+    fn test_cycle_start_day() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let settings = SettingsStore::open(&db).unwrap();
+        assert_eq!(None, settings.cycle_start_day().unwrap());
+
+        settings.set_cycle_start_day(25).unwrap();
+        assert_eq!(Some(25), settings.cycle_start_day().unwrap());
+
+        let reopened = SettingsStore::open(&db).unwrap();
+        assert_eq!(Some(25), reopened.cycle_start_day().unwrap());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{format_amount, parse_days, TransactionSplit, INVALID_ARGS, REPORT_MAX_DAYS};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate};
+
+pub const SPEND_USAGE: &str = "!spend <Category> [Days]";
+
+const SPEND_DEFAULT_DAYS: i64 = 30;
+
+/// A request for how much was spent in one category
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendArgs {
+    pub category: String,
+    pub days: i64,
+}
+
+/// The spending in a category over a window, broken down by week
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendReport {
+    pub total: f64,
+    pub count: usize,
+    /// The total for each week of the window, by the week's first day
+    pub weeks: Vec<(NaiveDate, f64)>,
+}
+
+impl SpendArgs {
+    /// Parses the category, which may contain spaces, followed by an optional number of days
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let args = args.trim();
+
+        let (category, days) = match args.rsplit_once(' ') {
+            Some((category, days)) if days.chars().all(|c| c.is_ascii_digit()) => (
+                category.trim(),
+                parse_days(Some(days), SPEND_DEFAULT_DAYS, REPORT_MAX_DAYS)?,
+            ),
+            _ => (args, SPEND_DEFAULT_DAYS),
+        };
+
+        if category.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {SPEND_USAGE}"));
+        }
+
+        Ok(Self {
+            category: category.to_string(),
+            days,
+        })
+    }
+}
+
+/// Totals the expenses in `category` between `start` and `end`, inclusive, in weeks starting from
+/// `start`. The last week may be shorter than 7 days.
+pub fn category_spend(
+    expenses: &[TransactionSplit],
+    category: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> SpendReport {
+    let mut weeks = Vec::new();
+    let mut week_start = start;
+    while week_start <= end {
+        weeks.push((week_start, 0.0));
+        week_start += Duration::days(7);
+    }
+
+    let mut total = 0.0;
+    let mut count = 0;
+
+    for expense in expenses.iter().filter(|expense| {
+        expense
+            .category_name
+            .as_deref()
+            .is_some_and(|name| name.eq_ignore_ascii_case(category))
+    }) {
+        let date = expense.date.date_naive();
+        if date < start || date > end {
+            continue;
+        }
+
+        total += expense.amount;
+        count += 1;
+        let week = ((date - start).num_days() / 7) as usize;
+        weeks[week].1 += expense.amount;
+    }
+
+    SpendReport {
+        total,
+        count,
+        weeks,
+    }
+}
+
+pub fn format_spend(category: &str, days: i64, report: &SpendReport) -> String {
+    if report.count == 0 {
+        return format!("No {category} expenses in the last {days} days");
+    }
+
+    let mut output = format!(
+        "{category} in the last {days} days: {} across {} expenses",
+        format_amount(report.total, None),
+        report.count
+    );
+    output.push_str("\nBy week:");
+    for (week_start, total) in &report.weeks {
+        output.push_str(&format!(
+            "\n - {week_start}: {}",
+            format_amount(*total, None)
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spend::{category_spend, format_spend, SpendArgs};
+    use crate::{TransactionSplit, REPORT_MAX_DAYS};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_spend() {
+        assert_eq!(
+            SpendArgs {
+                category: "Groceries".to_string(),
+                days: 30
+            },
+            SpendArgs::parse("Groceries").unwrap()
+        );
+        assert_eq!(
+            SpendArgs {
+                category: "Dining Out".to_string(),
+                days: 14
+            },
+            SpendArgs::parse("Dining Out 14").unwrap()
+        );
+        assert_eq!(
+            REPORT_MAX_DAYS,
+            SpendArgs::parse("Groceries 100000000").unwrap().days
+        );
+        assert!(SpendArgs::parse("Groceries 0").is_err());
+        assert!(SpendArgs::parse("").is_err());
+        assert!(SpendArgs::parse("14").is_ok());
+    }
+
+    #[test]
+    fn test_category_spend() {
+        let expenses: Vec<TransactionSplit> = serde_json::from_str(
+            r#"[
+                {"type": "withdrawal", "date": "2024-01-01T12:00:00+00:00", "amount": "10.00",
+                 "description": "a", "category_name": "Groceries", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-07T12:00:00+00:00", "amount": "5.50",
+                 "description": "b", "category_name": "groceries", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-08T12:00:00+00:00", "amount": "20.00",
+                 "description": "c", "category_name": "Groceries", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-09T12:00:00+00:00", "amount": "99.00",
+                 "description": "d", "category_name": "Dining", "notes": null, "currency_symbol": "$"},
+                {"type": "withdrawal", "date": "2024-01-10T12:00:00+00:00", "amount": "1.00",
+                 "description": "e", "category_name": null, "notes": null, "currency_symbol": "$"}
+            ]"#,
+        )
+        .unwrap();
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+
+        let report = category_spend(&expenses, "Groceries", start, end);
+        assert_eq!(35.5, report.total);
+        assert_eq!(3, report.count);
+        assert_eq!(
+            vec![
+                (start, 15.5),
+                (NaiveDate::from_ymd_opt(2024, 1, 8).unwrap(), 20.0)
+            ],
+            report.weeks
+        );
+        assert_eq!(
+            "Groceries in the last 10 days: 35.50 across 3 expenses\nBy week:\n - 2024-01-01: 15.50\n - 2024-01-08: 20.00",
+            format_spend("Groceries", 10, &report)
+        );
+
+        let empty = category_spend(&expenses, "Travel", start, end);
+        assert_eq!(0, empty.count);
+        assert_eq!(
+            "No Travel expenses in the last 10 days",
+            format_spend("Travel", 10, &empty)
+        );
+    }
+}
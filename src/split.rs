@@ -0,0 +1,400 @@
+use crate::{format_amount, AddArgs, NoteMode, INVALID_ARGS};
+use anyhow::anyhow;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+pub const SPLIT_USAGE: &str =
+    "!split <Person,Person...> <Category>: <Amount> [@Date] [>Payee] [Note] [#Tag...]";
+
+/// How the bot reports the outcome of a split
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitFeedback {
+    /// React ✅ if every share was added, or ❌ otherwise
+    #[default]
+    Reaction,
+    /// Reply with the outcome of each person's share
+    Summary,
+}
+
+/// Who is given the cents that are left over when a split doesn't divide evenly
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitRemainder {
+    /// One cent each to the first participants
+    #[default]
+    Distribute,
+    /// All of them to the first participant
+    First,
+    /// All of them to the person who sent the split, or the first participant if they're not in it
+    Issuer,
+}
+
+/// An expense that is split evenly between multiple people
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitArgs {
+    pub participants: Vec<String>,
+    pub add: AddArgs,
+}
+
+/// The outcome of adding one person's share of a split
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareResult {
+    pub person: String,
+    pub amount: f64,
+    pub added: bool,
+}
+
+/// The outcome of adding every share of a split
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitOutcome {
+    pub results: Vec<ShareResult>,
+    /// The number of added shares that were deleted again because another share failed
+    pub rolled_back: usize,
+}
+
+impl SplitArgs {
+    pub fn parse(args: &str, note_mode: NoteMode, decimal_comma: bool) -> anyhow::Result<Self> {
+        let Some((participants, rest)) = split_participants(args.trim()) else {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {SPLIT_USAGE}"));
+        };
+
+        let participants: Vec<String> = participants
+            .split(',')
+            .map(|person| person.trim().to_string())
+            .filter(|person| !person.is_empty())
+            .collect();
+
+        if participants.is_empty() {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {SPLIT_USAGE}"));
+        }
+
+        for (i, person) in participants.iter().enumerate() {
+            if participants[..i].contains(person) {
+                return Err(anyhow!("{person} is listed more than once"));
+            }
+        }
+
+        Ok(Self {
+            participants,
+            add: AddArgs::parse(rest, note_mode, decimal_comma)?,
+        })
+    }
+}
+
+/// Splits the leading comma-separated list of people from the rest of the args. Whitespace around
+/// the commas is allowed.
+fn split_participants(args: &str) -> Option<(&str, &str)> {
+    let mut prev = None;
+
+    for (i, c) in args.char_indices() {
+        if c.is_whitespace() {
+            let next = args[i..].trim_start().chars().next();
+            if prev != Some(',') && next != Some(',') {
+                return Some((&args[..i], &args[i..]));
+            }
+        } else {
+            prev = Some(c);
+        }
+    }
+
+    None
+}
+
+/// Divides `amount` into a share for each participant to the cent. Any remaining cents are given
+/// out according to the `remainder` policy, so that the shares always sum to `amount`. `issuer` is
+/// the person who sent the split.
+pub fn split_shares(
+    amount: f64,
+    participants: &[String],
+    remainder: SplitRemainder,
+    issuer: &str,
+) -> Vec<f64> {
+    let count = participants.len();
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let total_cents = (amount * 100.0).round() as i64;
+    let base = total_cents / count as i64;
+    let left_over = total_cents % count as i64;
+
+    let recipient = match remainder {
+        SplitRemainder::Distribute => None,
+        SplitRemainder::First => Some(0),
+        SplitRemainder::Issuer => Some(
+            participants
+                .iter()
+                .position(|person| person.eq_ignore_ascii_case(issuer))
+                .unwrap_or(0),
+        ),
+    };
+
+    (0..count)
+        .map(|i| {
+            let cents = match recipient {
+                None if (i as i64) < left_over => base + 1,
+                Some(recipient) if i == recipient => base + left_over,
+                _ => base,
+            };
+            cents as f64 / 100.0
+        })
+        .collect()
+}
+
+/// Adds each person's share with `add`, which returns the id of the transaction the share was added
+/// as, if known. When `atomic` is set, the first failure stops the split, and the shares that were
+/// already added are deleted with `delete`, so that either every share is added or none are.
+pub async fn add_shares<Add, AddFuture, Delete, DeleteFuture>(
+    shares: Vec<(String, f64)>,
+    atomic: bool,
+    add: Add,
+    delete: Delete,
+) -> SplitOutcome
+where
+    Add: Fn(String, f64) -> AddFuture,
+    AddFuture: Future<Output = anyhow::Result<Option<String>>>,
+    Delete: Fn(String) -> DeleteFuture,
+    DeleteFuture: Future<Output = anyhow::Result<()>>,
+{
+    let mut results = Vec::with_capacity(shares.len());
+    // The index in results of each added share, and the id of its transaction
+    let mut added = Vec::new();
+    let mut failed = false;
+
+    for (person, amount) in shares {
+        if atomic && failed {
+            results.push(ShareResult {
+                person,
+                amount,
+                added: false,
+            });
+            continue;
+        }
+
+        match add(person.clone(), amount).await {
+            Ok(id) => {
+                added.push((results.len(), id));
+                results.push(ShareResult {
+                    person,
+                    amount,
+                    added: true,
+                });
+            }
+            Err(e) => {
+                error!("Failed to add {person}'s share: {e}");
+                failed = true;
+                results.push(ShareResult {
+                    person,
+                    amount,
+                    added: false,
+                });
+            }
+        }
+    }
+
+    let mut rolled_back = 0;
+    if atomic && failed {
+        for (index, id) in added.into_iter().rev() {
+            let person = &results[index].person;
+            let Some(id) = id else {
+                warn!("Cannot roll back {person}'s share because its transaction id is unknown");
+                continue;
+            };
+            match delete(id.clone()).await {
+                Ok(_) => {
+                    results[index].added = false;
+                    rolled_back += 1;
+                }
+                Err(e) => error!("Failed to roll back {person}'s share, transaction {id}: {e}"),
+            }
+        }
+    }
+
+    SplitOutcome {
+        results,
+        rolled_back,
+    }
+}
+
+/// Formats each person's share and whether it was added, eg: "alice: 20.00 ✅, bob: 20.00 ❌"
+pub fn format_split_summary(results: &[ShareResult]) -> String {
+    results
+        .iter()
+        .map(|result| {
+            format!(
+                "{}: {} {}",
+                result.person,
+                format_amount(result.amount, None),
+                if result.added { "✅" } else { "❌" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::split::{
+        add_shares, format_split_summary, split_shares, ShareResult, SplitArgs, SplitRemainder,
+    };
+    use crate::NoteMode;
+    use anyhow::anyhow;
+    use std::sync::Mutex;
+
+    fn parse(args: &str) -> anyhow::Result<SplitArgs> {
+        SplitArgs::parse(args, NoteMode::Standard, false)
+    }
+
+    #[test]
+    fn test_parse_split() {
+        let args = parse("alice, bob,carol Dinner: 60 pizza #food").unwrap();
+        assert_eq!(vec!["alice", "bob", "carol"], args.participants);
+        assert_eq!("Dinner", args.add.category);
+        assert_eq!(60.0, args.add.amount);
+        assert_eq!(Some("pizza".to_string()), args.add.note);
+
+        assert!(parse("Dinner: 60").is_err());
+        assert!(parse("alice,alice Dinner: 60").is_err());
+        assert!(parse("alice,bob").is_err());
+    }
+
+    fn people(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_shares() {
+        let three = people(&["alice", "bob", "carol"]);
+        let distribute = |amount, participants: &[String]| {
+            split_shares(amount, participants, SplitRemainder::Distribute, "alice")
+        };
+
+        assert_eq!(vec![20.0, 20.0, 20.0], distribute(60.0, &three));
+        assert_eq!(vec![3.34, 3.33, 3.33], distribute(10.0, &three));
+        assert_eq!(vec![0.01, 0.0], distribute(0.01, &people(&["a", "b"])));
+        assert!(distribute(10.0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_split_remainder_policies() {
+        let three = people(&["alice", "bob", "carol"]);
+        let four = people(&["alice", "bob", "carol", "dave"]);
+        let sum = |shares: &[f64]| (shares.iter().sum::<f64>() * 100.0).round() / 100.0;
+
+        let cases = [
+            (SplitRemainder::Distribute, "carol", vec![3.34, 3.33, 3.33]),
+            (SplitRemainder::First, "carol", vec![3.34, 3.33, 3.33]),
+            (SplitRemainder::Issuer, "Carol", vec![3.33, 3.33, 3.34]),
+            (SplitRemainder::Issuer, "zoe", vec![3.34, 3.33, 3.33]),
+        ];
+        for (policy, issuer, expected) in cases {
+            let shares = split_shares(10.0, &three, policy, issuer);
+            assert_eq!(expected, shares, "{policy:?} issued by {issuer}");
+            assert_eq!(10.0, sum(&shares));
+        }
+
+        let cases = [
+            (SplitRemainder::Distribute, vec![0.26, 0.26, 0.26, 0.25]),
+            (SplitRemainder::First, vec![0.28, 0.25, 0.25, 0.25]),
+            (SplitRemainder::Issuer, vec![0.25, 0.28, 0.25, 0.25]),
+        ];
+        for (policy, expected) in cases {
+            let shares = split_shares(1.03, &four, policy, "bob");
+            assert_eq!(expected, shares, "{policy:?}");
+            assert_eq!(1.03, sum(&shares));
+        }
+    }
+
+    #[test]
+    fn test_split_summary() {
+        let results = vec![
+            ShareResult {
+                person: "alice".to_string(),
+                amount: 20.0,
+                added: true,
+            },
+            ShareResult {
+                person: "bob".to_string(),
+                amount: 20.0,
+                added: true,
+            },
+            ShareResult {
+                person: "carol".to_string(),
+                amount: 20.0,
+                added: false,
+            },
+        ];
+
+        assert_eq!(
+            "alice: 20.00 ✅, bob: 20.00 ✅, carol: 20.00 ❌",
+            format_split_summary(&results)
+        );
+    }
+
+    fn shares() -> Vec<(String, f64)> {
+        vec![
+            ("alice".to_string(), 20.0),
+            ("bob".to_string(), 20.0),
+            ("carol".to_string(), 20.0),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_atomic_split_rolls_back() {
+        let deleted = Mutex::new(Vec::new());
+
+        let outcome = add_shares(
+            shares(),
+            true,
+            |person, _| async move {
+                match person.as_str() {
+                    "bob" => Err(anyhow!("Firefly is down")),
+                    _ => Ok(Some(format!("{person}-id"))),
+                }
+            },
+            |id| {
+                deleted.lock().unwrap().push(id);
+                async { Ok(()) }
+            },
+        )
+        .await;
+
+        assert_eq!(1, outcome.rolled_back);
+        assert!(outcome.results.iter().all(|result| !result.added));
+        assert_eq!(vec!["alice-id"], *deleted.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_non_atomic_split_keeps_added_shares() {
+        let deleted = Mutex::new(Vec::new());
+
+        let outcome = add_shares(
+            shares(),
+            false,
+            |person, _| async move {
+                match person.as_str() {
+                    "bob" => Err(anyhow!("Firefly is down")),
+                    _ => Ok(Some(format!("{person}-id"))),
+                }
+            },
+            |id| {
+                deleted.lock().unwrap().push(id);
+                async { Ok(()) }
+            },
+        )
+        .await;
+
+        assert_eq!(0, outcome.rolled_back);
+        assert_eq!(
+            vec![true, false, true],
+            outcome
+                .results
+                .iter()
+                .map(|result| result.added)
+                .collect::<Vec<_>>()
+        );
+        assert!(deleted.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::cycle::budget_cycle;
+use chrono::{DateTime, Duration, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+const SUBSCRIPTIONS_TREE: &str = "subscriptions";
+
+/// How often a subscriber receives their summary
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// A user's subscription to summaries of their spending
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub user_id: String,
+    pub frequency: Frequency,
+    /// When the last summary was sent, or when the user subscribed if none have been sent yet
+    pub last_sent: DateTime<Local>,
+}
+
+/// Persists subscriptions in the bot's store
+pub struct SubscriptionStore {
+    tree: sled::Tree,
+}
+
+impl Frequency {
+    pub fn parse(frequency: &str) -> Option<Self> {
+        match frequency.trim().to_lowercase().as_str() {
+            "daily" => Some(Frequency::Daily),
+            "weekly" => Some(Frequency::Weekly),
+            "monthly" => Some(Frequency::Monthly),
+            _ => None,
+        }
+    }
+
+    /// The number of days covered by each daily or weekly summary. Monthly summaries cover the
+    /// previous budget cycle instead.
+    pub fn days(&self) -> i64 {
+        match self {
+            Frequency::Daily => 1,
+            Frequency::Weekly => 7,
+            Frequency::Monthly => 30,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+        }
+    }
+}
+
+impl Subscription {
+    /// Daily and weekly summaries are due once their period has passed. Monthly summaries are due
+    /// once a new budget cycle, starting on `cycle_start_day`, has started.
+    pub fn is_due(&self, now: DateTime<Local>, cycle_start_day: u32) -> bool {
+        match self.frequency {
+            Frequency::Monthly => {
+                budget_cycle(now.date_naive(), cycle_start_day).0 > self.last_sent.date_naive()
+            }
+            _ => now - self.last_sent >= Duration::days(self.frequency.days()),
+        }
+    }
+}
+
+impl SubscriptionStore {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            tree: db.open_tree(SUBSCRIPTIONS_TREE)?,
+        })
+    }
+
+    /// Subscribes the user, replacing any existing subscription. The first summary is sent once
+    /// the frequency's period has passed, or the next budget cycle starts for monthly summaries.
+    pub fn subscribe(
+        &self,
+        user_id: &str,
+        frequency: Frequency,
+        now: DateTime<Local>,
+    ) -> anyhow::Result<()> {
+        self.save(&Subscription {
+            user_id: user_id.to_string(),
+            frequency,
+            last_sent: now,
+        })
+    }
+
+    /// Removes the user's subscription, returning true if they had one
+    pub fn unsubscribe(&self, user_id: &str) -> anyhow::Result<bool> {
+        let removed = self.tree.remove(user_id)?.is_some();
+        self.tree.flush()?;
+        Ok(removed)
+    }
+
+    pub fn mark_sent(&self, user_id: &str, sent_at: DateTime<Local>) -> anyhow::Result<()> {
+        if let Some(mut subscription) = self.get(user_id)? {
+            subscription.last_sent = sent_at;
+            self.save(&subscription)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, user_id: &str) -> anyhow::Result<Option<Subscription>> {
+        Ok(match self.tree.get(user_id)? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    /// Returns every subscription, skipping any that cannot be read
+    pub fn list(&self) -> Vec<Subscription> {
+        self.tree
+            .iter()
+            .values()
+            .filter_map(|value| {
+                value
+                    .map_err(anyhow::Error::from)
+                    .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+                    .map_err(|e| warn!("Skipping unreadable subscription: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    fn save(&self, subscription: &Subscription) -> anyhow::Result<()> {
+        self.tree.insert(
+            subscription.user_id.as_str(),
+            serde_json::to_vec(subscription)?,
+        )?;
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscriptions::{Frequency, Subscription, SubscriptionStore};
+    use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+
+    #[test]
+    fn test_subscription_store() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SubscriptionStore::open(&db).unwrap();
+        let now = Local::now();
+
+        assert!(store.list().is_empty());
+
+        store
+            .subscribe("@alice:example.org", Frequency::Weekly, now)
+            .unwrap();
+        store
+            .subscribe("@bob:example.org", Frequency::Daily, now)
+            .unwrap();
+        assert_eq!(2, store.list().len());
+
+        let alice = store.get("@alice:example.org").unwrap().unwrap();
+        assert_eq!(Frequency::Weekly, alice.frequency);
+        assert!(!alice.is_due(now + Duration::days(6), 1));
+        assert!(alice.is_due(now + Duration::days(7), 1));
+
+        let later = now + Duration::days(7);
+        store.mark_sent("@alice:example.org", later).unwrap();
+        let alice = store.get("@alice:example.org").unwrap().unwrap();
+        assert_eq!(later, alice.last_sent);
+        assert!(!alice.is_due(later, 1));
+
+        store
+            .subscribe("@alice:example.org", Frequency::Monthly, now)
+            .unwrap();
+        assert_eq!(
+            Frequency::Monthly,
+            store.get("@alice:example.org").unwrap().unwrap().frequency
+        );
+
+        assert!(store.unsubscribe("@alice:example.org").unwrap());
+        assert!(!store.unsubscribe("@alice:example.org").unwrap());
+        assert_eq!(None, store.get("@alice:example.org").unwrap());
+        assert_eq!(1, store.list().len());
+    }
+
+    #[test]
+    fn test_monthly_due_on_new_cycle() {
+        let subscription = Subscription {
+            user_id: "@alice:example.org".to_string(),
+            frequency: Frequency::Monthly,
+            last_sent: at(2024, 3, 10),
+        };
+
+        assert!(!subscription.is_due(at(2024, 3, 24), 25));
+        assert!(subscription.is_due(at(2024, 3, 25), 25));
+        assert!(!subscription.is_due(at(2024, 3, 31), 1));
+        assert!(subscription.is_due(at(2024, 4, 1), 1));
+    }
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Local> {
+        Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(year, month, day)
+                    .unwrap()
+                    .and_hms_opt(12, 0, 0)
+                    .unwrap(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(Some(Frequency::Daily), Frequency::parse("daily"));
+        assert_eq!(Some(Frequency::Weekly), Frequency::parse(" Weekly"));
+        assert_eq!(Some(Frequency::Monthly), Frequency::parse("MONTHLY"));
+        assert_eq!(None, Frequency::parse("yearly"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::cycle::budget_cycle;
+use crate::{format_amount, INVALID_ARGS};
+use anyhow::anyhow;
+use chrono::{Datelike, Months, NaiveDate};
+
+pub const SUMMARY_USAGE: &str = "!summary [month | YYYY-MM]";
+
+/// A request for the spending in each category over a period
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummaryArgs {
+    /// The current budget cycle
+    Cycle,
+    /// A calendar month, by its first day
+    Month(NaiveDate),
+}
+
+impl SummaryArgs {
+    /// Parses `month`, or nothing, for the current budget cycle, or a month written as `YYYY-MM`
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let args = args.trim();
+        if args.is_empty() || args.eq_ignore_ascii_case("month") {
+            return Ok(Self::Cycle);
+        }
+        if args.contains(' ') {
+            return Err(anyhow!("{INVALID_ARGS} Usage: {SUMMARY_USAGE}"));
+        }
+
+        match NaiveDate::parse_from_str(&format!("{args}-01"), "%Y-%m-%d") {
+            Ok(month) => Ok(Self::Month(month)),
+            Err(_) => Err(anyhow!("Invalid month: {args}. Use YYYY-MM")),
+        }
+    }
+
+    /// The first and last days of the period, given the day that budget cycles start on
+    pub fn period(&self, today: NaiveDate, start_day: u32) -> (NaiveDate, NaiveDate) {
+        match self {
+            Self::Cycle => budget_cycle(today, start_day),
+            Self::Month(month) => (
+                *month,
+                (*month + Months::new(1)).pred_opt().unwrap_or(*month),
+            ),
+        }
+    }
+}
+
+/// Names the period as it's written, eg: `2024-01` for a calendar month, or `2024-01-15 to
+/// 2024-02-14` for a budget cycle that doesn't start on the first
+pub fn period_label(start: NaiveDate, end: NaiveDate) -> String {
+    let whole_month = start.day() == 1 && end == (start + Months::new(1)).pred_opt().unwrap_or(end);
+    if whole_month {
+        start.format("%Y-%m").to_string()
+    } else {
+        format!("{start} to {end}")
+    }
+}
+
+/// Lists the spending in each category, as it's ordered, followed by the total
+pub fn format_summary(label: &str, totals: &[(String, f64)]) -> String {
+    if totals.is_empty() {
+        return format!("No spending in {label}");
+    }
+
+    let mut response = format!("Spending in {label}:");
+    for (category, total) in totals {
+        response.push_str(&format!("\n - {category}: {}", format_amount(*total, None)));
+    }
+    let total: f64 = totals.iter().map(|(_, total)| total).sum();
+    response.push_str(&format!("\nTotal: {}", format_amount(total, None)));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::summary::{format_summary, period_label, SummaryArgs};
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let today = date(2024, 2, 17);
+        assert_eq!(SummaryArgs::Cycle, SummaryArgs::parse("").unwrap());
+        assert_eq!(SummaryArgs::Cycle, SummaryArgs::parse(" Month ").unwrap());
+        assert_eq!(
+            (date(2024, 2, 1), date(2024, 2, 29)),
+            SummaryArgs::Cycle.period(today, 1)
+        );
+        assert_eq!(
+            (date(2024, 1, 25), date(2024, 2, 24)),
+            SummaryArgs::Cycle.period(today, 25)
+        );
+
+        let january = SummaryArgs::parse("2024-01").unwrap();
+        assert_eq!(SummaryArgs::Month(date(2024, 1, 1)), january);
+        assert_eq!(
+            (date(2024, 1, 1), date(2024, 1, 31)),
+            january.period(today, 25)
+        );
+        assert_eq!(
+            (date(2023, 12, 1), date(2023, 12, 31)),
+            SummaryArgs::parse("2023-12").unwrap().period(today, 1)
+        );
+
+        assert!(SummaryArgs::parse("2024-13").is_err());
+        assert!(SummaryArgs::parse("2024-01-05").is_err());
+        assert!(SummaryArgs::parse("January").is_err());
+        assert!(SummaryArgs::parse("2024-01 2024-02").is_err());
+    }
+
+    #[test]
+    fn test_period_label() {
+        assert_eq!("2024-02", period_label(date(2024, 2, 1), date(2024, 2, 29)));
+        assert_eq!(
+            "2024-01-25 to 2024-02-24",
+            period_label(date(2024, 1, 25), date(2024, 2, 24))
+        );
+    }
+
+    #[test]
+    fn test_format_summary() {
+        assert_eq!("No spending in 2024-01", format_summary("2024-01", &[]));
+        assert_eq!(
+            "Spending in 2024-01:\n - Groceries: 120.50\n - Household: 15.00\nTotal: 135.50",
+            format_summary(
+                "2024-01",
+                &[
+                    ("Groceries".to_string(), 120.5),
+                    ("Household".to_string(), 15.0)
+                ]
+            )
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::mirror::MirroredAccount;
+use crate::INVALID_ARGS;
+use anyhow::anyhow;
+use std::str::FromStr;
+
+pub const TRANSFER_USAGE: &str = "!transfer <FromAccount> -> <ToAccount>: <Amount> [Note]";
+
+/// Money moved from one asset account to another
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferArgs {
+    pub from: String,
+    pub to: String,
+    pub amount: f64,
+    pub note: Option<String>,
+}
+
+impl TransferArgs {
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow!("{INVALID_ARGS} Usage: {TRANSFER_USAGE}");
+
+        let (accounts, rest) = args.split_once(':').ok_or_else(invalid)?;
+        let (from, to) = accounts.split_once("->").ok_or_else(invalid)?;
+        let (from, to) = (from.trim(), to.trim());
+        if from.is_empty() || to.is_empty() {
+            return Err(invalid());
+        }
+
+        let rest = rest.trim();
+        let (amount, note) = rest.split_once(' ').unwrap_or((rest, ""));
+        let amount = amount.strip_prefix('$').unwrap_or(amount);
+        if amount.is_empty() {
+            return Err(invalid());
+        }
+        let amount = match f64::from_str(amount) {
+            Ok(amount) if amount.is_finite() && amount > 0.0 => amount,
+            _ => return Err(anyhow!("Invalid amount: {amount}")),
+        };
+
+        let note = note.trim();
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            note: (!note.is_empty()).then(|| note.to_string()),
+        })
+    }
+}
+
+/// Returns the id of the account named `name`, ignoring case
+pub fn resolve_account(name: &str, accounts: &[MirroredAccount]) -> Option<i64> {
+    accounts
+        .iter()
+        .find(|account| account.name.eq_ignore_ascii_case(name))
+        .map(|account| account.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirror::MirroredAccount;
+    use crate::transfer::{resolve_account, TransferArgs};
+
+    #[test]
+    fn test_parse_transfer() {
+        assert_eq!(
+            TransferArgs {
+                from: "Checking".to_string(),
+                to: "Rainy Day Savings".to_string(),
+                amount: 250.0,
+                note: Some("monthly top up".to_string()),
+            },
+            TransferArgs::parse(" Checking->  Rainy Day Savings : $250 monthly top up").unwrap()
+        );
+        assert_eq!(
+            None,
+            TransferArgs::parse("Checking -> Savings: 10").unwrap().note
+        );
+
+        assert!(TransferArgs::parse("Checking Savings: 10").is_err());
+        assert!(TransferArgs::parse("Checking -> Savings 10").is_err());
+        assert!(TransferArgs::parse(" -> Savings: 10").is_err());
+        assert!(TransferArgs::parse("Checking -> Savings:").is_err());
+        assert!(TransferArgs::parse("Checking -> Savings: -10").is_err());
+        assert!(TransferArgs::parse("Checking -> Savings: ten").is_err());
+    }
+
+    #[test]
+    fn test_resolve_account() {
+        let accounts = vec![
+            MirroredAccount {
+                id: 1,
+                name: "Checking".to_string(),
+            },
+            MirroredAccount {
+                id: 2,
+                name: "Savings".to_string(),
+            },
+        ];
+
+        assert_eq!(Some(2), resolve_account("savings", &accounts));
+        assert_eq!(None, resolve_account("Brokerage", &accounts));
+    }
+}